@@ -19,9 +19,20 @@ var __importStar = (this && this.__importStar) || function (mod) {
     return result;
 };
 Object.defineProperty(exports, "__esModule", { value: true });
+const crypto_1 = require("crypto");
 const ws_1 = __importStar(require("ws"));
 const PORT = process.env.PORT ? parseInt(process.env.PORT) : 8080;
+// Room messages kept for clients asking for older ones, oldest first.
+const HISTORY_LIMIT = 1000;
+const HISTORY_PAGE_SIZE = 50;
+// Everyone is in this room, including clients that don't know about rooms.
+const DEFAULT_ROOM = 'general';
 let users = [];
+let history = [];
+let directs = [];
+// The newest room message each user has read, by name and then room, so
+// another device of theirs opens where they stopped.
+let readPositions = {};
 console.log(`Listening on port ${PORT}`);
 const wss = new ws_1.WebSocketServer({ port: PORT });
 wss.on('connection', (ws) => {
@@ -32,36 +43,317 @@ wss.on('connection', (ws) => {
             const parsed_data = JSON.parse(raw_data);
             switch (parsed_data.messageType) {
                 case 'register':
-                    users.push({ ws, nick: parsed_data.data, isAlive: true });
-                    broadcast(JSON.stringify({ messageType: 'users', dataArray: users.map((u) => u.nick) }));
+                    // Clients tell users apart by name, so two open
+                    // connections can't share one, whatever its case.
+                    const nick = (parsed_data.data || '').trim();
+                    const taken = users.some(
+                        (u) => u.ws !== ws && u.ws.readyState === ws_1.default.OPEN && u.nick.toLowerCase() === nick.toLowerCase()
+                    );
+                    if (!nick || taken) {
+                        const reason = nick ? `The name "${nick}" is already taken.` : 'Please choose a name.';
+                        ws.send(JSON.stringify({ messageType: 'rejected', data: reason }));
+                        break;
+                    }
+                    users.push({
+                        ws,
+                        nick,
+                        isAlive: true,
+                        status: 'online',
+                        avatar: parsed_data.avatar || '',
+                        rooms: [DEFAULT_ROOM],
+                    });
+                    broadcastUsers();
+                    Object.entries(readPositions[nick] || {}).forEach(([room, position]) => sendReadPosition(ws, room, position));
                     break;
                 case 'message':
                     const sender = users.find((u) => u.ws === ws);
                     if (sender) {
-                        broadcast(JSON.stringify({
-                            messageType: 'message',
+                        // A resend of one we already have, after its ack got
+                        // lost: ack it again and echo it to the sender only.
+                        const resent = findResent(history, sender.nick, parsed_data.clientMsgId);
+                        if (resent) {
+                            acknowledge(ws, parsed_data, resent.id);
+                            ws.send(JSON.stringify({ messageType: 'message', data: JSON.stringify(resent) }));
+                            break;
+                        }
+                        const stored = {
+                            id: (0, crypto_1.randomUUID)(),
+                            from: sender.nick,
+                            room: parsed_data.room || DEFAULT_ROOM,
+                            message: parsed_data.data,
+                            time: Date.now(),
+                            replyTo: parsed_data.replyTo,
+                            quote: parsed_data.quote,
+                            clientMsgId: parsed_data.clientMsgId,
+                        };
+                        history.push(stored);
+                        if (history.length > HISTORY_LIMIT) {
+                            history.shift();
+                        }
+                        acknowledge(ws, parsed_data, stored.id);
+                        broadcastToRoom(stored.room, JSON.stringify({ messageType: 'message', data: JSON.stringify(stored) }));
+                    }
+                    break;
+                case 'typing':
+                case 'stoptyping':
+                    const typist = users.find((u) => u.ws === ws);
+                    if (typist) {
+                        const room = parsed_data.room || DEFAULT_ROOM;
+                        broadcastToRoom(room, JSON.stringify({ messageType: parsed_data.messageType, data: typist.nick, room }));
+                    }
+                    break;
+                case 'direct':
+                    const author = users.find((u) => u.ws === ws);
+                    if (author) {
+                        const resentDirect = findResent(directs, author.nick, parsed_data.clientMsgId);
+                        if (resentDirect) {
+                            acknowledge(ws, parsed_data, resentDirect.id);
+                            ws.send(resentDirect.frame);
+                            break;
+                        }
+                        const directId = (0, crypto_1.randomUUID)();
+                        const direct = JSON.stringify({
+                            messageType: 'direct',
                             data: JSON.stringify({
-                                from: sender.nick,
+                                id: directId,
+                                from: author.nick,
+                                to: parsed_data.to,
                                 message: parsed_data.data,
                                 time: Date.now(),
+                                replyTo: parsed_data.replyTo,
+                                quote: parsed_data.quote,
+                            }),
+                        });
+                        if (parsed_data.clientMsgId) {
+                            directs.push({ id: directId, from: author.nick, clientMsgId: parsed_data.clientMsgId, frame: direct });
+                            if (directs.length > HISTORY_LIMIT) {
+                                directs.shift();
+                            }
+                        }
+                        acknowledge(ws, parsed_data, directId);
+                        sendDirect(ws, parsed_data.to, direct);
+                    }
+                    break;
+                case 'edit':
+                    const editor = users.find((u) => u.ws === ws);
+                    if (editor) {
+                        const edit = JSON.stringify({
+                            messageType: 'edit',
+                            data: JSON.stringify({
+                                id: parsed_data.id,
+                                from: editor.nick,
+                                to: parsed_data.to,
+                                message: parsed_data.data,
                             }),
-                        }));
+                        });
+                        if (parsed_data.to) {
+                            sendDirect(ws, parsed_data.to, edit);
+                        } else {
+                            const stored = findStored(parsed_data.id, editor.nick);
+                            if (stored && !stored.deleted) {
+                                stored.message = parsed_data.data;
+                                stored.edited = true;
+                                broadcastToRoom(stored.room, edit);
+                            }
+                        }
+                    }
+                    break;
+                case 'delete':
+                    const remover = users.find((u) => u.ws === ws);
+                    if (remover) {
+                        const removal = JSON.stringify({
+                            messageType: 'delete',
+                            data: JSON.stringify({ id: parsed_data.id, from: remover.nick, to: parsed_data.to }),
+                        });
+                        if (parsed_data.to) {
+                            sendDirect(ws, parsed_data.to, removal);
+                        } else {
+                            const stored = findStored(parsed_data.id, remover.nick);
+                            if (stored) {
+                                stored.message = '';
+                                stored.deleted = true;
+                                broadcastToRoom(stored.room, removal);
+                            }
+                        }
+                    }
+                    break;
+                case 'file':
+                    const uploader = users.find((u) => u.ws === ws);
+                    if (uploader) {
+                        const uploadRoom = parsed_data.room || DEFAULT_ROOM;
+                        const upload = JSON.stringify({
+                            messageType: 'file',
+                            data: JSON.stringify({
+                                id: parsed_data.id,
+                                from: uploader.nick,
+                                to: parsed_data.to,
+                                room: parsed_data.to ? undefined : uploadRoom,
+                                message: '',
+                                file: JSON.parse(parsed_data.data),
+                                time: Date.now(),
+                            }),
+                        });
+                        if (parsed_data.to) {
+                            sendDirect(ws, parsed_data.to, upload);
+                        } else {
+                            broadcastToRoom(uploadRoom, upload);
+                        }
+                    }
+                    break;
+                case 'status':
+                    const member = users.find((u) => u.ws === ws);
+                    if (member && ['online', 'away', 'offline'].includes(parsed_data.data)) {
+                        member.status = parsed_data.data;
+                        broadcastUsers();
+                    }
+                    break;
+                case 'avatar':
+                    const profile = users.find((u) => u.ws === ws);
+                    if (profile) {
+                        profile.avatar = parsed_data.data || '';
+                        broadcastUsers();
+                    }
+                    break;
+                case 'read':
+                    const reader = users.find((u) => u.ws === ws);
+                    if (reader && parsed_data.id) {
+                        const receipt = JSON.stringify({
+                            messageType: 'read',
+                            data: JSON.stringify({ id: parsed_data.id, from: reader.nick, time: Date.now() }),
+                        });
+                        if (parsed_data.to) {
+                            sendDirect(ws, parsed_data.to, receipt);
+                        } else {
+                            const readMessage = history.find((m) => m.id === parsed_data.id);
+                            if (readMessage) {
+                                broadcastToRoom(readMessage.room, receipt);
+                            }
+                        }
                     }
+                    break;
+                case 'reaction':
+                    const reactor = users.find((u) => u.ws === ws);
+                    if (reactor && parsed_data.id) {
+                        const change = JSON.parse(parsed_data.data);
+                        const reaction = JSON.stringify({
+                            messageType: 'reaction',
+                            data: JSON.stringify({ id: parsed_data.id, from: reactor.nick, emoji: change.emoji, add: !!change.add }),
+                        });
+                        if (parsed_data.to) {
+                            sendDirect(ws, parsed_data.to, reaction);
+                        } else {
+                            const stored = history.find((m) => m.id === parsed_data.id);
+                            if (stored) {
+                                const reactions = (stored.reactions = stored.reactions || {});
+                                const names = (reactions[change.emoji] || []).filter((n) => n !== reactor.nick);
+                                if (change.add) {
+                                    names.push(reactor.nick);
+                                }
+                                if (names.length > 0) {
+                                    reactions[change.emoji] = names;
+                                } else {
+                                    delete reactions[change.emoji];
+                                }
+                                broadcastToRoom(stored.room, reaction);
+                            }
+                        }
+                    }
+                    break;
+                case 'readposition':
+                    // Reading on two devices keeps the later message. A
+                    // report that loses is answered with the one that won.
+                    const readUpTo = history.find((m) => m.id === parsed_data.id);
+                    const positioned = users.find((u) => u.ws === ws);
+                    if (positioned && readUpTo) {
+                        const positions = (readPositions[positioned.nick] = readPositions[positioned.nick] || {});
+                        const known = positions[readUpTo.room];
+                        if (!known || readUpTo.time > known.time) {
+                            positions[readUpTo.room] = { id: readUpTo.id, time: readUpTo.time };
+                        }
+                        else if (known.id !== readUpTo.id) {
+                            sendReadPosition(ws, readUpTo.room, known);
+                        }
+                    }
+                    break;
+                case 'history':
+                    // The page of the room's messages before `id`, or the
+                    // newest page without one. An unknown `id` gets an empty
+                    // page.
+                    const historyRoom = parsed_data.room || DEFAULT_ROOM;
+                    const roomHistory = history.filter((m) => m.room === historyRoom);
+                    const end = parsed_data.id ? roomHistory.findIndex((m) => m.id === parsed_data.id) : roomHistory.length;
+                    const start = Math.max(0, end - HISTORY_PAGE_SIZE);
+                    const page = end > 0 ? roomHistory.slice(start, end) : [];
+                    ws.send(JSON.stringify({
+                        messageType: 'history',
+                        room: historyRoom,
+                        data: JSON.stringify({ messages: page, more: start > 0 }),
+                    }));
+                    break;
+                case 'join':
+                    const joiner = users.find((u) => u.ws === ws);
+                    if (joiner && parsed_data.room && !joiner.rooms.includes(parsed_data.room)) {
+                        joiner.rooms.push(parsed_data.room);
+                    }
+                    break;
+                case 'leave':
+                    const leaver = users.find((u) => u.ws === ws);
+                    if (leaver && parsed_data.room !== DEFAULT_ROOM) {
+                        leaver.rooms = leaver.rooms.filter((r) => r !== parsed_data.room);
+                    }
+                    break;
+                case 'unregister':
+                    removeUser(ws);
+                    break;
+                case 'ping':
+                    ws.send(JSON.stringify({ messageType: 'pong' }));
             }
         }
         catch (e) {
             console.log('Error in message', e);
         }
     });
+    // Drop a user as soon as their socket closes instead of waiting for the
+    // next sweep.
+    ws.on('close', () => removeUser(ws));
 });
 const interval = setInterval(function ping() {
     const current_clients = Array.from(wss.clients);
     const updated_users = users.filter((u) => current_clients.includes(u.ws));
     if (updated_users.length !== users.length) {
         users = updated_users;
-        broadcast(JSON.stringify({ messageType: 'users', dataArray: users.map((u) => u.nick) }));
+        broadcastUsers();
     }
 }, 5000);
+// Each line typed into the server's console goes out to everyone as an
+// announcement, e.g. "Server restarting in 5 minutes".
+process.stdin.setEncoding('utf8');
+process.stdin.on('data', (chunk) => {
+    chunk
+        .split('\n')
+        .map((line) => line.trim())
+        .filter((line) => line.length > 0)
+        .forEach((line) => announce(line));
+});
+const announce = (text) => {
+    broadcast(JSON.stringify({ messageType: 'system', data: text }));
+};
+const removeUser = (ws) => {
+    const remaining = users.filter((u) => u.ws !== ws);
+    if (remaining.length !== users.length) {
+        users = remaining;
+        broadcastUsers();
+    }
+};
+// `dataArray` keeps the plain names for older clients; `data` adds each
+// user's status and avatar.
+const broadcastUsers = () => {
+    broadcast(JSON.stringify({
+        messageType: 'users',
+        dataArray: users.map((u) => u.nick),
+        data: JSON.stringify(users.map((u) => ({ name: u.nick, status: u.status, avatar: u.avatar }))),
+    }));
+};
 const broadcast = (data) => {
     wss.clients.forEach((client) => {
         if (client.readyState === ws_1.default.OPEN) {
@@ -69,3 +361,36 @@ const broadcast = (data) => {
         }
     });
 };
+// Tells the sender the id its message got, before the message itself goes
+// out, for clients that sent their own id along.
+const acknowledge = (ws, message, id) => {
+    if (message.clientMsgId) {
+        ws.send(JSON.stringify({ messageType: 'ack', data: JSON.stringify({ clientMsgId: message.clientMsgId, id }) }));
+    }
+};
+// Sends to the users in `room` only.
+const broadcastToRoom = (room, data) => {
+    users
+        .filter((u) => u.rooms.includes(room))
+        .forEach((u) => {
+            if (u.ws.readyState === ws_1.default.OPEN) {
+                u.ws.send(data);
+            }
+        });
+};
+const sendReadPosition = (ws, room, position) => {
+    ws.send(JSON.stringify({ messageType: 'readposition', room, data: JSON.stringify(position) }));
+};
+const findStored = (id, from) => id ? history.find((m) => m.id === id && m.from === from) : undefined;
+// What `from` already sent as `clientMsgId`, if anything.
+const findResent = (sent, from, clientMsgId) => clientMsgId ? sent.find((m) => m.from === from && m.clientMsgId === clientMsgId) : undefined;
+// Sends to the author of a direct message and its recipient only.
+const sendDirect = (ws, to, data) => {
+    users
+        .filter((u) => u.ws === ws || u.nick === to)
+        .forEach((u) => {
+            if (u.ws.readyState === ws_1.default.OPEN) {
+                u.ws.send(data);
+            }
+        });
+};
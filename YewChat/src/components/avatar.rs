@@ -0,0 +1,67 @@
+use yew::prelude::*;
+
+/// Background colours for initials placeholders, picked per username.
+const PLACEHOLDER_COLORS: &[&str] = &[
+    "bg-red-300",
+    "bg-orange-300",
+    "bg-amber-300",
+    "bg-green-300",
+    "bg-teal-300",
+    "bg-sky-300",
+    "bg-indigo-300",
+    "bg-pink-300",
+];
+
+#[derive(Properties, PartialEq)]
+pub struct AvatarProps {
+    pub name: String,
+    pub src: String,
+    /// Size and border classes, e.g. `"w-12 h-12 border-2 border-blue-200"`.
+    pub class: &'static str,
+    /// Defer fetching the image until it scrolls into view, showing the
+    /// user's initials until then.
+    #[prop_or_default]
+    pub lazy: bool,
+}
+
+#[function_component(Avatar)]
+pub fn avatar(props: &AvatarProps) -> Html {
+    if !props.lazy {
+        return html! {
+            <img class={format!("rounded-full {}", props.class)} src={props.src.clone()} alt={alt(&props.name)}/>
+        };
+    }
+
+    // The image is stacked on top of the initials, so they stay visible
+    // until it has loaded.
+    html! {
+        <div class={format!("relative rounded-full overflow-hidden flex items-center justify-center text-xs font-bold text-white {} {}", placeholder_color(&props.name), props.class)}>
+            <span aria-hidden="true">{initials(&props.name)}</span>
+            <img class="absolute inset-0 w-full h-full" loading="lazy" src={props.src.clone()} alt={alt(&props.name)}/>
+        </div>
+    }
+}
+
+fn alt(name: &str) -> String {
+    format!("{}'s avatar", name)
+}
+
+/// The first letter of up to two words of `name`, upper-cased.
+pub fn initials(name: &str) -> String {
+    let initials: String = name
+        .split_whitespace()
+        .filter_map(|word| word.chars().next())
+        .take(2)
+        .flat_map(char::to_uppercase)
+        .collect();
+    if initials.is_empty() {
+        "?".to_string()
+    } else {
+        initials
+    }
+}
+
+fn placeholder_color(name: &str) -> &'static str {
+    let hash = name.bytes().fold(0usize, |acc, b| acc.wrapping_mul(31).wrapping_add(b as usize));
+    PLACEHOLDER_COLORS[hash % PLACEHOLDER_COLORS.len()]
+}
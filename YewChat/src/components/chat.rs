@@ -1,22 +1,424 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use gloo::events::EventListener;
+use gloo::file::callbacks::FileReader;
+use gloo::file::File;
+use gloo::storage::{LocalStorage, Storage};
+use gloo::timers::callback::{Interval, Timeout};
 use serde::{Deserialize, Serialize};
-use web_sys::HtmlInputElement;
+use wasm_bindgen::JsCast;
+use web_sys::{DataTransfer, HtmlInputElement, KeyboardEvent};
 use yew::prelude::*;
 use yew_agent::{Bridge, Bridged};
-use yew_router::prelude::*;
-use crate::Route;
+use yew_router::prelude::{History, RouterScopeExt};
 
-use crate::services::event_bus::EventBus;
-use crate::{services::websocket::WebsocketService, User};
+use crate::components::avatar::Avatar;
+use crate::components::emoji::EmojiPicker;
+use crate::components::media::{self, Media};
+use crate::components::message_body::{contains_ignore_case, highlight, mentions, render_message_body, Highlights, MentionLinks};
+use crate::components::palette::{self, Command, CommandPalette};
+use crate::components::relative_time::{format_time, Clock, ExpiresIn, RelativeTime, CLOCK_TICK_MS};
+use crate::components::settings::Settings;
+use crate::components::shortcuts::{self, Action, ShortcutsHelp};
+use crate::components::toolbar::{self, FormattingToolbar};
+use crate::services::avatars::Avatars;
+use crate::services::bookmarks::{Bookmark, Bookmarks};
+use crate::services::clipboard;
+use crate::services::download::download;
+use crate::services::event_bus::{EventBus, Request};
+use crate::services::history;
+use crate::services::notes::{self, Note};
+use crate::services::notifications::{self, Notifier};
+use crate::services::outbox::{Outbox, Outgoing, OutgoingState};
+use crate::services::preferences::{ConnectionNotice, Preferences, LAZY_AVATAR_THRESHOLD};
+use crate::services::rate_limit::RateLimiter;
+use crate::services::reaction_batch::{ReactionBatch, REACTION_BATCH_MS};
+use crate::services::theme::Theme;
+use crate::services::username;
+use crate::services::transcript;
+use crate::services::visibility::VisibilityObserver;
+use crate::services::websocket::{self, ConnectionState, Heartbeat, WebsocketService};
+use crate::{Route, User, AVATAR_STORAGE_KEY};
 
+#[allow(clippy::enum_variant_names)]
 pub enum Msg {
     HandleMsg(String),
+    ConnectionChanged(ConnectionState),
     SubmitMessage,
+    InputChanged,
+    TypingTick,
+    /// The user paused typing long enough to tell the others.
+    TypingPaused,
+    ToggleSettings,
+    /// Opens or closes the list of bookmarks.
+    ToggleBookmarks,
+    /// Bookmarks the message `id`, or removes its bookmark.
+    ToggleBookmark(String),
+    /// Removes the bookmark of the message `id`, which may be gone.
+    RemoveBookmark(String),
+    /// Opens the conversation of the bookmarked message `id` and scrolls
+    /// to it.
+    OpenBookmark(String),
+    UpdatePreferences(Preferences),
+    /// Stops or restarts notifications about the named user's messages.
+    ToggleNotifMute(String),
+    PlayGif(String),
+    OpenLightbox(String),
+    CloseLightbox,
+    StartEdit(String),
+    SubmitEdit,
+    DeleteMessage(String),
+    /// Toggles our reaction with the emoji on the message `id`.
+    React(String, &'static str),
+    /// Toggles the `quick_reaction` preference on the message `id`, after a
+    /// double click.
+    QuickReact(String),
+    /// Sends the reaction toggles collected in `reaction_batch`.
+    FlushReactions,
+    /// Opens the reaction picker for the message `id`, or closes it.
+    ToggleReactionPicker(Option<String>),
+    /// Copies the text of the message at this index in `messages`.
+    CopyMessage(String),
+    Copied(String, bool),
+    CopyStatusDone,
+    /// Quotes the message `id` in the next message sent.
+    Reply(String),
+    /// Shows the quote in the reply with this `key` in full, or one line
+    /// of it again.
+    ToggleQuote(String),
+    /// Opens a direct conversation with the sender of the room message
+    /// `id`, replying to it there. The message stays in the room.
+    ReplyPrivately(String),
+    CancelReply,
+    /// Scrolls to the message `id`, e.g. from a reply quoting it.
+    ScrollToMessage(String),
+    /// Sends a failed message from the outbox again, reconnecting first if
+    /// needed.
+    RetryOutgoing(String),
+    DiscardOutgoing(String),
+    /// The server didn't acknowledge the outbox message in time.
+    OutgoingTimedOut(String),
+    PickFile(File),
+    /// Whether something is being dragged over the chat pane.
+    SetDragging(bool),
+    /// Something other than a file was dropped on the chat pane.
+    DroppedNonFile,
+    FileLoaded(String, Result<String, String>),
+    DismissUploadError,
+    GoAway,
+    ToggleAvatarEditor,
+    PaneScrolled,
+    MessagesSeen(Vec<String>),
+    LoadEarlier,
+    ToggleSearch,
+    SearchChanged(String),
+    ToggleExportMenu,
+    /// Downloads the open conversation in this format.
+    ExportTranscript(transcript::Format),
+    JumpToLatest,
+    /// Scrolls to the "New messages" divider.
+    JumpToUnread,
+    SetAvatar(String),
+    ToggleEmojiPicker,
+    InsertEmoji(&'static str),
+    Shortcut(Action),
+    RunCommand(Command),
+    LargePaste(String),
+    SendSnippet,
+    PasteInline,
+    CancelPaste,
+    CooldownTick,
+    OpenConversation(Conversation),
+    /// Switches to the named room through its route.
+    OpenRoom(String),
+    /// Adds the room typed by the user to the room list and opens it.
+    JoinRoom(String),
+    LeaveRoom(String),
+    FocusChanged(bool),
+    RosterTransitionsDone,
+    /// The roster stopped changing after a reconnect.
+    RosterSettled,
+    ToggleTheme,
+    Logout,
+    /// The tab is being closed or reloaded.
+    Unloading,
+    RateLimitWarningDone,
+    FlushNotifications,
 }
 
-#[derive(Deserialize)]
+/// The room everyone starts in. Messages from servers without rooms
+/// belong to it.
+const DEFAULT_ROOM: &str = "general";
+/// The rooms listed before the user joins any.
+const DEFAULT_ROOMS: [&str; 2] = [DEFAULT_ROOM, "random"];
+const ROOMS_STORAGE_KEY: &str = "yewchat.rooms.v1";
+const MAX_ROOM_NAME_CHARS: usize = 32;
+
+#[derive(Properties, PartialEq)]
+pub struct ChatProps {
+    /// The room to open, from the route; `None` for the default room.
+    #[prop_or_default]
+    pub room: Option<String>,
+    /// Gives users without a custom avatar theirs.
+    #[prop_or_default]
+    pub avatars: Avatars,
+}
+
+/// What the message pane is showing.
+#[derive(Clone, PartialEq)]
+pub enum Conversation {
+    /// The room in `Chat::room`.
+    Room,
+    /// The local-only "Saved Messages" notes.
+    Notes,
+    /// Private messages with the named user.
+    Direct(String),
+}
+
+/// Pastes longer than this many characters ask whether to send the text as
+/// a collapsible snippet instead of inlining it in the input.
+const LARGE_PASTE_THRESHOLD: usize = 2000;
+
+const GREETING_STORAGE_KEY: &str = "yewchat.greeting.last_sent";
+
+/// The join greeting isn't repeated if one was sent this recently, so
+/// quick reloads or rejoins don't spam the room.
+const GREETING_COOLDOWN_MS: f64 = 10.0 * 60.0 * 1000.0;
+
+/// How long roster entries animate in or out. Matches the CSS animations in
+/// `static/index.html`.
+const ROSTER_TRANSITION_MS: u32 = 300;
+
+/// After reconnecting, how long the roster may go unchanged before we stop
+/// waiting for the rest of the people from before the drop.
+const ROSTER_SETTLE_MS: u32 = 10_000;
+
+/// After the window has been unfocused this long, the roster may have gone
+/// stale, so a fresh one is requested on return.
+const STALE_ROSTER_AFTER_MS: f64 = 5.0 * 60.0 * 1000.0;
+
+/// Largest file that can be uploaded. Files travel base64-encoded over the
+/// websocket, so they grow by a third on the way.
+const MAX_UPLOAD_BYTES: u64 = 2 * 1024 * 1024;
+
+/// After the window has been unfocused this long we show as away.
+const AWAY_AFTER_MS: u32 = 60 * 1000;
+
+/// How many room messages are shown at first, and how many more each
+/// "Load earlier messages" reveals.
+const HISTORY_PAGE_SIZE: usize = 50;
+
+/// The message pane follows new messages while scrolled within this many
+/// pixels of the bottom.
+const NEAR_BOTTOM_PX: i32 = 80;
+
+/// Rows scrolled to, such as a quoted message, end up this far below the
+/// top of the pane.
+const SCROLL_TARGET_MARGIN_PX: i32 = 48;
+
+/// Quotes of replied-to messages are cut off after this many characters.
+const QUOTE_PREVIEW_CHARS: usize = 100;
+
+/// Conversations longer than this only render the bubbles near the view.
+const VIRTUALIZE_AFTER: usize = 100;
+/// The height assumed for a bubble that hasn't been measured yet.
+const ESTIMATED_ROW_PX: i32 = 96;
+/// How far beyond the visible part of the pane bubbles are still rendered.
+const OVERSCAN_PX: i32 = 600;
+
+/// Messages arriving within this long of the first unnotified one share a
+/// single notification.
+const NOTIFY_COALESCE_MS: u32 = 2000;
+
+/// At most this many messages may be sent within `RATE_LIMIT_WINDOW_MS`.
+const RATE_LIMIT_MESSAGES: usize = 5;
+const RATE_LIMIT_WINDOW_MS: f64 = 3000.0;
+/// How long the warning stays up after a message was held back.
+const RATE_LIMIT_WARNING_MS: u32 = 2000;
+
+/// The longest message that can be sent from the input, set at build time
+/// through `YEWCHAT_MAX_MESSAGE_LENGTH`.
+const CONFIGURED_MAX_MESSAGE_LENGTH: Option<&str> = option_env!("YEWCHAT_MAX_MESSAGE_LENGTH");
+const DEFAULT_MAX_MESSAGE_LENGTH: usize = 500;
+/// The counter turns red from this fraction of the limit on.
+const LENGTH_WARNING_RATIO: f64 = 0.9;
+
+/// A message the server hasn't acknowledged this long after it was sent
+/// is marked failed.
+const SEND_TIMEOUT_MS: u32 = 10_000;
+
+/// How long "Copied!" stays next to a message.
+const COPY_STATUS_MS: u32 = 1500;
+
+/// The page title when there is nothing unread.
+const DOCUMENT_TITLE: &str = "Yewchat!";
+
+/// Typing notifications are sent at most this often while the user types.
+const TYPING_SEND_INTERVAL_MS: f64 = 2000.0;
+/// A `StopTyping` goes out after this long without a keystroke.
+const TYPING_PAUSE_MS: u32 = 3000;
+
+/// Someone counts as typing for this long after their last notification.
+const TYPING_TIMEOUT_MS: f64 = 3000.0;
+
+#[derive(Default, Deserialize, Serialize)]
 struct MessageData {
     from: String,
+    /// Chosen by the sender so later edits can refer to the message.
+    /// Messages from older clients have none and can't be edited.
+    #[serde(default)]
+    id: Option<String>,
+    /// The recipient of a direct message; `None` for room messages.
+    #[serde(default)]
+    to: Option<String>,
+    /// The room of a room message; see `room_of`.
+    #[serde(default)]
+    room: Option<String>,
     message: String,
+    #[serde(default)]
+    edited: bool,
+    /// When the server relayed the message, in epoch millis. Older servers
+    /// don't send it, so it is filled in on receipt.
+    #[serde(default)]
+    time: Option<f64>,
+    /// Deleted messages keep their place, shown as a placeholder.
+    #[serde(default)]
+    deleted: bool,
+    /// An uploaded file sent instead of text.
+    #[serde(default)]
+    file: Option<Attachment>,
+    /// A notice added by this client, such as someone joining, with its
+    /// text in `message`. Never read from frames, so nobody can send one.
+    #[serde(skip)]
+    system: bool,
+    /// A `System` notice pushed by the server, such as a restart warning.
+    /// Also `system`.
+    #[serde(skip)]
+    announcement: bool,
+    /// Who has reported seeing this message or a later one.
+    #[serde(skip)]
+    read_by: HashSet<String>,
+    /// Who reacted with each emoji.
+    #[serde(default)]
+    reactions: BTreeMap<String, Vec<String>>,
+    /// The id of the message this one replies to.
+    #[serde(default, rename = "replyTo")]
+    reply_to: Option<String>,
+    /// The part of that message quoted in the reply; all of it if `None`.
+    #[serde(default)]
+    quote: Option<String>,
+    /// Identifies the message's bubble across renders, for keyed rendering,
+    /// row heights and the like: the id it first had, or a made-up one.
+    /// Kept when an ack swaps our id for the server's.
+    #[serde(skip)]
+    key: String,
+}
+
+impl MessageData {
+    /// Gives a message from a frame or storage its `key`.
+    fn keyed(mut self) -> Self {
+        self.key = self.id.clone().unwrap_or_else(new_message_id);
+        self
+    }
+}
+
+/// The message the next one sent replies to, and the part of it quoted.
+struct ReplyTarget {
+    id: String,
+    /// See `MessageData::quote`.
+    quote: Option<String>,
+}
+
+/// Follows the roster filling up again after we reconnect, e.g. when a
+/// server restart dropped everyone at once: until it has as many people
+/// as before the drop, or stops changing, it shows how many are back.
+#[derive(Debug, Default)]
+struct RosterRefill {
+    /// The roster size when the connection dropped.
+    expected: Option<usize>,
+    /// The roster size since reconnecting, while short of `expected`.
+    back: Option<usize>,
+}
+
+impl RosterRefill {
+    /// The connection dropped with `count` people, us included, in the
+    /// roster. Later drops before the roster refilled keep the first count.
+    fn dropped(&mut self, count: usize) {
+        if self.expected.is_none() && count > 1 {
+            self.expected = Some(count);
+        }
+        self.back = None;
+    }
+
+    /// A roster of `count` people came after reconnecting. Returns whether
+    /// what `shown` returns changed.
+    fn roster(&mut self, count: usize) -> bool {
+        let Some(expected) = self.expected else {
+            return false;
+        };
+        let back = (count < expected).then_some(count);
+        if back.is_none() {
+            self.expected = None;
+        }
+        std::mem::replace(&mut self.back, back) != back
+    }
+
+    /// Stops waiting for the rest. Returns whether anything was shown.
+    fn settle(&mut self) -> bool {
+        self.expected = None;
+        self.back.take().is_some()
+    }
+
+    /// How many are back of how many there were, while waiting.
+    fn shown(&self) -> Option<(usize, usize)> {
+        Some((self.back?, self.expected?))
+    }
+}
+
+/// Where the "New messages" divider is relative to the visible part of
+/// the message pane.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DividerPosition {
+    Above,
+    InView,
+    Below,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+struct Attachment {
+    /// The original file name.
+    name: String,
+    /// The contents as a `data:` URL.
+    url: String,
+}
+
+impl Attachment {
+    fn is_image(&self) -> bool {
+        self.url.starts_with("data:image/")
+    }
+
+    /// Where the file can be downloaded from. Uploads are always `data:`
+    /// URLs; anything else, such as a `javascript:` URL sent by a peer, is
+    /// never linked.
+    fn href(&self) -> Option<&str> {
+        let is_data_url = self.url.get(..5).is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"));
+        is_data_url.then_some(self.url.as_str())
+    }
+}
+
+/// Whether `m` carries a file that can't safely be shown; see
+/// `Attachment::href`.
+fn has_unsafe_attachment(m: &MessageData) -> bool {
+    m.file.as_ref().is_some_and(|file| file.href().is_none())
+}
+
+/// The payload of `Edit` and `Delete` frames.
+#[derive(Deserialize)]
+struct MessageChange {
+    id: String,
+    from: String,
+    /// The new text, for edits.
+    message: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -25,6 +427,58 @@ pub enum MsgTypes {
     Users,
     Register,
     Message,
+    Capabilities,
+    /// `data` is the name of a user who is typing.
+    Typing,
+    /// `data` is the name of a user who stopped typing without sending.
+    StopTyping,
+    /// A private message. Sent with the recipient in `to`; received like
+    /// `Message`, with `to` set in the payload.
+    Direct,
+    /// New text for the message `id`, sent by its author.
+    Edit,
+    /// Removes the message `id`, sent by its author.
+    Delete,
+    /// An uploaded file; `data` is an `Attachment` as JSON. Received like
+    /// `Message`, with `file` set in the payload.
+    File,
+    /// Sets our presence; `data` is a lowercase `Presence`.
+    Status,
+    /// Sets our avatar URL; empty `data` restores the default.
+    Avatar,
+    /// Asks for the room messages before the message `id`, or the newest
+    /// ones without it. Answered with a `HistoryPage` in `data`.
+    History,
+    /// Leaves the chat, so the server drops us from the user list right
+    /// away.
+    Unregister,
+    /// Adds or removes our reaction to the message `id`, with the peer in
+    /// `to` for direct messages. `data` is a `ReactionChange`; received
+    /// with a `Reaction` in `data`.
+    Reaction,
+    /// Reports the newest message `id` this user has seen in a
+    /// conversation, with the peer in `to` for direct messages. Received
+    /// with a `ReadReceipt` in `data`.
+    Read,
+    /// An announcement from the server to everyone, with its text in
+    /// `data`. Only ever received.
+    System,
+    /// The server refused our `Register`, e.g. because the name is taken,
+    /// with the reason in `data`. Only ever received.
+    Rejected,
+    /// Starts receiving the messages of the room named in `room`. Sent for
+    /// every listed room after each (re)connect.
+    Join,
+    /// Stops receiving the messages of the room named in `room`.
+    Leave,
+    /// Confirms a message we sent, before its echo; `data` is an `Ack`.
+    /// Only ever received.
+    Ack,
+    /// Reports the newest message `id` this user has read in the room
+    /// `room`, for their other devices. Received with a `ReadPosition` in
+    /// `data`, after `Register` for each room the server has one for and
+    /// whenever a report loses to a later one.
+    ReadPosition,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -33,208 +487,4286 @@ struct WebSocketMessage {
     message_type: MsgTypes,
     data_array: Option<Vec<String>>,
     data: Option<String>,
+    to: Option<String>,
+    id: Option<String>,
+    /// A custom avatar URL, announced with `Register`.
+    avatar: Option<String>,
+    /// The room a room message, typing notice or history request is for.
+    room: Option<String>,
+    /// Our own id for a message, echoed in the server's `Ack`.
+    client_msg_id: Option<String>,
+    /// The message a room or direct message replies to.
+    reply_to: Option<String>,
+    /// The part of `reply_to` quoted; see `MessageData::quote`.
+    quote: Option<String>,
+}
+
+impl WebSocketMessage {
+    fn new(message_type: MsgTypes, data: Option<String>) -> Self {
+        Self {
+            message_type,
+            data_array: None,
+            data,
+            to: None,
+            id: None,
+            avatar: None,
+            room: None,
+            client_msg_id: None,
+            reply_to: None,
+            quote: None,
+        }
+    }
+}
+
+/// Room policies announced by the server in a `Capabilities` frame, whose
+/// `data` is this struct as JSON, for the frame's `room` or the default
+/// room. Every field is optional so servers only send what they enforce.
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RoomCapabilities {
+    /// Messages are deleted by the server after this many seconds.
+    retention_secs: Option<u64>,
+    /// Each user may send at most one message per this many seconds.
+    slow_mode_secs: Option<u64>,
+}
+
+/// Messages in rooms with a retention period count down to their deletion
+/// from this long before, or the last quarter of shorter periods.
+const EXPIRY_WARNING_MS: f64 = 60.0 * 60.0 * 1000.0;
+
+/// The policies of rooms the server announced none for.
+const NO_CAPABILITIES: RoomCapabilities = RoomCapabilities {
+    retention_secs: None,
+    slow_mode_secs: None,
+};
+
+/// The emoji messages can be reacted with, in the order they are shown.
+pub const REACTIONS: [&str; 6] = ["👍", "❤️", "😂", "😮", "😢", "🎉"];
+
+#[derive(Serialize)]
+struct ReactionChange<'a> {
+    emoji: &'a str,
+    add: bool,
+}
+
+#[derive(Deserialize)]
+struct Reaction {
+    id: String,
+    from: String,
+    emoji: String,
+    add: bool,
+}
+
+/// The payload of `Ack` frames.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Ack {
+    client_msg_id: String,
+    /// The id the server gave the message.
+    id: String,
+}
+
+/// The newest room message this user has read on any device. The later
+/// message wins, so reading on two devices keeps the furthest position.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct ReadPosition {
+    id: String,
+    /// When the message was sent, in epoch millis.
+    time: f64,
+}
+
+#[derive(Deserialize)]
+struct ReadReceipt {
+    id: String,
+    /// Who saw the message.
+    from: String,
+    /// When, in epoch millis. Older servers don't send it, so it is filled
+    /// in on receipt.
+    #[serde(default)]
+    time: Option<f64>,
+}
+
+/// Older room messages, oldest first.
+#[derive(Deserialize)]
+struct HistoryPage {
+    messages: Vec<MessageData>,
+    /// Whether the server has messages before these.
+    more: bool,
 }
 
 #[derive(Clone)]
 struct UserProfile {
     name: String,
     avatar: String,
+    status: Presence,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Presence {
+    #[default]
+    Online,
+    Away,
+    Offline,
+}
+
+impl Presence {
+    fn dot_class(self) -> &'static str {
+        match self {
+            Presence::Online => "bg-green-500",
+            Presence::Away => "bg-amber-400",
+            Presence::Offline => "bg-gray-400",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Presence::Online => "Online",
+            Presence::Away => "Away",
+            Presence::Offline => "Offline",
+        }
+    }
+}
+
+/// An entry of the structured roster some servers send in a `Users`
+/// frame's `data`, alongside the plain names in `data_array`.
+#[derive(Deserialize)]
+struct RosterEntry {
+    name: String,
+    #[serde(default)]
+    status: Presence,
+    /// A custom avatar URL; empty for the default.
+    #[serde(default)]
+    avatar: String,
 }
 
 pub struct Chat {
     users: Vec<UserProfile>,
+    /// Whether the first `Users` frame has arrived.
+    roster_loaded: bool,
+    /// Users who just joined, rendered with the enter animation.
+    entering: HashSet<String>,
+    /// Users who just left, kept in the sidebar for the leave animation
+    /// together with their previous position.
+    leaving: Vec<(usize, UserProfile)>,
+    /// Clears `entering` and `leaving` once the animations have run.
+    roster_transition: Option<Timeout>,
+    roster_refill: RosterRefill,
+    /// Ends `roster_refill` once the roster stops changing.
+    roster_settle: Option<Timeout>,
     chat_input: NodeRef,
     _producer: Box<dyn Bridge<EventBus>>,
-    wss: WebsocketService,
+    /// `None` once the server rejected our name, so nothing reconnects
+    /// and registers it again.
+    wss: Option<WebsocketService>,
+    connection: ConnectionState,
     messages: Vec<MessageData>,
+    conversation: Conversation,
+    /// The room shown for `Conversation::Room`.
+    room: String,
+    /// The rooms in the sidebar, in the order they were joined.
+    rooms: Vec<String>,
+    /// Messages that arrived in rooms other than the open one.
+    room_unread: HashMap<String, usize>,
+    /// For each peer, the `key` of our latest direct message they have
+    /// seen, and when they saw it.
+    dm_seen: HashMap<String, (String, f64)>,
+    notes: Vec<Note>,
+    /// By room; see `room_capabilities`.
+    capabilities: HashMap<String, RoomCapabilities>,
+    preferences: Preferences,
+    theme: Theme,
+    show_settings: bool,
+    bookmarks: Bookmarks,
+    show_bookmarks: bool,
+    /// The message to scroll to once the room it is in has opened.
+    jump_after_switch: Option<String>,
+    lightbox: Option<String>,
+    /// GIFs the user clicked to play while autoplay is off, keyed as in
+    /// `view_message_body`.
+    played_gifs: HashSet<String>,
+    show_shortcuts: bool,
+    show_palette: bool,
+    show_emoji_picker: bool,
+    show_search: bool,
+    /// Only messages matching this are shown, with the matches marked.
+    search: String,
+    search_input: NodeRef,
+    /// Focus `search_input` after the next render.
+    focus_search: bool,
+    show_export_menu: bool,
+    /// Keeps the read of a file being uploaded alive until it finishes.
+    upload: Option<FileReader>,
+    upload_error: Option<String>,
+    /// Shows the "Drop to send" overlay while a drag hovers the chat pane.
+    dragging: bool,
+    /// The id of the own message being edited inline.
+    editing: Option<String>,
+    edit_input: NodeRef,
+    /// The message whose reaction picker is open.
+    reacting_to: Option<String>,
+    /// Our reaction toggles, already shown in the tallies, until
+    /// `reaction_flush` sends them.
+    reaction_batch: ReactionBatch,
+    reaction_flush: Option<Timeout>,
+    /// The message the next one sent replies to.
+    replying_to: Option<ReplyTarget>,
+    /// Replies whose quote is shown in full, by `key`.
+    expanded_quotes: HashSet<String>,
+    /// Opens a direct conversation from a mention of someone in `users`.
+    mention_links: MentionLinks,
+    /// Scroll to the row of the message with this `key` after the next
+    /// render.
+    scroll_to: Option<String>,
+    /// Our text messages not echoed by the server yet, oldest first.
+    outbox: Outbox,
+    /// The `key` of the message last copied, and whether that worked.
+    copy_status: Option<(String, bool)>,
+    copy_status_timer: Option<Timeout>,
+    /// Focus `edit_input` after the next render.
+    focus_edit: bool,
+    /// A large paste waiting for the user to pick how to send it.
+    pending_paste: Option<String>,
+    /// When this client last sent a message to each room, in epoch millis.
+    last_sent_at: HashMap<String, f64>,
+    /// Keeps rapid sends from flooding the room and direct messages.
+    rate_limiter: RateLimiter,
+    /// Hides the "slow down" warning; set while it is shown.
+    rate_limit_warning: Option<Timeout>,
+    /// How many characters are in the message input.
+    draft_len: usize,
+    /// The most characters a message sent from the input may have.
+    max_length: usize,
+    /// Set when sending was refused because the draft is too long.
+    too_long_warning: bool,
+    /// Re-renders the send button once a second while slow mode holds it.
+    cooldown_ticker: Option<Interval>,
+    /// Other users who are typing, with when we last heard from each.
+    typing: Vec<(String, f64)>,
+    /// When this client last sent a typing notification, while the user
+    /// counts as typing.
+    typing_sent_at: Option<f64>,
+    /// Sends `StopTyping` once the user pauses; restarted by each keystroke.
+    typing_pause_timer: Option<Timeout>,
+    /// Drops stale entries from `typing` while it isn't empty.
+    typing_ticker: Option<Interval>,
+    /// When the window lost focus, if it currently doesn't have it.
+    blurred_at: Option<f64>,
+    /// Messages from others that arrived while the window was unfocused.
+    unread: usize,
+    /// The index in `messages` of the first of those, where the "New
+    /// messages" divider goes. Kept after the user is back until they
+    /// have scrolled past it.
+    unread_divider: Option<usize>,
+    /// Whether the divider has been in view since the user came back.
+    unread_divider_seen: bool,
+    unread_divider_ref: NodeRef,
+    /// Whether one of the `unread` messages mentions this user.
+    mentioned: bool,
+    notifier: Notifier,
+    /// Senders and texts of messages waiting for `notify_timer`.
+    pending_notifications: Vec<(String, String)>,
+    notify_timer: Option<Timeout>,
+    /// Marks us away once the window has been unfocused for a while.
+    away_timer: Option<Timeout>,
+    /// Whether we last told the server we are away.
+    away: bool,
+    show_avatar_editor: bool,
+    avatar_input: NodeRef,
+    /// The scrolling container of the message pane.
+    message_pane: NodeRef,
+    /// Scroll the message pane to the bottom after the next render.
+    scroll_to_bottom: bool,
+    /// Whether the pane is scrolled away from the newest message.
+    scrolled_up: bool,
+    /// Messages that arrived in the open conversation while scrolled up.
+    new_below: usize,
+    /// Keep the pane this far from its bottom after the next render, so
+    /// prepending older messages doesn't move the view.
+    keep_scroll: Option<i32>,
+    /// The list of bubbles inside the message pane.
+    message_list: NodeRef,
+    /// Measured bubble heights, keyed by the messages' `key`.
+    row_heights: HashMap<String, i32>,
+    /// The pane's scroll position and height as of the last scroll.
+    pane_scroll_top: i32,
+    pane_height: i32,
+    /// Where the bubble list starts within the pane.
+    list_offset: i32,
+    /// How many of the newest room messages are shown.
+    room_shown: usize,
+    /// Reports message bubbles scrolled into view, for read receipts.
+    read_observer: Option<VisibilityObserver>,
+    /// The newest message seen per conversation, keyed by `thread_key`.
+    seen: HashMap<String, String>,
+    /// What was last sent in read receipts, keyed like `seen`.
+    reported_read: HashMap<String, String>,
+    /// The newest message read per room, here or on another device.
+    read_positions: HashMap<String, ReadPosition>,
+    /// When the newest message read by the time we connected was sent, per
+    /// room not opened since. Local reading is reported right away, so
+    /// these are kept apart from `read_positions`.
+    read_boundaries: HashMap<String, f64>,
+    /// Whether the server may have room messages older than ours.
+    history_more: bool,
+    loading_history: bool,
+    /// Keeps the relative message times current.
+    clock: Clock,
+    /// Ticks `clock`; cancelled when the component is dropped.
+    _clock_ticker: Interval,
+    _keydown_listener: EventListener,
+    _focus_listeners: [EventListener; 2],
+    /// Unregisters, on a best-effort basis, when the tab goes away without
+    /// logging out. Removed with the component.
+    _unload_listener: EventListener,
 }
 impl Component for Chat {
     type Message = Msg;
-    type Properties = ();
+    type Properties = ChatProps;
 
     fn create(ctx: &Context<Self>) -> Self {
         let (user, _) = ctx
             .link()
             .context::<User>(Callback::noop())
             .expect("context to be set");
-        let wss = WebsocketService::new();
         let username = user.username.borrow().clone();
+        let preferences = Preferences::load();
+        // Restored before registering so new messages land after it, and
+        // what we hadn't got through last time after that.
+        let mut messages: Vec<MessageData> = history::load().into_iter().map(MessageData::keyed).collect();
+        let outbox = Outbox::load(&username);
+        messages.extend(outbox.iter().map(|outgoing| MessageData {
+            from: username.clone(),
+            id: Some(outgoing.id.clone()),
+            to: outgoing.to.clone(),
+            room: outgoing.room.clone(),
+            message: outgoing.text.clone(),
+            time: Some(outgoing.time),
+            reply_to: outgoing.reply_to.clone(),
+            quote: outgoing.quote.clone(),
+            key: outgoing.id.clone(),
+            ..Default::default()
+        }));
+        let room = ctx.props().room.as_deref().and_then(room_name).unwrap_or_else(|| DEFAULT_ROOM.to_string());
+        let mut rooms = load_rooms();
+        if !rooms.contains(&room) {
+            rooms.push(room.clone());
+            save_rooms(&rooms);
+        }
 
-        let message = WebSocketMessage {
-            message_type: MsgTypes::Register,
-            data: Some(username.to_string()),
-            data_array: None,
+        // Sent again after every reconnect so we reappear in the user list.
+        // A name that slipped past the login page is sent back there
+        // instead of registering.
+        let wss = match username::validate(&username) {
+            Ok(_) => Some(WebsocketService::connect_with_retry(
+                websocket::server_url(),
+                register_frame(&username, &user.avatar.borrow()),
+                Some(Heartbeat::default()),
+            )),
+            Err(e) => {
+                back_to_login(ctx, e.to_string());
+                None
+            }
         };
 
-        if let Ok(_) = wss
-            .tx
-            .clone()
-            .try_send(serde_json::to_string(&message).unwrap())
-        {
-            log::debug!("message sent successfully");
-        }
+        let on_shortcut = ctx.link().callback(Msg::Shortcut);
+        let keydown_listener =
+            EventListener::new(&gloo::utils::document(), "keydown", move |e| {
+                if let Some(shortcut) = e.dyn_ref::<KeyboardEvent>().and_then(shortcuts::find) {
+                    e.prevent_default();
+                    on_shortcut.emit(shortcut.action);
+                }
+            });
+
+        // Ticks the labels directly, so `Chat` itself doesn't re-render and
+        // scrolling and the input are left alone.
+        let clock = Clock::default();
+        let clock_ticker = {
+            let clock = clock.clone();
+            Interval::new(CLOCK_TICK_MS, move || clock.tick())
+        };
+
+        let window = gloo::utils::window();
+        let on_focus = ctx.link().callback(|_| Msg::FocusChanged(true));
+        let on_blur = ctx.link().callback(|_| Msg::FocusChanged(false));
+        let focus_listeners = [
+            EventListener::new(&window, "focus", move |_| on_focus.emit(())),
+            EventListener::new(&window, "blur", move |_| on_blur.emit(())),
+        ];
+        let on_unload = ctx.link().callback(|_| Msg::Unloading);
+        let unload_listener = EventListener::new(&window, "beforeunload", move |_| on_unload.emit(()));
 
         Self {
             users: vec![],
-            messages: vec![],
+            roster_loaded: false,
+            entering: HashSet::new(),
+            leaving: vec![],
+            roster_transition: None,
+            roster_refill: RosterRefill::default(),
+            roster_settle: None,
+            messages,
+            conversation: Conversation::Room,
+            room,
+            rooms,
+            room_unread: HashMap::new(),
+            dm_seen: HashMap::new(),
+            notes: notes::load(),
+            capabilities: HashMap::new(),
             chat_input: NodeRef::default(),
             wss,
-            _producer: EventBus::bridge(ctx.link().callback(Msg::HandleMsg)),
+            connection: ConnectionState::Connecting,
+            preferences,
+            theme: Theme::load(),
+            show_settings: false,
+            bookmarks: Bookmarks::load(&username),
+            show_bookmarks: false,
+            jump_after_switch: None,
+            lightbox: None,
+            played_gifs: HashSet::new(),
+            show_shortcuts: false,
+            show_palette: false,
+            show_emoji_picker: false,
+            show_search: false,
+            search: String::new(),
+            search_input: NodeRef::default(),
+            focus_search: false,
+            show_export_menu: false,
+            upload: None,
+            upload_error: None,
+            dragging: false,
+            editing: None,
+            edit_input: NodeRef::default(),
+            copy_status: None,
+            reacting_to: None,
+            reaction_batch: ReactionBatch::default(),
+            reaction_flush: None,
+            replying_to: None,
+            expanded_quotes: HashSet::new(),
+            mention_links: MentionLinks {
+                present: HashMap::new(),
+                open: ctx.link().callback(|name| Msg::OpenConversation(Conversation::Direct(name))),
+            },
+            scroll_to: None,
+            outbox,
+            copy_status_timer: None,
+            focus_edit: false,
+            pending_paste: None,
+            last_sent_at: HashMap::new(),
+            rate_limiter: RateLimiter::new(RATE_LIMIT_MESSAGES, RATE_LIMIT_WINDOW_MS),
+            rate_limit_warning: None,
+            draft_len: 0,
+            max_length: max_message_length(),
+            too_long_warning: false,
+            cooldown_ticker: None,
+            typing: vec![],
+            typing_sent_at: None,
+            typing_pause_timer: None,
+            typing_ticker: None,
+            blurred_at: None,
+            unread: 0,
+            unread_divider: None,
+            unread_divider_seen: false,
+            unread_divider_ref: NodeRef::default(),
+            mentioned: false,
+            notifier: Notifier::default(),
+            pending_notifications: Vec::new(),
+            notify_timer: None,
+            away_timer: None,
+            away: false,
+            show_avatar_editor: false,
+            avatar_input: NodeRef::default(),
+            message_pane: NodeRef::default(),
+            scroll_to_bottom: false,
+            scrolled_up: false,
+            new_below: 0,
+            keep_scroll: None,
+            message_list: NodeRef::default(),
+            row_heights: HashMap::new(),
+            pane_scroll_top: 0,
+            pane_height: 0,
+            list_offset: 0,
+            room_shown: HISTORY_PAGE_SIZE,
+            history_more: true,
+            read_observer: None,
+            seen: HashMap::new(),
+            reported_read: HashMap::new(),
+            read_positions: HashMap::new(),
+            read_boundaries: HashMap::new(),
+            loading_history: false,
+            _keydown_listener: keydown_listener,
+            clock,
+            _clock_ticker: clock_ticker,
+            _focus_listeners: focus_listeners,
+            _unload_listener: unload_listener,
+            _producer: EventBus::bridge(ctx.link().callback(|request| match request {
+                Request::EventBusMsg(s) => Msg::HandleMsg(s),
+                Request::ConnectionChanged(state) => Msg::ConnectionChanged(state),
+            })),
         }
     }
 
-    fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             Msg::HandleMsg(s) => {
-                let msg: WebSocketMessage = serde_json::from_str(&s).unwrap();
+                let Some(msg) = parse_frame(&s) else {
+                    return false;
+                };
                 match msg.message_type {
                     MsgTypes::Users => {
-                        let users_from_message = msg.data_array.unwrap_or_default();
-                        self.users = users_from_message
-                            .iter()
-                            .map(|u| UserProfile {
-                                name: u.into(),
-                                avatar: format!(
-                                    "https://avatars.dicebear.com/api/adventurer-neutral/{}.svg",
-                                    u
-                                )
-                                .into(),
+                        // Servers without presence only send names; those
+                        // users count as online.
+                        let entries: Vec<RosterEntry> = match msg.data.as_deref().map(serde_json::from_str) {
+                            Some(Ok(entries)) => entries,
+                            _ => msg
+                                .data_array
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(|name| RosterEntry {
+                                    name,
+                                    status: Presence::default(),
+                                    avatar: String::new(),
+                                })
+                                .collect(),
+                        };
+                        let users: Vec<UserProfile> = entries
+                            .into_iter()
+                            .map(|e| UserProfile {
+                                avatar: if is_avatar_url(&e.avatar) {
+                                    e.avatar
+                                } else {
+                                    ctx.props().avatars.avatar_url(&e.name)
+                                },
+                                name: e.name,
+                                status: e.status,
                             })
                             .collect();
-                        return true;
+                        // Roster refreshes usually return the same list; skip
+                        // the re-render so the sidebar doesn't flicker.
+                        let refill_changed = self.connection == ConnectionState::Connected && self.roster_refill.roster(users.len());
+                        if self.roster_refill.shown().is_some() {
+                            let link = ctx.link().clone();
+                            self.roster_settle = Some(Timeout::new(ROSTER_SETTLE_MS, move || {
+                                link.send_message(Msg::RosterSettled)
+                            }));
+                        } else {
+                            self.roster_settle = None;
+                        }
+                        let profile = |u: &UserProfile| (u.name.clone(), u.status, u.avatar.clone());
+                        if users.iter().map(profile).eq(self.users.iter().map(profile)) {
+                            return refill_changed;
+                        }
+                        // The first roster is who was already here, not who
+                        // joined, so it isn't animated.
+                        if self.roster_loaded {
+                            let (joined, left) = roster_diff(&self.users, &users);
+                            let notices = joined
+                                .iter()
+                                .map(|name| format!("{} joined", name))
+                                .chain(left.iter().map(|(_, u)| format!("{} left", u.name)));
+                            for notice in notices {
+                                self.push_system_message(notice, false);
+                            }
+                            self.entering.extend(joined);
+                            self.leaving.extend(left);
+                            let link = ctx.link().clone();
+                            self.roster_transition = Some(Timeout::new(ROSTER_TRANSITION_MS, move || {
+                                link.send_message(Msg::RosterTransitionsDone)
+                            }));
+                        }
+                        self.users = users;
+                        self.mention_links.present = self.users.iter().map(|u| (u.name.to_lowercase(), u.name.clone())).collect();
+                        if !self.roster_loaded {
+                            self.roster_loaded = true;
+                            self.send_greeting(ctx);
+                        }
+                        true
                     }
-                    MsgTypes::Message => {
-                        let message_data: MessageData =
-                            serde_json::from_str(&msg.data.unwrap()).unwrap();
-                        self.messages.push(message_data);
-                        return true;
+                    MsgTypes::Message | MsgTypes::Direct | MsgTypes::File => {
+                        let Some(mut message_data) = read_message(msg) else {
+                            return false;
+                        };
+                        message_data.time.get_or_insert_with(js_sys::Date::now);
+                        self.typing.retain(|(name, _)| *name != message_data.from);
+                        let me = current_username(ctx);
+                        // Our own messages are shown from when they were sent;
+                        // the echo confirms them in place. Repeated echoes are
+                        // dropped.
+                        let shown = self
+                            .messages
+                            .iter_mut()
+                            .find(|m| m.id.is_some() && m.id == message_data.id && m.from == message_data.from);
+                        if let Some(shown) = shown {
+                            if message_data.id.as_deref().and_then(|id| self.outbox.confirm(id)).is_none() {
+                                return false;
+                            }
+                            self.outbox.save(&me);
+                            message_data.key = std::mem::take(&mut shown.key);
+                            *shown = message_data;
+                            self.save_history();
+                            return true;
+                        }
+                        if self.blurred_at.is_some() && message_data.from != me {
+                            if self.unread == 0 {
+                                self.unread_divider = Some(self.messages.len());
+                                self.unread_divider_seen = false;
+                            }
+                            self.unread += 1;
+                            self.mentioned |= mentions(&message_data.message, &me);
+                            update_document_title(self.unread, self.mentioned);
+                            if self.preferences.notifies_about(&message_data.from) {
+                                self.queue_notification(ctx, &message_data);
+                            }
+                        }
+                        let elsewhere = message_data.to.is_none() && room_of(&message_data) != self.room;
+                        if elsewhere && message_data.from != me {
+                            *self.room_unread.entry(room_of(&message_data).to_string()).or_default() += 1;
+                        }
+                        // Follow the conversation unless the user scrolled up
+                        // to read; our own messages always bring it back.
+                        if !elsewhere && (!self.scrolled_up || message_data.from == me) {
+                            self.scroll_to_bottom = true;
+                        } else if self.scrolled_up && self.in_conversation(&message_data, &me) {
+                            self.new_below += 1;
+                        }
+                        self.messages.push(message_data.keyed());
+                        self.save_history();
+                        true
                     }
-                    _ => {
-                        return false;
+                    MsgTypes::Edit | MsgTypes::Delete => {
+                        let change: MessageChange = match serde_json::from_str(msg.data.as_deref().unwrap_or_default()) {
+                            Ok(change) => change,
+                            Err(e) => {
+                                log::error!("invalid {:?} frame: {:?}", msg.message_type, e);
+                                return false;
+                            }
+                        };
+                        // Only the author may change a message; changes to
+                        // messages we no longer have are dropped.
+                        let target = self
+                            .messages
+                            .iter_mut()
+                            .find(|m| m.id.as_ref() == Some(&change.id) && m.from == change.from);
+                        let Some(m) = target else {
+                            return false;
+                        };
+                        match (msg.message_type, change.message) {
+                            (MsgTypes::Edit, Some(message)) if !m.deleted => {
+                                m.message = message;
+                                m.edited = true;
+                            }
+                            (MsgTypes::Delete, _) => {
+                                m.message.clear();
+                                m.deleted = true;
+                            }
+                            _ => return false,
+                        }
+                        self.save_history();
+                        true
+                    }
+                    MsgTypes::Typing => {
+                        let Some(name) = msg.data else {
+                            return false;
+                        };
+                        if msg.room.is_some_and(|room| room != self.room) {
+                            return false;
+                        }
+                        if name == current_username(ctx) {
+                            return false;
+                        }
+                        let now = js_sys::Date::now();
+                        match self.typing.iter_mut().find(|(n, _)| *n == name) {
+                            Some(entry) => entry.1 = now,
+                            None => self.typing.push((name, now)),
+                        }
+                        self.update_typing_ticker(ctx);
+                        true
+                    }
+                    MsgTypes::StopTyping => {
+                        let Some(name) = msg.data else {
+                            return false;
+                        };
+                        let before = self.typing.len();
+                        self.typing.retain(|(n, _)| *n != name);
+                        self.update_typing_ticker(ctx);
+                        self.typing.len() != before
+                    }
+                    MsgTypes::Reaction => {
+                        let reaction: Reaction = match serde_json::from_str(msg.data.as_deref().unwrap_or_default()) {
+                            Ok(reaction) => reaction,
+                            Err(e) => {
+                                log::error!("invalid reaction frame: {:?}", e);
+                                return false;
+                            }
+                        };
+                        // Reactions to messages we don't have are dropped,
+                        // as are emoji this client doesn't offer.
+                        let target = self.messages.iter_mut().find(|m| m.id.as_ref() == Some(&reaction.id));
+                        let (Some(m), true) = (target, REACTIONS.contains(&reaction.emoji.as_str())) else {
+                            return false;
+                        };
+                        set_reaction(m, &reaction.emoji, reaction.from, reaction.add);
+                        self.save_history();
+                        true
+                    }
+                    MsgTypes::Ack => {
+                        let ack: Ack = match serde_json::from_str(msg.data.as_deref().unwrap_or_default()) {
+                            Ok(ack) => ack,
+                            Err(e) => {
+                                log::error!("invalid ack frame: {:?}", e);
+                                return false;
+                            }
+                        };
+                        let Some(shown_as) = self.outbox.acknowledge(&ack.client_msg_id, ack.id.clone()) else {
+                            log::debug!("ack for unknown or settled message {}", ack.client_msg_id);
+                            return false;
+                        };
+                        // From now on the message goes by the server's id, which
+                        // edits, reactions and replies refer to.
+                        if let Some(m) = self.messages.iter_mut().find(|m| m.id.as_ref() == Some(&shown_as)) {
+                            m.id = Some(ack.id);
+                        }
+                        true
+                    }
+                    MsgTypes::Rejected => {
+                        self.wss = None;
+                        back_to_login(ctx, msg.data.unwrap_or_else(|| "The server didn't accept this name.".to_string()));
+                        false
+                    }
+                    MsgTypes::System => {
+                        let Some(text) = msg.data.filter(|text| !text.trim().is_empty()) else {
+                            return false;
+                        };
+                        self.push_system_message(text, true);
+                        true
+                    }
+                    MsgTypes::Read => {
+                        let receipt: ReadReceipt = match serde_json::from_str(msg.data.as_deref().unwrap_or_default()) {
+                            Ok(receipt) => receipt,
+                            Err(e) => {
+                                log::error!("invalid read frame: {:?}", e);
+                                return false;
+                            }
+                        };
+                        let me = current_username(ctx);
+                        let Some(pos) = self.messages.iter().position(|m| m.id.as_ref() == Some(&receipt.id)) else {
+                            return false;
+                        };
+                        // Seeing a message means having seen the ones before
+                        // it in the same conversation.
+                        let room = self.messages[pos].to.is_none().then(|| room_of(&self.messages[pos]).to_string());
+                        let mut changed = false;
+                        let mut latest_seen = None;
+                        for m in &mut self.messages[..=pos] {
+                            let same_conversation = if let Some(room) = &room {
+                                m.to.is_none() && room_of(m) == room
+                            } else {
+                                m.to.as_ref() == Some(&receipt.from)
+                            };
+                            if m.from == me && same_conversation && !m.system {
+                                changed |= m.read_by.insert(receipt.from.clone());
+                                latest_seen = Some(m.key.clone());
+                            }
+                        }
+                        // Receipts from a peer's other tabs may come late;
+                        // the latest one wins.
+                        if let (None, Some(key)) = (&room, latest_seen) {
+                            let time = receipt.time.unwrap_or_else(js_sys::Date::now);
+                            if self.dm_seen.get(&receipt.from).is_none_or(|&(_, seen)| time >= seen) {
+                                changed |= self.dm_seen.insert(receipt.from, (key.clone(), time)) != Some((key, time));
+                            }
+                        }
+                        changed
+                    }
+                    MsgTypes::History => {
+                        // A page for a room we have since left the view of.
+                        if msg.room.as_deref().unwrap_or(DEFAULT_ROOM) != self.room {
+                            return false;
+                        }
+                        self.loading_history = false;
+                        let page: HistoryPage = match serde_json::from_str(msg.data.as_deref().unwrap_or_default()) {
+                            Ok(page) => page,
+                            Err(e) => {
+                                log::error!("invalid history frame: {:?}", e);
+                                return true;
+                            }
+                        };
+                        self.history_more = page.more;
+                        // The page may overlap what we loaded from storage.
+                        let older: Vec<MessageData> = page
+                            .messages
+                            .into_iter()
+                            .filter(|m| m.id.is_none() || !self.messages.iter().any(|known| known.id == m.id))
+                            .filter(|m| !has_unsafe_attachment(m))
+                            .map(MessageData::keyed)
+                            .collect();
+                        self.keep_scroll_position();
+                        self.room_shown += older.len();
+                        if let Some(divider) = &mut self.unread_divider {
+                            *divider += older.len();
+                        }
+                        self.messages.splice(0..0, older);
+                        true
+                    }
+                    MsgTypes::ReadPosition => {
+                        let position: ReadPosition = match serde_json::from_str(msg.data.as_deref().unwrap_or_default()) {
+                            Ok(position) => position,
+                            Err(e) => {
+                                log::error!("invalid read position frame: {:?}", e);
+                                return false;
+                            }
+                        };
+                        let room = msg.room.unwrap_or_else(|| DEFAULT_ROOM.to_string());
+                        let boundary = self.read_boundaries.entry(room.clone()).or_insert(position.time);
+                        *boundary = boundary.max(position.time);
+                        if reads_further(&self.read_positions, &room, position.time) {
+                            self.read_positions.insert(room.clone(), position);
+                        }
+                        room == self.room && self.show_read_boundary(&current_username(ctx))
+                    }
+                    MsgTypes::Capabilities => {
+                        match serde_json::from_str(msg.data.as_deref().unwrap_or("{}")) {
+                            Ok(capabilities) => {
+                                let room = msg.room.unwrap_or_else(|| DEFAULT_ROOM.to_string());
+                                self.capabilities.insert(room, capabilities);
+                                self.update_cooldown_ticker(ctx);
+                                true
+                            }
+                            Err(e) => {
+                                log::error!("invalid capabilities frame: {:?}", e);
+                                false
+                            }
+                        }
+                    }
+                    _ => false,
+                }
+            }
+            Msg::ConnectionChanged(state) => {
+                let was_connected = std::mem::replace(&mut self.connection, state) == ConnectionState::Connected;
+                match state {
+                    ConnectionState::Connected => {
+                        for room in &self.rooms {
+                            self.send(WebSocketMessage {
+                                room: Some(room.clone()),
+                                ..WebSocketMessage::new(MsgTypes::Join, None)
+                            });
+                        }
+                        self.flush_outbox(ctx);
+                    }
+                    ConnectionState::Offline => {
+                        self.outbox.give_up();
+                        self.roster_refill.settle();
+                        self.roster_settle = None;
                     }
+                    ConnectionState::Reconnecting { .. } if was_connected => self.roster_refill.dropped(self.users.len()),
+                    ConnectionState::Connecting | ConnectionState::Reconnecting { .. } => {}
+                }
+                true
+            }
+            Msg::RetryOutgoing(id) => {
+                if !self.outbox.retry(&id) {
+                    return false;
                 }
+                if self.connection == ConnectionState::Offline {
+                    self.reconnect(ctx);
+                } else {
+                    self.flush_outbox(ctx);
+                }
+                true
+            }
+            Msg::DiscardOutgoing(id) => {
+                let Some(outgoing) = self.outbox.discard(&id) else {
+                    return false;
+                };
+                self.outbox.save(&current_username(ctx));
+                if let Some(index) = self.messages.iter().position(|m| outgoing.shows(m.id.as_deref())) {
+                    self.remove_message(index);
+                }
+                true
             }
+            Msg::OutgoingTimedOut(id) => self.outbox.time_out(&id),
             Msg::SubmitMessage => {
                 let input = self.chat_input.cast::<HtmlInputElement>();
                 if let Some(input) = input {
-                    let message = WebSocketMessage {
-                        message_type: MsgTypes::Message,
-                        data: Some(input.value()),
-                        data_array: None,
-                    };
-                    if let Err(e) = self
-                        .wss
-                        .tx
-                        .clone()
-                        .try_send(serde_json::to_string(&message).unwrap())
-                    {
-                        log::debug!("error sending to channel: {:?}", e);
+                    if input.value().trim().is_empty() {
+                        return false;
+                    }
+                    if input.value().chars().count() > self.max_length {
+                        self.too_long_warning = true;
+                        return true;
+                    }
+                    if self.submit_text(ctx, input.value()) {
+                        input.set_value("");
+                        self.sync_draft_len();
+                        // The message itself ends the typing notice for
+                        // the others.
+                        self.typing_pause_timer = None;
+                        self.typing_sent_at = None;
                     }
-                    input.set_value("");
                 };
+                true
+            }
+            Msg::InputChanged => {
+                let previous_len = self.draft_len;
+                self.sync_draft_len();
+                let now = js_sys::Date::now();
+                let due = self
+                    .typing_sent_at
+                    .map(|sent| now - sent >= TYPING_SEND_INTERVAL_MS)
+                    .unwrap_or(true);
+                // Notes are private, so typing in them isn't announced.
+                if self.conversation == Conversation::Room {
+                    if due {
+                        self.typing_sent_at = Some(now);
+                        self.send(WebSocketMessage {
+                            room: Some(self.room.clone()),
+                            ..WebSocketMessage::new(MsgTypes::Typing, None)
+                        });
+                    }
+                    let link = ctx.link().clone();
+                    self.typing_pause_timer = Some(Timeout::new(TYPING_PAUSE_MS, move || {
+                        link.send_message(Msg::TypingPaused)
+                    }));
+                }
+                self.draft_len != previous_len
+            }
+            Msg::TypingPaused => {
+                self.typing_pause_timer = None;
+                if self.typing_sent_at.take().is_some() {
+                    self.send(WebSocketMessage {
+                        room: Some(self.room.clone()),
+                        ..WebSocketMessage::new(MsgTypes::StopTyping, None)
+                    });
+                }
                 false
             }
-        }
-    }
-
-    fn view(&self, ctx: &Context<Self>) -> Html {
-        let submit = ctx.link().callback(|_| Msg::SubmitMessage);
-
-        // Get current user for message bubble coloring
-        let current_username = ctx
-            .link()
-            .context::<User>(Callback::noop())
-            .map(|(u, _)| u.username.borrow().clone())
-            .unwrap_or_default();
-
-        html! {
-            <div class="flex w-screen">
-                <div class="flex-none w-56 h-screen bg-gradient-to-b from-gray-100 to-blue-100">
-                    <div class="text-xl p-3 flex flex-col gap-2">
-                        {"Users"}
-                        <span class="text-xs text-blue-600">{"🌟 Active Now"}</span>
-                    </div>
-                    {
-                        self.users.clone().iter().map(|u| {
-                            html!{
-                                <div class="flex m-3 bg-white rounded-lg p-2 shadow-sm items-center">
-                                    <div>
-                                        <img class="w-12 h-12 rounded-full border-2 border-blue-200" src={u.avatar.clone()} alt="avatar"/>
-                                    </div>
-                                    <div class="flex-grow p-3">
-                                        <div class="flex text-xs justify-between">
-                                            <div class="font-semibold">{u.name.clone()}</div>
-                                        </div>
-                                        <div class="text-xs text-gray-400 italic">
-                                            {"Hi there! 👋"}
-                                        </div>
-                                    </div>
-                                </div>
+            Msg::TypingTick => {
+                self.update_typing_ticker(ctx);
+                true
+            }
+            Msg::LargePaste(text) => {
+                self.pending_paste = Some(text);
+                true
+            }
+            Msg::SendSnippet => {
+                if let Some(text) = self.pending_paste.take() {
+                    let snippet = format!("```\n{}\n```", text.trim_end_matches('\n'));
+                    if !self.submit_text(ctx, snippet) {
+                        self.pending_paste = Some(text);
+                    }
+                }
+                true
+            }
+            Msg::PasteInline => {
+                if let Some(text) = self.pending_paste.take() {
+                    if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+                        let _ = input.set_range_text(&text);
+                    }
+                    self.sync_draft_len();
+                    self.focus_input();
+                }
+                true
+            }
+            Msg::CancelPaste => {
+                self.pending_paste = None;
+                true
+            }
+            Msg::CooldownTick => {
+                self.update_cooldown_ticker(ctx);
+                true
+            }
+            Msg::OpenConversation(conversation) => {
+                if conversation != self.conversation {
+                    self.replying_to = None;
+                }
+                self.conversation = conversation;
+                self.scroll_to_bottom = true;
+                true
+            }
+            Msg::OpenRoom(room) if room == self.room => {
+                self.conversation = Conversation::Room;
+                self.room_unread.remove(&room);
+                self.scroll_to_bottom = true;
+                true
+            }
+            Msg::OpenRoom(room) => {
+                // The route change comes back through `changed`.
+                match ctx.link().history() {
+                    Some(history) => {
+                        history.push(Route::Room { room });
+                        false
+                    }
+                    None => {
+                        self.switch_room(ctx, room);
+                        true
+                    }
+                }
+            }
+            Msg::JoinRoom(input) => {
+                let Some(room) = room_name(&input) else {
+                    return false;
+                };
+                self.join_room(&room);
+                ctx.link().send_message(Msg::OpenRoom(room));
+                true
+            }
+            Msg::LeaveRoom(room) => {
+                if room == DEFAULT_ROOM || !self.rooms.contains(&room) {
+                    return false;
+                }
+                self.rooms.retain(|r| *r != room);
+                save_rooms(&self.rooms);
+                self.room_unread.remove(&room);
+                self.send(WebSocketMessage {
+                    room: Some(room.clone()),
+                    ..WebSocketMessage::new(MsgTypes::Leave, None)
+                });
+                if self.room == room {
+                    ctx.link().send_message(Msg::OpenRoom(DEFAULT_ROOM.to_string()));
+                }
+                true
+            }
+            Msg::PaneScrolled => {
+                let scrolled_up = !self.is_near_bottom();
+                if let Some(pane) = self.message_pane.cast::<web_sys::Element>() {
+                    self.pane_scroll_top = pane.scroll_top();
+                    self.pane_height = pane.client_height();
+                }
+                let virtualized = self.shown_rows(&current_username(ctx)) > VIRTUALIZE_AFTER;
+                let divider_changed = self.track_unread_divider();
+                if !scrolled_up {
+                    self.new_below = 0;
+                }
+                std::mem::replace(&mut self.scrolled_up, scrolled_up) != scrolled_up || virtualized || divider_changed
+            }
+            Msg::MessagesSeen(ids) => {
+                for id in ids {
+                    let Some(pos) = self.messages.iter().position(|m| m.id.as_ref() == Some(&id)) else {
+                        continue;
+                    };
+                    let key = thread_key(&self.messages[pos]);
+                    let seen_pos = self
+                        .seen
+                        .get(&key)
+                        .and_then(|seen| self.messages.iter().position(|m| m.id.as_ref() == Some(seen)));
+                    let newer = seen_pos.is_none_or(|seen_pos| pos > seen_pos);
+                    if newer {
+                        self.seen.insert(key, id);
+                    }
+                }
+                self.report_read();
+                false
+            }
+            Msg::LoadEarlier => {
+                if self.hidden_room_messages(&current_username(ctx)) > 0 {
+                    self.keep_scroll_position();
+                    self.room_shown += HISTORY_PAGE_SIZE;
+                    return true;
+                }
+                if self.loading_history {
+                    return false;
+                }
+                let before = self
+                    .messages
+                    .iter()
+                    .filter(|m| m.to.is_none() && !m.system && room_of(m) == self.room)
+                    .find_map(|m| m.id.clone());
+                self.loading_history = true;
+                self.send(WebSocketMessage {
+                    id: before,
+                    room: Some(self.room.clone()),
+                    ..WebSocketMessage::new(MsgTypes::History, None)
+                });
+                true
+            }
+            Msg::JumpToLatest => {
+                self.scroll_to_bottom = true;
+                self.new_below = 0;
+                true
+            }
+            Msg::JumpToUnread => {
+                self.scroll_to = self
+                    .unread_divider_row(&current_username(ctx))
+                    .map(|index| self.messages[index].key.clone());
+                self.scroll_to.is_some()
+            }
+            Msg::FlushNotifications => {
+                self.notify_timer = None;
+                let pending = std::mem::take(&mut self.pending_notifications);
+                let (title, body) = match pending.as_slice() {
+                    [] => return false,
+                    [(from, text)] => (from.clone(), text.clone()),
+                    _ => {
+                        let mut senders: Vec<&str> = Vec::new();
+                        for (from, _) in &pending {
+                            if !senders.contains(&from.as_str()) {
+                                senders.push(from);
                             }
-                        }).collect::<Html>()
+                        }
+                        (format!("{} new messages", pending.len()), format!("From {}", senders.join(", ")))
                     }
-                </div>
-                <div class="grow h-screen flex flex-col" style="background: repeating-linear-gradient(135deg,#f0f4ff 0 20px,#e9e9f9 20px 40px);">
-                    <div class="w-full h-14 border-b-2 border-gray-300 flex items-center justify-between">
-                        <div class="text-xl p-3 flex items-center gap-2">
-                            {"💬 Chat!"}
-                            <span class="ml-2 text-sm text-blue-400 animate-bounce">{"Welcome to the fun zone! 🎉"}</span>
-                        </div>
-                        <div class="mr-4 text-xs text-gray-500 italic">{"Tip: Try sending an emoji or a .gif URL!"}</div>
-                    </div>
-                    <div class="w-full grow overflow-auto border-b-2 border-gray-300 px-2 py-4">
-                        {
-                            self.messages.iter().map(|m| {
-                                let user = self.users.iter().find(|u| u.name == m.from);
-                                let avatar = user.map(|u| u.avatar.clone()).unwrap_or_else(|| "https://cdn-icons-png.flaticon.com/512/4712/4712035.png".to_string());
-                                let is_me = m.from == current_username;
-                                let bubble_class = if is_me {
-                                    "bg-blue-200 text-right ml-auto"
-                                } else {
-                                    "bg-gray-100"
-                                };
-                                let text_class = if is_me {
-                                    "text-blue-900"
+                };
+                self.notifier.chime();
+                self.notifier.show(&title, &body);
+                false
+            }
+            Msg::RateLimitWarningDone => {
+                self.rate_limit_warning = None;
+                true
+            }
+            Msg::Logout => {
+                // The frame still goes out after this component and its
+                // service are dropped; see `WebsocketService`.
+                self.send_frame(MsgTypes::Unregister, None);
+                if let Some(history) = ctx.link().history() {
+                    history.push(Route::Login);
+                }
+                false
+            }
+            Msg::Unloading => {
+                // The page may be gone before the frame is written; the
+                // server drops us when the socket closes either way.
+                self.send_frame(MsgTypes::Unregister, None);
+                false
+            }
+            Msg::ToggleTheme => {
+                self.theme = self.theme.toggled();
+                self.theme.save();
+                self.theme.apply_to_document();
+                true
+            }
+            Msg::RosterTransitionsDone => {
+                self.entering.clear();
+                self.leaving.clear();
+                self.roster_transition = None;
+                true
+            }
+            Msg::RosterSettled => {
+                self.roster_settle = None;
+                self.roster_refill.settle()
+            }
+            Msg::FocusChanged(focused) => {
+                if !focused {
+                    self.blurred_at = Some(js_sys::Date::now());
+                    let link = ctx.link().clone();
+                    self.away_timer = Some(Timeout::new(AWAY_AFTER_MS, move || {
+                        link.send_message(Msg::GoAway)
+                    }));
+                    return false;
+                }
+                self.away_timer = None;
+                if std::mem::take(&mut self.away) {
+                    self.send_status(Presence::Online);
+                }
+                if let Some(blurred_at) = self.blurred_at.take() {
+                    if js_sys::Date::now() - blurred_at > STALE_ROSTER_AFTER_MS {
+                        self.request_roster();
+                    }
+                }
+                self.report_read();
+                // Nothing to notify about once the user is back.
+                self.pending_notifications.clear();
+                self.notify_timer = None;
+                let had_unread = self.unread > 0;
+                self.unread = 0;
+                self.mentioned = false;
+                update_document_title(0, false);
+                had_unread
+            }
+            Msg::ToggleBookmarks => {
+                self.show_bookmarks = !self.show_bookmarks;
+                true
+            }
+            Msg::ToggleBookmark(id) => {
+                let Some(m) = self.find_message(&id) else {
+                    return false;
+                };
+                let me = current_username(ctx);
+                let bookmark = Bookmark {
+                    id,
+                    from: m.from.clone(),
+                    text: match &m.file {
+                        Some(file) => format!("📎 {}", file.name),
+                        None => m.message.clone(),
+                    },
+                    time: m.time,
+                    room: m.to.is_none().then(|| room_of(m).to_string()),
+                    peer: m.to.as_ref().map(|to| if *to == me { m.from.clone() } else { to.clone() }),
+                };
+                self.bookmarks.toggle(bookmark);
+                self.bookmarks.save(&me);
+                true
+            }
+            Msg::RemoveBookmark(id) => {
+                let removed = self.bookmarks.remove(&id);
+                if removed {
+                    self.bookmarks.save(&current_username(ctx));
+                }
+                removed
+            }
+            Msg::OpenBookmark(id) => {
+                let Some(bookmark) = self.bookmarks.iter().find(|b| b.id == id) else {
+                    return false;
+                };
+                match (&bookmark.peer, &bookmark.room) {
+                    (Some(peer), _) => self.conversation = Conversation::Direct(peer.clone()),
+                    (None, Some(room)) if *room != self.room => {
+                        self.jump_after_switch = Some(id);
+                        ctx.link().send_message(Msg::OpenRoom(room.clone()));
+                        return false;
+                    }
+                    (None, _) => self.conversation = Conversation::Room,
+                }
+                ctx.link().send_message(Msg::ScrollToMessage(id));
+                true
+            }
+            Msg::ToggleSettings => {
+                self.show_settings = !self.show_settings;
+                true
+            }
+            Msg::UpdatePreferences(preferences) => {
+                preferences.save();
+                let history_limit_changed = preferences.history_limit != self.preferences.history_limit;
+                if preferences.notify && !self.preferences.notify {
+                    notifications::request_permission();
+                }
+                preferences.apply_to_document();
+                self.preferences = preferences;
+                self.report_read();
+                if history_limit_changed {
+                    self.save_history();
+                }
+                true
+            }
+            Msg::ToggleNotifMute(name) => {
+                let mut preferences = self.preferences.clone();
+                if !preferences.notif_muted.remove(&name) {
+                    preferences.notif_muted.insert(name);
+                }
+                ctx.link().send_message(Msg::UpdatePreferences(preferences));
+                false
+            }
+            Msg::PlayGif(key) => self.played_gifs.insert(key),
+            Msg::OpenLightbox(src) => {
+                self.lightbox = Some(src);
+                true
+            }
+            Msg::CloseLightbox => {
+                self.lightbox = None;
+                true
+            }
+            Msg::React(id, emoji) => {
+                self.reacting_to = None;
+                let me = current_username(ctx);
+                let Some(m) = self.messages.iter_mut().find(|m| m.id.as_ref() == Some(&id)) else {
+                    return true;
+                };
+                let was = m.reactions.get(emoji).map(|names| names.contains(&me)).unwrap_or(false);
+                let to = m.to.as_ref().map(|to| if *to == me { m.from.clone() } else { to.clone() });
+                // Shown right away; the server's echo of the batched change
+                // leaves the tally as it is.
+                set_reaction(m, emoji, me, !was);
+                self.reaction_batch.toggle(&id, emoji, to, was);
+                let link = ctx.link().clone();
+                self.reaction_flush = Some(Timeout::new(REACTION_BATCH_MS, move || link.send_message(Msg::FlushReactions)));
+                true
+            }
+            Msg::FlushReactions => {
+                self.reaction_flush = None;
+                let me = current_username(ctx);
+                let mut reverted = false;
+                for pending in self.reaction_batch.take() {
+                    let change = ReactionChange {
+                        emoji: pending.emoji,
+                        add: pending.add,
+                    };
+                    let sent = self.send(WebSocketMessage {
+                        id: Some(pending.id.clone()),
+                        to: pending.to,
+                        ..WebSocketMessage::new(MsgTypes::Reaction, serde_json::to_string(&change).ok())
+                    });
+                    // Nobody else will see it, so neither should we.
+                    if let Some(m) = self.messages.iter_mut().find(|m| !sent && m.id.as_ref() == Some(&pending.id)) {
+                        set_reaction(m, pending.emoji, me.clone(), !pending.add);
+                        reverted = true;
+                    }
+                }
+                reverted
+            }
+            Msg::QuickReact(id) => {
+                // Double-clicking a word selects it, and that is all it
+                // should do.
+                let selected = gloo::utils::window().get_selection().ok().flatten().is_some_and(|s| !s.is_collapsed());
+                if let Some(emoji) = quick_reaction(&self.preferences.quick_reaction).filter(|_| !selected) {
+                    ctx.link().send_message(Msg::React(id, emoji));
+                }
+                false
+            }
+            Msg::ToggleReactionPicker(id) => {
+                self.reacting_to = if self.reacting_to == id { None } else { id };
+                true
+            }
+            Msg::CopyMessage(key) => {
+                let Some(text) = self.messages.iter().find(|m| m.key == key).map(|m| m.message.clone()) else {
+                    return false;
+                };
+                ctx.link().send_future(async move {
+                    let copied = clipboard::write_text(&text).await;
+                    if let Err(e) = &copied {
+                        log::error!("failed to copy message: {:?}", e);
+                    }
+                    Msg::Copied(key, copied.is_ok())
+                });
+                false
+            }
+            Msg::Copied(key, ok) => {
+                self.copy_status = Some((key, ok));
+                // A failure leaves the hint up longer, to read it.
+                let duration = if ok { COPY_STATUS_MS } else { COPY_STATUS_MS * 3 };
+                let link = ctx.link().clone();
+                self.copy_status_timer = Some(Timeout::new(duration, move || {
+                    link.send_message(Msg::CopyStatusDone)
+                }));
+                true
+            }
+            Msg::CopyStatusDone => {
+                self.copy_status_timer = None;
+                self.copy_status.take().is_some()
+            }
+            Msg::Reply(id) => {
+                let quote = self.selected_quote(&id);
+                self.replying_to = Some(ReplyTarget { id, quote });
+                self.focus_input();
+                true
+            }
+            Msg::ReplyPrivately(id) => {
+                let me = current_username(ctx);
+                let selected = self.selected_quote(&id);
+                let Some((peer, target)) = self.find_message(&id).and_then(|m| private_reply(m, selected, &me)) else {
+                    return false;
+                };
+                self.conversation = Conversation::Direct(peer);
+                self.replying_to = Some(target);
+                self.scroll_to_bottom = true;
+                self.focus_input();
+                true
+            }
+            Msg::CancelReply => self.replying_to.take().is_some(),
+            Msg::ToggleQuote(key) => {
+                if !self.expanded_quotes.remove(&key) {
+                    self.expanded_quotes.insert(key);
+                }
+                true
+            }
+            Msg::ScrollToMessage(id) => {
+                let me = current_username(ctx);
+                let Some(index) = self.messages.iter().position(|m| m.id.as_ref() == Some(&id)) else {
+                    return false;
+                };
+                let Some(row) = self.matching(&me).position(|(i, _)| i == index) else {
+                    return false;
+                };
+                // The message may be among those "Load earlier" holds back.
+                let hidden = self.hidden_room_messages(&me);
+                if row < hidden {
+                    self.room_shown += hidden - row;
+                }
+                self.scroll_to = Some(self.messages[index].key.clone());
+                true
+            }
+            Msg::StartEdit(id) => {
+                self.editing = Some(id);
+                self.focus_edit = true;
+                true
+            }
+            Msg::SubmitEdit => {
+                let Some(id) = self.editing.take() else {
+                    return false;
+                };
+                let text = match self.edit_input.cast::<HtmlInputElement>() {
+                    Some(input) => input.value(),
+                    None => return true,
+                };
+                let original = self.messages.iter().find(|m| m.id.as_ref() == Some(&id));
+                if let Some(original) = original {
+                    if !text.trim().is_empty() && text != original.message {
+                        self.send(WebSocketMessage {
+                            id: Some(id),
+                            to: original.to.clone(),
+                            ..WebSocketMessage::new(MsgTypes::Edit, Some(text))
+                        });
+                    }
+                }
+                true
+            }
+            Msg::DeleteMessage(id) => {
+                if let Some(m) = self.messages.iter().find(|m| m.id.as_ref() == Some(&id)) {
+                    self.send(WebSocketMessage {
+                        id: Some(id),
+                        to: m.to.clone(),
+                        ..WebSocketMessage::new(MsgTypes::Delete, None)
+                    });
+                }
+                false
+            }
+            Msg::PickFile(file) => {
+                if file.size() > MAX_UPLOAD_BYTES {
+                    self.upload_error = Some(format!(
+                        "{} is too large. Files can be at most {} MB.",
+                        file.name(),
+                        MAX_UPLOAD_BYTES / 1024 / 1024
+                    ));
+                    return true;
+                }
+                let name = file.name();
+                let link = ctx.link().clone();
+                self.upload_error = None;
+                self.upload = Some(gloo::file::callbacks::read_as_data_url(&file, move |result| {
+                    link.send_message(Msg::FileLoaded(name, result.map_err(|e| e.to_string())))
+                }));
+                true
+            }
+            Msg::FileLoaded(name, result) => {
+                self.upload = None;
+                match result {
+                    Ok(url) => self.send_attachment(ctx, Attachment { name, url }),
+                    Err(e) => self.upload_error = Some(format!("Couldn't read {}: {}", name, e)),
+                }
+                true
+            }
+            Msg::GoAway => {
+                self.away_timer = None;
+                self.away = true;
+                self.send_status(Presence::Away);
+                false
+            }
+            Msg::ToggleAvatarEditor => {
+                self.show_avatar_editor = !self.show_avatar_editor;
+                true
+            }
+            Msg::SetAvatar(url) => {
+                let url = url.trim().to_string();
+                if !url.is_empty() && !is_avatar_url(&url) {
+                    return false;
+                }
+                self.show_avatar_editor = false;
+                let (user, _) = ctx
+                    .link()
+                    .context::<User>(Callback::noop())
+                    .expect("context to be set");
+                *user.avatar.borrow_mut() = url.clone();
+                if let Err(e) = LocalStorage::set(AVATAR_STORAGE_KEY, &url) {
+                    log::error!("failed to save avatar: {:?}", e);
+                }
+                if let Some(wss) = &self.wss {
+                    wss.set_handshake(register_frame(&user.username.borrow(), &url));
+                }
+                self.send_frame(MsgTypes::Avatar, Some(url));
+                true
+            }
+            Msg::SetDragging(dragging) => std::mem::replace(&mut self.dragging, dragging) != dragging,
+            Msg::DroppedNonFile => {
+                self.dragging = false;
+                self.upload_error = Some("Only files can be dropped here.".to_string());
+                true
+            }
+            Msg::DismissUploadError => {
+                self.upload_error = None;
+                true
+            }
+            Msg::ToggleSearch => {
+                self.show_search = !self.show_search;
+                self.focus_search = self.show_search;
+                // Closing the search brings every message back.
+                self.search.clear();
+                self.scroll_to_bottom = true;
+                true
+            }
+            Msg::SearchChanged(query) => {
+                self.search = query;
+                true
+            }
+            Msg::ToggleExportMenu => {
+                self.show_export_menu = !self.show_export_menu;
+                true
+            }
+            Msg::ExportTranscript(format) => {
+                self.show_export_menu = false;
+                let (title, entries) = self.transcript(&current_username(ctx));
+                download(
+                    &transcript::filename(&title, format),
+                    format.mime_type(),
+                    &transcript::render(&title, &entries, format),
+                );
+                true
+            }
+            Msg::ToggleEmojiPicker => {
+                self.show_emoji_picker = !self.show_emoji_picker;
+                true
+            }
+            Msg::InsertEmoji(emoji) => {
+                self.show_emoji_picker = false;
+                if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+                    let value = input.value();
+                    let end = input
+                        .selection_end()
+                        .ok()
+                        .flatten()
+                        .unwrap_or(value.encode_utf16().count() as u32);
+                    let start = input.selection_start().ok().flatten().unwrap_or(end);
+                    let (value, caret) = toolbar::insert(&value, start, end, emoji);
+                    input.set_value(&value);
+                    let _ = input.set_selection_range(caret, caret);
+                }
+                self.sync_draft_len();
+                self.focus_input();
+                true
+            }
+            Msg::Shortcut(action) => match action {
+                Action::ShowHelp => {
+                    self.show_shortcuts = true;
+                    true
+                }
+                Action::CloseOverlay => {
+                    let changed = self.show_shortcuts
+                        || self.show_palette
+                        || self.show_emoji_picker
+                        || self.editing.is_some()
+                        || self.reacting_to.is_some()
+                        || self.replying_to.is_some()
+                        || self.show_export_menu
+                        || self.lightbox.is_some();
+                    self.show_shortcuts = false;
+                    self.show_palette = false;
+                    self.show_emoji_picker = false;
+                    self.editing = None;
+                    self.reacting_to = None;
+                    self.replying_to = None;
+                    self.show_export_menu = false;
+                    self.lightbox = None;
+                    changed
+                }
+                Action::FocusInput => {
+                    self.focus_input();
+                    false
+                }
+                Action::OpenPalette => {
+                    self.show_palette = true;
+                    true
+                }
+            },
+            Msg::RunCommand(command) => {
+                self.show_palette = false;
+                match command {
+                    Command::ShowShortcuts => self.show_shortcuts = true,
+                    Command::ToggleSettings => self.show_settings = !self.show_settings,
+                    Command::ToggleCompactRoster => {
+                        let mut preferences = self.preferences.clone();
+                        preferences.compact_roster = !preferences.compact_roster;
+                        ctx.link().send_message(Msg::UpdatePreferences(preferences));
+                    }
+                    Command::ToggleGifAutoplay => {
+                        let mut preferences = self.preferences.clone();
+                        preferences.autoplay_gifs = !preferences.autoplay_gifs;
+                        ctx.link().send_message(Msg::UpdatePreferences(preferences));
+                    }
+                    Command::FocusInput => self.focus_input(),
+                    Command::OpenNotes => self.conversation = Conversation::Notes,
+                    Command::Mention(username) => {
+                        if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+                            let value = input.value();
+                            let separator = if value.is_empty() || value.ends_with(' ') {
+                                ""
+                            } else {
+                                " "
+                            };
+                            input.set_value(&format!("{}{}@{} ", value, separator, username));
+                        }
+                        self.sync_draft_len();
+                        self.focus_input();
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    fn changed(&mut self, ctx: &Context<Self>) -> bool {
+        let room = ctx.props().room.as_deref().and_then(room_name).unwrap_or_else(|| DEFAULT_ROOM.to_string());
+        if room == self.room {
+            return false;
+        }
+        self.switch_room(ctx, room);
+        true
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        if let Some(pane) = self.message_pane.cast::<web_sys::Element>() {
+            if first_render {
+                self.read_observer = VisibilityObserver::new(&pane, "data-read-id", ctx.link().callback(Msg::MessagesSeen));
+            }
+            if let Some(observer) = &self.read_observer {
+                observer.observe_marked(&pane);
+            }
+        }
+        if let Some(from_bottom) = self.keep_scroll.take() {
+            if let Some(pane) = self.message_pane.cast::<web_sys::Element>() {
+                pane.set_scroll_top(pane.scroll_height() - from_bottom);
+            }
+        } else if std::mem::take(&mut self.scroll_to_bottom) || first_render {
+            if let Some(pane) = self.message_pane.cast::<web_sys::Element>() {
+                pane.set_scroll_top(pane.scroll_height());
+            }
+            self.scrolled_up = false;
+            self.new_below = 0;
+        }
+        self.measure_rows(ctx);
+        if let Some(key) = self.scroll_to.take() {
+            self.scroll_to_row(&current_username(ctx), &key);
+        }
+        // The divider may be in view without any scrolling, e.g. when few
+        // messages came in.
+        if !self.unread_divider_seen && self.unread_divider_position() == Some(DividerPosition::InView) {
+            ctx.link().send_message(Msg::PaneScrolled);
+        }
+        if std::mem::take(&mut self.focus_search) {
+            if let Some(input) = self.search_input.cast::<HtmlInputElement>() {
+                let _ = input.focus();
+            }
+        }
+        if std::mem::take(&mut self.focus_edit) {
+            if let Some(input) = self.edit_input.cast::<HtmlInputElement>() {
+                let _ = input.focus();
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let submit = ctx.link().callback(|_| Msg::SubmitMessage);
+        // Shift+Enter is left alone for multi-line input later on.
+        let onkeypress = ctx.link().batch_callback(|e: KeyboardEvent| {
+            if e.key() == "Enter" && !e.shift_key() {
+                e.prevent_default();
+                Some(Msg::SubmitMessage)
+            } else {
+                None
+            }
+        });
+        // Slow mode only limits messages to the room.
+        let cooldown = match self.conversation {
+            Conversation::Room => self.cooldown_remaining(),
+            Conversation::Notes | Conversation::Direct(_) => 0,
+        };
+        let offline = self.is_offline();
+        let placeholder = match &self.conversation {
+            _ if offline => "You're offline. Messages can't be sent right now.".to_string(),
+            Conversation::Room => "Type your message and hit Enter 🚀".to_string(),
+            Conversation::Notes => "Write a note to yourself 📝".to_string(),
+            Conversation::Direct(peer) => format!("Message {} privately 🤫", peer),
+        };
+        let on_large_paste = ctx.link().callback(Msg::LargePaste);
+        let onpaste = Callback::from(move |e: Event| {
+            // `ClipboardEvent` is behind web-sys' unstable APIs, so read its
+            // `clipboardData` reflectively.
+            let text = js_sys::Reflect::get(&e, &"clipboardData".into())
+                .ok()
+                .and_then(|data| data.dyn_into::<DataTransfer>().ok())
+                .and_then(|data| data.get_data("text").ok())
+                .unwrap_or_default();
+            if text.chars().count() > LARGE_PASTE_THRESHOLD {
+                e.prevent_default();
+                on_large_paste.emit(text);
+            }
+        });
+        let toggle_settings = ctx.link().callback(|_| Msg::ToggleSettings);
+        let toggle_emoji_picker = ctx.link().callback(|_| Msg::ToggleEmojiPicker);
+        let toggle_avatar_editor = ctx.link().callback(|_| Msg::ToggleAvatarEditor);
+        let pick_file = ctx.link().batch_callback(|e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let file = input.files().and_then(|files| files.get(0));
+            input.set_value("");
+            file.map(|file| Msg::PickFile(File::from(file)))
+        });
+        // Dropping a file sends it like the upload button; cancelling
+        // `dragover` is what allows the drop. Notes can't hold files.
+        let accepts_drop = self.conversation != Conversation::Notes;
+        let ondragover = ctx.link().batch_callback(move |e: DragEvent| {
+            accepts_drop.then(|| {
+                e.prevent_default();
+                Msg::SetDragging(true)
+            })
+        });
+        let ondragleave = ctx.link().callback(|_: DragEvent| Msg::SetDragging(false));
+        let ondrop = ctx.link().batch_callback(|e: DragEvent| {
+            e.prevent_default();
+            let file = e.data_transfer().and_then(|data| data.files()).and_then(|files| files.get(0));
+            vec![
+                Msg::SetDragging(false),
+                file.map_or(Msg::DroppedNonFile, |file| Msg::PickFile(File::from(file))),
+            ]
+        });
+        let update_preferences = ctx.link().callback(Msg::UpdatePreferences);
+        let close_settings = ctx.link().callback(|_| Msg::ToggleSettings);
+        let lazy_avatars =
+            self.preferences.lazy_avatars && self.users.len() > LAZY_AVATAR_THRESHOLD;
+
+        let oninput = ctx.link().callback(|_: InputEvent| Msg::InputChanged);
+
+        // Get current user for message bubble coloring
+        let current_username = current_username(ctx);
+
+        let page = html! {
+            <div class="flex w-screen">
+                <div class="flex-none w-56 h-screen overflow-y-auto bg-gradient-to-b from-gray-100 to-blue-100 dark:from-gray-900 dark:to-gray-800 dark:text-gray-100">
+                    <div class="text-xl p-3 flex flex-col gap-2">
+                        <div class="flex justify-between items-center">
+                            {"Users"}
+                            <div class="flex gap-2">
+                                <button onclick={toggle_avatar_editor} class="text-base text-gray-500 hover:text-gray-700" title="Edit avatar" aria-label="Edit avatar">{"🖼️"}</button>
+                                <button onclick={toggle_settings} class="text-base text-gray-500 hover:text-gray-700" title="Settings" aria-label="Settings">{"⚙️"}</button>
+                                <button onclick={ctx.link().callback(|_| Msg::Logout)} class="text-base text-gray-500 hover:text-gray-700" title="Log out" aria-label="Log out">{"🚪"}</button>
+                            </div>
+                        </div>
+                        <span class="text-xs text-blue-600">{"🌟 Active Now"}</span>
+                    </div>
+                    {
+                        if self.show_avatar_editor {
+                            self.view_avatar_editor(ctx)
+                        } else {
+                            html! {}
+                        }
+                    }
+                    {
+                        if self.show_settings {
+                            html! {
+                                <Settings
+                                    preferences={self.preferences.clone()}
+                                    on_change={update_preferences}
+                                    on_close={close_settings}
+                                />
+                            }
+                        } else {
+                            html! {}
+                        }
+                    }
+                    {self.view_rooms(ctx)}
+                    {self.view_bookmarks(ctx)}
+                    {
+                        {
+                            let open = ctx.link().callback(|_| Msg::OpenConversation(Conversation::Notes));
+                            let active = if self.conversation == Conversation::Notes {
+                                "bg-blue-200 dark:bg-blue-900"
+                            } else {
+                                "bg-white hover:bg-blue-50 dark:bg-gray-700 dark:hover:bg-gray-600"
+                            };
+                            html! {
+                                <button onclick={open} class={format!("flex w-auto mx-3 my-2 rounded-lg px-3 py-2 shadow-sm items-center gap-2 text-sm font-semibold {}", active)}>
+                                    {"📌 Saved Messages"}
+                                </button>
+                            }
+                        }
+                    }
+                    <div role="list" aria-label="Users" aria-busy={(!self.roster_loaded).to_string()}>
+                        if !self.roster_loaded {
+                            {view_roster_skeleton()}
+                        }
+                        {
+                            self.roster_with_leaving().into_iter().map(|(u, leaving)| {
+                                let transition = if leaving {
+                                    "roster-leave"
+                                } else if self.entering.contains(&u.name) {
+                                    "roster-enter"
                                 } else {
-                                    "text-gray-700"
+                                    ""
                                 };
-                                html!{
-                                    <div class={format!("flex items-end w-3/6 m-4 rounded-tl-lg rounded-tr-lg rounded-br-lg shadow-sm {}", if is_me { "flex-row-reverse" } else { "" })}>
-                                        <img class="w-8 h-8 rounded-full m-3 border border-blue-100" src={avatar} alt="avatar"/>
-                                        <div class={format!("p-3 rounded-lg {}", bubble_class)}>
-                                            <div class={format!("text-sm font-bold {}", text_class)}>
-                                                {if is_me { "You".to_string() } else { m.from.clone() }}
-                                            </div>
-                                            <div class={format!("text-xs mt-1 {}", text_class)}>
-                                                {
-                                                    if m.message.ends_with(".gif") {
-                                                        html!{ <img class="mt-3 rounded" src={m.message.clone()}/> }
-                                                    } else {
-                                                        html!{ <span>{m.message.clone()}</span> }
-                                                    }
+                                self.view_roster_entry(ctx, u, transition, lazy_avatars)
+                            }).collect::<Html>()
+                        }
+                    </div>
+                </div>
+                <div {ondragover} class="relative grow h-screen flex flex-col dark:text-gray-100" style={match self.theme {
+                    Theme::Light => "background: repeating-linear-gradient(135deg,#f0f4ff 0 20px,#e9e9f9 20px 40px);",
+                    Theme::Dark => "background: repeating-linear-gradient(135deg,#111827 0 20px,#1a2233 20px 40px);",
+                }}>
+                    // The overlay takes the drag events while it is up, so
+                    // leaving it means leaving the pane.
+                    if self.dragging && accepts_drop {
+                        <div {ondragleave} {ondrop} class="absolute inset-0 z-40 m-4 flex items-center justify-center rounded-xl border-4 border-dashed border-blue-400 bg-white bg-opacity-80 text-xl text-blue-600 dark:bg-gray-900 dark:bg-opacity-80 dark:text-blue-300">
+                            {"Drop to send 📎"}
+                        </div>
+                    }
+                    <div class="w-full h-14 border-b-2 border-gray-300 flex items-center justify-between">
+                        {
+                            match &self.conversation {
+                                Conversation::Room => html! {
+                                    <div class="text-xl p-3 flex items-center gap-2">
+                                        {format!("💬 #{}", self.room)}
+                                        {
+                                            if self.unread > 0 {
+                                                html! {
+                                                    <span class="px-2 rounded-full bg-red-500 text-white text-xs font-bold" title="Unread messages" aria-label={format!("{} unread", self.unread)}>
+                                                        {self.unread}
+                                                    </span>
                                                 }
-                                            </div>
-                                        </div>
+                                            } else {
+                                                html! {}
+                                            }
+                                        }
+                                        <span class="ml-2 text-sm text-blue-400 animate-bounce">{"Welcome to the fun zone! 🎉"}</span>
                                     </div>
+                                },
+                                Conversation::Notes => {
+                                    let back = ctx.link().callback(|_| Msg::OpenConversation(Conversation::Room));
+                                    html! {
+                                        <div class="text-xl p-3 flex items-center gap-2">
+                                            <button onclick={back} class="text-sm text-blue-600 hover:underline">{"← Back to chat"}</button>
+                                            {"📌 Saved Messages"}
+                                            <span class="ml-2 text-xs text-gray-400 italic">{"Only visible to you, stored in this browser"}</span>
+                                        </div>
+                                    }
                                 }
-                            }).collect::<Html>()
+                                Conversation::Direct(peer) => {
+                                    let back = ctx.link().callback(|_| Msg::OpenConversation(Conversation::Room));
+                                    html! {
+                                        <div class="text-xl p-3 flex items-center gap-2">
+                                            <button onclick={back} class="text-sm text-blue-600 hover:underline">{"← Back to chat"}</button>
+                                            {format!("✉️ {}", peer)}
+                                            <span class="ml-2 text-xs text-gray-400 italic">{"Direct message"}</span>
+                                        </div>
+                                    }
+                                }
+                            }
+                        }
+                        <div class="mr-4 flex items-center gap-2">
+                            {connection_pill(self.connection)}
+                            if self.show_search {
+                                {self.view_search(ctx, &current_username)}
+                            } else {
+                                <div class="text-xs text-gray-500 italic dark:text-gray-400">{"Tip: Try sending an emoji or a .gif URL!"}</div>
+                            }
+                            if self.conversation != Conversation::Notes {
+                                {self.view_export_menu(ctx)}
+                                <button onclick={ctx.link().callback(|_| Msg::ToggleSearch)} class="text-base text-gray-500 hover:text-gray-700"
+                                    title={if self.show_search { "Close search" } else { "Search messages" }}
+                                    aria-label={if self.show_search { "Close search" } else { "Search messages" }}>
+                                    {if self.show_search { "✖️" } else { "🔍" }}
+                                </button>
+                            }
+                            <button onclick={ctx.link().callback(|_| Msg::ToggleTheme)} class="text-base text-gray-500 hover:text-gray-700"
+                                title={if self.theme == Theme::Dark { "Switch to light mode" } else { "Switch to dark mode" }}
+                                aria-label={if self.theme == Theme::Dark { "Switch to light mode" } else { "Switch to dark mode" }}>
+                                {if self.theme == Theme::Dark { "☀️" } else { "🌙" }}
+                            </button>
+                        </div>
+                    </div>
+                    {
+                        match (connection_notice(self.connection, self.roster_refill.shown()), self.preferences.connection_notice) {
+                            (None, _) | (_, ConnectionNotice::Silent) => html! {},
+                            (Some(notice), ConnectionNotice::Banner) => html! {
+                                <div role={notice.role} class={classes!("w-full", "px-3", "py-1", "text-xs", "text-center", notice.class)}>
+                                    {notice.text}
+                                </div>
+                            },
+                            (Some(notice), ConnectionNotice::Corner) => html! {
+                                <div role={notice.role} title={notice.text}
+                                    class={classes!("fixed", "bottom-20", "right-4", "z-20", "px-2", "py-1", "rounded-full", "shadow", "text-xs", notice.class)}>
+                                    {notice.short}
+                                </div>
+                            },
+                        }
+                    }
+                    <div ref={self.message_pane.clone()} onscroll={ctx.link().callback(|_| Msg::PaneScrolled)}
+                        role="log" aria-live="polite" aria-label="Messages" class="relative w-full grow overflow-auto border-b-2 border-gray-300 px-2 py-4">
+                        if !self.unread_divider_seen && self.unread_divider_row(&current_username).is_some() {
+                            <div class="sticky top-2 z-10 h-0 flex justify-center">
+                                <button onclick={ctx.link().callback(|_| Msg::JumpToUnread)} class="h-6 px-3 rounded-full shadow bg-red-500 text-white text-xs hover:bg-red-600">
+                                    {"↑ New messages"}
+                                </button>
+                            </div>
+                        }
+                        {
+                            match &self.conversation {
+                                Conversation::Room => self.view_room(ctx, &current_username),
+                                Conversation::Notes => self.view_notes(ctx),
+                                Conversation::Direct(peer) => self.view_direct(ctx, &current_username, peer),
+                            }
+                        }
+                        if self.scrolled_up {
+                            <div class="sticky bottom-2 flex justify-center">
+                                <button onclick={ctx.link().callback(|_| Msg::JumpToLatest)} class="px-3 py-1 rounded-full shadow bg-blue-600 text-white text-xs hover:bg-blue-700">
+                                    {match self.new_below {
+                                        0 => "↓ Jump to latest".to_string(),
+                                        1 => "↓ 1 new message".to_string(),
+                                        n => format!("↓ {} new messages", n),
+                                    }}
+                                </button>
+                            </div>
                         }
                     </div>
-                    <div class="w-full h-18 flex flex-col px-3 py-2 items-center bg-white bg-opacity-80">
+                    <div class="w-full h-18 flex flex-col px-3 py-2 items-center bg-white bg-opacity-80 dark:bg-gray-800 dark:bg-opacity-80">
+                        {
+                            if let Some(text) = &self.pending_paste {
+                                let snippet = ctx.link().callback(|_| Msg::SendSnippet);
+                                let inline = ctx.link().callback(|_| Msg::PasteInline);
+                                let cancel = ctx.link().callback(|_| Msg::CancelPaste);
+                                html! {
+                                    <div class="w-full mb-2 px-3 py-2 rounded-lg bg-amber-100 text-xs text-amber-900 flex items-center gap-2">
+                                        <span class="grow">
+                                            {format!("That's a lot of text ({} characters). Send it as a snippet instead?", text.chars().count())}
+                                        </span>
+                                        <button onclick={snippet} class="px-2 py-1 rounded bg-amber-600 text-white hover:bg-amber-700">{"Send as snippet"}</button>
+                                        <button onclick={inline} class="px-2 py-1 rounded hover:bg-amber-200">{"Paste anyway"}</button>
+                                        <button onclick={cancel} class="px-2 py-1 rounded hover:bg-amber-200">{"Cancel"}</button>
+                                    </div>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        {
+                            match typing_label(&self.typing.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>()) {
+                                Some(label) => html! {
+                                    <div role="status" class="w-full px-4 pb-1 text-xs text-gray-500 italic">{label}</div>
+                                },
+                                None => html! {},
+                            }
+                        }
+                        if self.too_long_warning {
+                            <div role="alert" class="w-full mb-2 px-3 py-2 rounded-lg bg-red-100 text-xs text-red-900">
+                                {format!(
+                                    "Messages can be at most {} characters. Remove {} to send this one.",
+                                    self.max_length,
+                                    self.draft_len.saturating_sub(self.max_length),
+                                )}
+                            </div>
+                        }
+                        if self.rate_limit_warning.is_some() {
+                            <div role="status" class="w-full mb-2 px-3 py-2 rounded-lg bg-amber-100 text-xs text-amber-900">
+                                {"Slow down a little! 😅"}
+                            </div>
+                        }
+                        {
+                            if let Some(error) = &self.upload_error {
+                                let dismiss = ctx.link().callback(|_| Msg::DismissUploadError);
+                                html! {
+                                    <div role="alert" class="w-full mb-2 px-3 py-2 rounded-lg bg-red-100 text-xs text-red-900 flex items-center gap-2">
+                                        <span class="grow">{error.clone()}</span>
+                                        <button onclick={dismiss} class="px-2 py-1 rounded hover:bg-red-200">{"Dismiss"}</button>
+                                    </div>
+                                }
+                            } else {
+                                html! {}
+                            }
+                        }
+                        {
+                            match self.replying_to.as_ref().and_then(|r| Some((self.find_message(&r.id)?, r.quote.as_deref()))) {
+                                Some((parent, quote)) => html! {
+                                    <div class="w-full mb-2 px-3 py-2 rounded-lg bg-blue-50 text-xs text-blue-900 flex items-center gap-2 dark:bg-gray-700 dark:text-gray-100">
+                                        <span class="grow truncate">
+                                            {"↩️ Replying to "}
+                                            <span class="font-semibold">{parent.from.clone()}</span>
+                                            {format!(": {}", self.quote_text(parent, quote))}
+                                        </span>
+                                        <button onclick={ctx.link().callback(|_| Msg::CancelReply)} title="Cancel reply" aria-label="Cancel reply"
+                                            class="px-2 py-1 rounded hover:bg-blue-100 dark:hover:bg-gray-600">{"✕"}</button>
+                                    </div>
+                                },
+                                None => html! {},
+                            }
+                        }
+                        <FormattingToolbar input={self.chat_input.clone()} />
                         <div class="w-full flex items-center">
-                            <input ref={self.chat_input.clone()} type="text" placeholder="Type your message and hit Enter 🚀" class="block w-full py-2 pl-4 mx-3 bg-gray-100 rounded-full outline-none focus:text-gray-700" name="message" required=true />
-                            <button onclick={submit} class="p-3 shadow-sm bg-blue-600 w-10 h-10 rounded-full flex justify-center items-center color-white hover:bg-blue-700 transition">
-                                <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-white">
-                                    <path d="M0 0h24v24H0z" fill="none"></path><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"></path>
-                                </svg>
-                            </button>
+                            <input ref={self.chat_input.clone()} {oninput} {onpaste} {onkeypress} type="text" {placeholder} aria-label="Message" class="block w-full py-2 pl-4 mx-3 bg-gray-100 rounded-full outline-none focus:text-gray-700 dark:bg-gray-700 dark:text-gray-100 dark:focus:text-gray-100" name="message" required=true />
+                            if self.draft_len > 0 {
+                                <span class={classes!("flex-none", "mr-2", "text-xs", "tabular-nums", self.length_counter_color())}
+                                    aria-label={format!("{} of {} characters", self.draft_len, self.max_length)}>
+                                    {format!("{}/{}", self.draft_len, self.max_length)}
+                                </span>
+                            }
+                            if self.conversation != Conversation::Notes {
+                                <label title={if self.upload.is_some() { "Uploading…" } else { "Upload a file" }}
+                                    class="w-10 h-10 flex-none rounded-full text-xl flex justify-center items-center cursor-pointer hover:bg-gray-200 focus-within:ring-2 focus-within:ring-blue-400 transition">
+                                    <span aria-hidden="true">{if self.upload.is_some() { "⏳" } else { "📎" }}</span>
+                                    // Visually hidden rather than `hidden`, so the picker stays in
+                                    // the tab order.
+                                    <input type="file" class="sr-only" onchange={pick_file} disabled={self.upload.is_some()}
+                                        aria-label={if self.upload.is_some() { "Uploading…" } else { "Upload a file" }} />
+                                </label>
+                            }
+                            <div class="relative mr-2">
+                                <button type="button" onclick={toggle_emoji_picker} title="Emoji" aria-label="Insert emoji" aria-expanded={self.show_emoji_picker.to_string()} class="w-10 h-10 rounded-full text-xl hover:bg-gray-200 transition">{"😊"}</button>
+                                {
+                                    if self.show_emoji_picker {
+                                        let on_pick = ctx.link().callback(Msg::InsertEmoji);
+                                        let close = ctx.link().callback(|_| Msg::ToggleEmojiPicker);
+                                        html! { <EmojiPicker {on_pick} on_close={close} /> }
+                                    } else {
+                                        html! {}
+                                    }
+                                }
+                            </div>
+                            {
+                                if offline {
+                                    html! {
+                                        <button type="button" disabled=true title="Offline" aria-label="Send message (offline)" class="p-3 shadow-sm bg-gray-400 w-10 h-10 rounded-full flex justify-center items-center cursor-not-allowed">
+                                            <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-white" aria-hidden="true">
+                                                <path d="M0 0h24v24H0z" fill="none"></path><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"></path>
+                                            </svg>
+                                        </button>
+                                    }
+                                } else if cooldown > 0 {
+                                    html! {
+                                        <button type="button" disabled=true title="Slow mode is on"
+                                            aria-label={format!("Send message (slow mode, {}s left)", cooldown)} class="p-3 shadow-sm bg-gray-400 w-10 h-10 rounded-full flex justify-center items-center text-xs font-bold text-white cursor-not-allowed">
+                                            {format!("{}s", cooldown)}
+                                        </button>
+                                    }
+                                } else {
+                                    html! {
+                                        <button type="button" onclick={submit} title="Send" aria-label="Send message"
+                                            class="p-3 shadow-sm bg-blue-600 w-10 h-10 rounded-full flex justify-center items-center color-white hover:bg-blue-700 focus:outline-none focus:ring-2 focus:ring-blue-400 transition">
+                                            <svg viewBox="0 0 24 24" xmlns="http://www.w3.org/2000/svg" class="fill-white" aria-hidden="true">
+                                                <path d="M0 0h24v24H0z" fill="none"></path><path d="M2.01 21L23 12 2.01 3 2 10l15 2-15 2z"></path>
+                                            </svg>
+                                        </button>
+                                    }
+                                }
+                            }
                         </div>
                         <div class="w-full text-xs text-gray-400 mt-1 text-center italic">
                             {"💡 Tip of the Day: Spread kindness, one message at a time!"}
                         </div>
                     </div>
                 </div>
+                {
+                    if let Some(src) = &self.lightbox {
+                        let close = ctx.link().callback(|_| Msg::CloseLightbox);
+                        html! {
+                            <div onclick={close} role="dialog" aria-modal="true" aria-label="Image preview" class="fixed inset-0 z-50 overflow-auto bg-black bg-opacity-75 flex cursor-zoom-out">
+                                <img class="m-auto" src={src.clone()} alt="full size image"/>
+                            </div>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                {
+                    if self.show_shortcuts {
+                        let close = ctx.link().callback(|_| Msg::Shortcut(Action::CloseOverlay));
+                        html! { <ShortcutsHelp on_close={close} /> }
+                    } else {
+                        html! {}
+                    }
+                }
+                {
+                    if self.show_palette {
+                        let items = palette::actions()
+                            .into_iter()
+                            .chain(self.users.iter().map(|u| palette::mention(&u.name)))
+                            .collect::<Vec<_>>();
+                        let on_select = ctx.link().callback(Msg::RunCommand);
+                        let close = ctx.link().callback(|_| Msg::Shortcut(Action::CloseOverlay));
+                        html! { <CommandPalette {items} {on_select} on_close={close} /> }
+                    } else {
+                        html! {}
+                    }
+                }
+            </div>
+        };
+        html! {
+            <ContextProvider<Clock> context={self.clock.clone()}>
+                {page}
+            </ContextProvider<Clock>>
+        }
+    }
+}
+
+impl Chat {
+    /// The current roster with users who just left spliced back in at their
+    /// old positions, each flagged with whether it is leaving.
+    fn roster_with_leaving(&self) -> Vec<(&UserProfile, bool)> {
+        let mut roster: Vec<(&UserProfile, bool)> = self.users.iter().map(|u| (u, false)).collect();
+        for (index, u) in &self.leaving {
+            // A user who left and came straight back is shown once.
+            if !self.users.iter().any(|current| current.name == u.name) {
+                roster.insert((*index).min(roster.len()), (u, true));
+            }
+        }
+        roster
+    }
+
+    fn view_avatar_editor(&self, ctx: &Context<Self>) -> Html {
+        let current = ctx
+            .link()
+            .context::<User>(Callback::noop())
+            .map(|(u, _)| u.avatar.borrow().clone())
+            .unwrap_or_default();
+        let input = self.avatar_input.clone();
+        let save = ctx.link().batch_callback(move |_| {
+            input
+                .cast::<HtmlInputElement>()
+                .map(|input| Msg::SetAvatar(input.value()))
+        });
+        let reset = ctx.link().callback(|_| Msg::SetAvatar(String::new()));
+        let close = ctx.link().callback(|_| Msg::ToggleAvatarEditor);
+        html! {
+            <div class="m-3 p-3 bg-white rounded-lg shadow-sm text-sm dark:bg-gray-800">
+                <div class="flex justify-between items-center mb-2">
+                    <span class="font-semibold">{"Avatar"}</span>
+                    <button onclick={close} class="text-gray-400 hover:text-gray-600">{"✕"}</button>
+                </div>
+                <input ref={self.avatar_input.clone()} type="url" value={current} placeholder="https://example.com/me.png"
+                    class="w-full px-1 border rounded" />
+                <div class="flex gap-2 mt-2">
+                    <button onclick={save} class="px-2 py-1 rounded bg-gray-100 hover:bg-gray-200">{"Save"}</button>
+                    <button onclick={reset} class="px-2 py-1 rounded bg-gray-100 hover:bg-gray-200">{"Use default"}</button>
+                </div>
+            </div>
+        }
+    }
+
+    /// The room list in the sidebar, with unread counts and a field to join
+    /// another room.
+    fn view_rooms(&self, ctx: &Context<Self>) -> Html {
+        let onkeydown = ctx.link().batch_callback(|e: KeyboardEvent| {
+            if e.key() != "Enter" {
+                return None;
+            }
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let room = input.value();
+            input.set_value("");
+            Some(Msg::JoinRoom(room))
+        });
+        html! {
+            <div class="mx-3 my-2">
+                <div class="px-1 mb-1 text-xs font-semibold uppercase text-gray-400">{"Rooms"}</div>
+                {
+                    for self.rooms.iter().map(|room| {
+                        let active = self.conversation == Conversation::Room && *room == self.room;
+                        let class = if active {
+                            "bg-blue-200 dark:bg-blue-900"
+                        } else {
+                            "hover:bg-blue-50 dark:hover:bg-gray-600"
+                        };
+                        let name = room.clone();
+                        let open = ctx.link().callback(move |_| Msg::OpenRoom(name.clone()));
+                        let unread = self.room_unread.get(room).copied().unwrap_or(0);
+                        html! {
+                            <div class={format!("group flex items-center rounded-lg text-sm {}", class)}>
+                                <button onclick={open} class="grow flex items-center gap-2 px-2 py-1 text-left">
+                                    <span class="text-gray-400">{"#"}</span>
+                                    <span class={if unread > 0 { "font-bold" } else { "" }}>{room.clone()}</span>
+                                    if unread > 0 {
+                                        <span class="ml-auto px-2 rounded-full bg-red-500 text-white text-xs font-bold" aria-label={format!("{} unread", unread)}>{unread}</span>
+                                    }
+                                </button>
+                                if room != DEFAULT_ROOM {
+                                    <button onclick={ctx.link().callback({ let room = room.clone(); move |_| Msg::LeaveRoom(room.clone()) })}
+                                        title="Leave room" aria-label={format!("Leave #{}", room)}
+                                        class="opacity-0 group-hover:opacity-100 focus:opacity-100 px-2 text-gray-400 hover:text-red-600">{"×"}</button>
+                                }
+                            </div>
+                        }
+                    })
+                }
+                <input {onkeydown} type="text" placeholder="Join a room…" aria-label="Join a room"
+                    class="w-full mt-1 px-2 py-1 rounded-lg bg-white text-sm outline-none dark:bg-gray-700" />
+            </div>
+        }
+    }
+
+    fn view_export_menu(&self, ctx: &Context<Self>) -> Html {
+        html! {
+            <div class="relative">
+                <button onclick={ctx.link().callback(|_| Msg::ToggleExportMenu)} class="text-base text-gray-500 hover:text-gray-700"
+                    title="Export conversation" aria-label="Export conversation" aria-haspopup="menu" aria-expanded={self.show_export_menu.to_string()}>
+                    {"💾"}
+                </button>
+                if self.show_export_menu {
+                    <div role="menu" class="absolute right-0 top-8 z-40 w-36 py-1 rounded-lg shadow-lg bg-white text-sm dark:bg-gray-800">
+                        { for [transcript::Format::Text, transcript::Format::Json].into_iter().map(|format| html! {
+                            <button role="menuitem" onclick={ctx.link().callback(move |_| Msg::ExportTranscript(format))}
+                                class="block w-full px-3 py-1 text-left hover:bg-gray-100 dark:hover:bg-gray-700">
+                                {format.label()}
+                            </button>
+                        }) }
+                    </div>
+                }
             </div>
         }
     }
+
+    fn view_roster_entry(&self, ctx: &Context<Self>, u: &UserProfile, transition: &str, lazy_avatars: bool) -> Html {
+        // Clicking someone else opens a direct conversation with them.
+        let onclick = (u.name != current_username(ctx)).then(|| {
+            let name = u.name.clone();
+            ctx.link().callback(move |_| Msg::OpenConversation(Conversation::Direct(name.clone())))
+        });
+        // Those are focusable too, and open on Enter or Space like a button.
+        let tabindex = onclick.is_some().then_some("0");
+        let onkeydown = onclick.is_some().then(|| {
+            let name = u.name.clone();
+            ctx.link().batch_callback(move |e: KeyboardEvent| {
+                matches!(e.key().as_str(), "Enter" | " ").then(|| {
+                    e.prevent_default();
+                    Msg::OpenConversation(Conversation::Direct(name.clone()))
+                })
+            })
+        });
+        let label = onclick.is_some().then(|| format!("Message {}", u.name));
+        let mute = if onclick.is_some() && self.preferences.notify {
+            let muted = self.preferences.notif_muted.contains(&u.name);
+            let name = u.name.clone();
+            // Handled here, so the row doesn't open the conversation too.
+            let onclick = ctx.link().callback(move |e: MouseEvent| {
+                e.stop_propagation();
+                Msg::ToggleNotifMute(name.clone())
+            });
+            let onkeydown = Callback::from(|e: KeyboardEvent| e.stop_propagation());
+            let title = if muted {
+                format!("Notify me about {}'s messages", u.name)
+            } else {
+                format!("Don't notify me about {}'s messages", u.name)
+            };
+            let aria_label = title.clone();
+            html! {
+                <button {onclick} {onkeydown} {title} aria-label={aria_label} aria-pressed={muted.to_string()}
+                    class="flex-none ml-auto px-1 rounded text-sm text-gray-400 hover:bg-gray-200 dark:hover:bg-gray-600">
+                    {if muted { "🔕" } else { "🔔" }}
+                </button>
+            }
+        } else {
+            html! {}
+        };
+        let dimmed = if u.status == Presence::Offline { "opacity-50" } else { "" };
+        let background = if self.conversation == Conversation::Direct(u.name.clone()) {
+            "bg-blue-200 dark:bg-blue-900"
+        } else if onclick.is_some() {
+            "bg-white hover:bg-blue-50 cursor-pointer dark:bg-gray-700 dark:hover:bg-gray-600"
+        } else {
+            "bg-white dark:bg-gray-700"
+        };
+        // Compact mode keeps the same avatar wrapper so per-user
+        // decorations can be layered on in either layout.
+        if self.preferences.compact_roster {
+            html! {
+                <div key={u.name.clone()} role="listitem" {tabindex} {onclick} {onkeydown} aria-label={label}
+                    class={format!("flex mx-3 my-1 rounded-lg px-2 py-1 shadow-sm items-center gap-2 {} {} {}", background, dimmed, transition)}>
+                    <div class="relative flex-none">
+                        <Avatar name={u.name.clone()} src={u.avatar.clone()} class="w-6 h-6 border border-blue-200" lazy={lazy_avatars} />
+                        {presence_dot(u.status, "w-2 h-2")}
+                    </div>
+                    <div class="text-xs font-semibold truncate">{u.name.clone()}</div>
+                    {mute}
+                </div>
+            }
+        } else {
+            html! {
+                <div key={u.name.clone()} role="listitem" {tabindex} {onclick} {onkeydown} aria-label={label}
+                    class={format!("flex m-3 rounded-lg p-2 shadow-sm items-center {} {} {}", background, dimmed, transition)}>
+                    <div class="relative flex-none">
+                        <Avatar name={u.name.clone()} src={u.avatar.clone()} class="w-12 h-12 border-2 border-blue-200" lazy={lazy_avatars} />
+                        {presence_dot(u.status, "w-3 h-3")}
+                    </div>
+                    <div class="flex-grow p-3">
+                        <div class="flex text-xs justify-between">
+                            <div class="font-semibold">{u.name.clone()}</div>
+                        </div>
+                        <div class="text-xs text-gray-400 italic">
+                            {if u.status == Presence::Online { "Hi there! 👋" } else { u.status.label() }}
+                        </div>
+                    </div>
+                    {mute}
+                </div>
+            }
+        }
+    }
+
+    fn view_room(&self, ctx: &Context<Self>, current_username: &str) -> Html {
+        html! {
+            <>
+                {
+                    if let Some(secs) = self.room_capabilities().retention_secs {
+                        html! {
+                            <div class="text-center text-xs text-gray-400 italic mb-2">
+                                {format!("🕒 Messages disappear after {}", format_duration(secs))}
+                            </div>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                {
+                    if let Some(secs) = self.room_capabilities().slow_mode_secs.filter(|&secs| secs > 0) {
+                        html! {
+                            <div class="text-center text-xs text-gray-400 italic mb-2">
+                                {format!("🐢 Slow mode: one message every {}", format_duration(secs))}
+                            </div>
+                        }
+                    } else {
+                        html! {}
+                    }
+                }
+                if self.search.is_empty() && (self.history_more || self.hidden_room_messages(current_username) > 0) {
+                    <div class="flex justify-center mb-2">
+                        <button onclick={ctx.link().callback(|_| Msg::LoadEarlier)} disabled={self.loading_history}
+                            class="px-3 py-1 rounded-full bg-white text-xs text-blue-600 shadow-sm hover:bg-blue-50 disabled:text-gray-400 dark:bg-gray-700 dark:text-blue-300">
+                            {if self.loading_history { "Loading…" } else { "Load earlier messages" }}
+                        </button>
+                    </div>
+                }
+                if self.search.is_empty() && self.room_is_empty(current_username) {
+                    if self.connection == ConnectionState::Connecting {
+                        <div class="mt-16 flex flex-col items-center gap-3 animate-pulse" aria-busy="true">
+                            <div class="w-48 h-3 rounded-full bg-gray-300 dark:bg-gray-600"></div>
+                            <div class="w-32 h-3 rounded-full bg-gray-300 dark:bg-gray-600"></div>
+                            <div class="text-sm text-gray-400 italic">{"Loading messages…"}</div>
+                        </div>
+                    } else {
+                        <div class="mt-16 text-center text-lg text-gray-400">
+                            {"No messages yet — say hi! 👋"}
+                        </div>
+                    }
+                }
+                {self.view_matching(ctx, current_username)}
+            </>
+        }
+    }
+
+    /// Whether nobody has written in the open room yet, as far as we know.
+    /// Our own notices, like someone joining, don't count.
+    fn room_is_empty(&self, current_username: &str) -> bool {
+        !self.messages.iter().any(|m| !m.system && self.in_conversation(m, current_username))
+    }
+
+    fn view_direct(&self, ctx: &Context<Self>, current_username: &str, peer: &str) -> Html {
+        if !self.messages.iter().any(|m| self.in_conversation(m, current_username)) {
+            return html! {
+                <div class="text-center text-sm text-gray-400 italic mt-8">
+                    {format!("Only you and {} can see messages here.", peer)}
+                </div>
+            };
+        }
+        self.view_matching(ctx, current_username)
+    }
+
+    /// Whether `m` belongs to the open room or direct conversation.
+    fn in_conversation(&self, m: &MessageData, current_username: &str) -> bool {
+        match (&self.conversation, &m.to) {
+            (Conversation::Room, to) => to.is_none() && (m.announcement || room_of(m) == self.room),
+            (Conversation::Direct(peer), Some(to)) => {
+                (m.from == current_username && to == peer) || (m.from == *peer && to == current_username)
+            }
+            (Conversation::Direct(_), None) | (Conversation::Notes, _) => false,
+        }
+    }
+
+    /// The name of the open conversation and its messages, for exporting.
+    /// Deleted messages and our own notices are left out.
+    fn transcript(&self, current_username: &str) -> (String, Vec<transcript::Entry>) {
+        let title = match &self.conversation {
+            Conversation::Direct(peer) => peer.clone(),
+            Conversation::Room | Conversation::Notes => format!("#{}", self.room),
+        };
+        let entries = self
+            .messages
+            .iter()
+            .filter(|m| self.in_conversation(m, current_username) && !m.deleted && (!m.system || m.announcement))
+            .map(|m| transcript::Entry {
+                from: if m.announcement { "Server".to_string() } else { m.from.clone() },
+                message: match &m.file {
+                    Some(file) => format!("[file] {}", file.name),
+                    None => m.message.clone(),
+                },
+                time: m.time,
+            })
+            .collect();
+        (title, entries)
+    }
+
+    fn matches_search(&self, m: &MessageData) -> bool {
+        contains_ignore_case(&m.from, &self.search)
+            || (!m.deleted && contains_ignore_case(&m.message, &self.search))
+            || m.file.as_ref().map(|f| contains_ignore_case(&f.name, &self.search)).unwrap_or(false)
+    }
+
+    /// The open conversation's messages that match the search.
+    fn matching<'a>(&'a self, current_username: &'a str) -> impl Iterator<Item = (usize, &'a MessageData)> + 'a {
+        self.messages
+            .iter()
+            .enumerate()
+            .filter(move |(_, m)| self.in_conversation(m, current_username) && self.matches_search(m))
+    }
+
+    /// How many older room messages are held back until "Load earlier
+    /// messages" is clicked. Searching shows every match.
+    fn hidden_room_messages(&self, current_username: &str) -> usize {
+        if self.conversation != Conversation::Room || !self.search.is_empty() {
+            return 0;
+        }
+        self.matching(current_username).count().saturating_sub(self.room_shown)
+    }
+
+    fn view_matching(&self, ctx: &Context<Self>, current_username: &str) -> Html {
+        let hidden = self.hidden_room_messages(current_username);
+        let rows: Vec<(usize, &MessageData)> = self.matching(current_username).skip(hidden).collect();
+        if rows.is_empty() && !self.search.is_empty() {
+            return html! {
+                <div class="text-center text-sm text-gray-400 italic mt-8">
+                    {format!("No messages match \"{}\".", self.search)}
+                </div>
+            };
+        }
+        let (window, above, below) = self.visible_window(&rows);
+        let divider = self.unread_divider_row(current_username);
+        html! {
+            <>
+            <div ref={self.message_list.clone()}>
+                <div style={format!("height: {}px;", above)}></div>
+                <div>
+                    {
+                        for rows[window].iter().map(|&(index, m)| {
+                            html! {
+                                <div key={m.key.clone()} data-row-key={m.key.clone()} class="flow-root">
+                                    if divider == Some(index) {
+                                        <div ref={self.unread_divider_ref.clone()} role="separator" aria-label="New messages"
+                                            class="flex items-center gap-2 mx-4 mt-2 text-xs font-semibold text-red-500">
+                                            <div class="grow border-t border-red-300"></div>
+                                            {"New messages"}
+                                            <div class="grow border-t border-red-300"></div>
+                                        </div>
+                                    }
+                                    {self.view_bubble(ctx, m, current_username)}
+                                </div>
+                            }
+                        })
+                    }
+                </div>
+                <div style={format!("height: {}px;", below)}></div>
+            </div>
+            </>
+        }
+    }
+
+    /// Scrolls the pane so the row of the message with `key` is near its
+    /// top. Measured from the row heights, as virtualizing may keep the row
+    /// from being rendered.
+    fn scroll_to_row(&self, current_username: &str, key: &str) {
+        let above: i32 = self
+            .matching(current_username)
+            .skip(self.hidden_room_messages(current_username))
+            .take_while(|(_, m)| m.key != key)
+            .map(|(_, m)| self.row_heights.get(&m.key).copied().unwrap_or(ESTIMATED_ROW_PX))
+            .sum();
+        if let Some(pane) = self.message_pane.cast::<web_sys::Element>() {
+            pane.set_scroll_top(self.list_offset + above - SCROLL_TARGET_MARGIN_PX);
+        }
+    }
+
+    /// The index in `messages` of the message the "New messages" divider
+    /// goes above: the first new one from someone else in the open
+    /// conversation. When every shown message is new, that is the first.
+    fn unread_divider_row(&self, current_username: &str) -> Option<usize> {
+        let divider = self.unread_divider?;
+        if !self.search.is_empty() {
+            return None;
+        }
+        self.matching(current_username)
+            .skip(self.hidden_room_messages(current_username))
+            .find(|(index, m)| *index >= divider && m.from != current_username)
+            .map(|(index, _)| index)
+    }
+
+    /// Where the rendered divider is relative to the view, or `None` while
+    /// it isn't rendered or the user is away.
+    fn unread_divider_position(&self) -> Option<DividerPosition> {
+        if self.blurred_at.is_some() {
+            return None;
+        }
+        let divider = self.unread_divider_ref.cast::<web_sys::HtmlElement>()?;
+        let pane = self.message_pane.cast::<web_sys::Element>()?;
+        let top = divider.offset_top();
+        Some(if top + divider.offset_height() < pane.scroll_top() {
+            DividerPosition::Above
+        } else if top > pane.scroll_top() + pane.client_height() {
+            DividerPosition::Below
+        } else {
+            DividerPosition::InView
+        })
+    }
+
+    /// Marks the divider seen once it is in view, and removes it once it
+    /// has then been scrolled above the view. Returns whether either
+    /// happened.
+    fn track_unread_divider(&mut self) -> bool {
+        match self.unread_divider_position() {
+            Some(DividerPosition::InView) if !self.unread_divider_seen => {
+                self.unread_divider_seen = true;
+                true
+            }
+            Some(DividerPosition::Above) if self.unread_divider_seen => {
+                self.unread_divider = None;
+                self.unread_divider_seen = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// How many bubbles `view_matching` has to show.
+    fn shown_rows(&self, current_username: &str) -> usize {
+        self.matching(current_username).count() - self.hidden_room_messages(current_username)
+    }
+
+    /// Which of `rows` to render, and the heights of the space left for
+    /// the rows above and below them. Short conversations render every
+    /// row. Longer ones render the rows within `OVERSCAN_PX` of the view,
+    /// taken from the bottom while following new messages.
+    fn visible_window(&self, rows: &[(usize, &MessageData)]) -> (std::ops::Range<usize>, i32, i32) {
+        if rows.len() <= VIRTUALIZE_AFTER {
+            return (0..rows.len(), 0, 0);
+        }
+        let heights: Vec<i32> = rows
+            .iter()
+            .map(|(_, m)| self.row_heights.get(&m.key).copied().unwrap_or(ESTIMATED_ROW_PX))
+            .collect();
+        let total: i32 = heights.iter().sum();
+        let view_top = match self.keep_scroll {
+            Some(from_bottom) => total - from_bottom,
+            None if self.scroll_to_bottom || !self.scrolled_up => total - self.pane_height,
+            None => self.pane_scroll_top - self.list_offset,
+        };
+
+        let mut start = 0;
+        let mut y = 0;
+        while start < heights.len() && y + heights[start] <= view_top - OVERSCAN_PX {
+            y += heights[start];
+            start += 1;
+        }
+        let above = y;
+        let mut end = start;
+        while end < heights.len() && y < view_top + self.pane_height + OVERSCAN_PX {
+            y += heights[end];
+            end += 1;
+        }
+        (start..end, above, total - y)
+    }
+
+    /// Records the heights of the rendered bubbles and where the pane is
+    /// scrolled, so the next render picks the right window. Renders again
+    /// if the pane changed height, e.g. on the first render.
+    fn measure_rows(&mut self, ctx: &Context<Self>) {
+        let pane = match self.message_pane.cast::<web_sys::Element>() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let height = pane.client_height();
+        self.pane_scroll_top = pane.scroll_top();
+        let resized = std::mem::replace(&mut self.pane_height, height) != height;
+
+        let list = match self.message_list.cast::<web_sys::HtmlElement>() {
+            Some(list) => list,
+            None => return,
+        };
+        self.list_offset = list.offset_top();
+        if let Ok(rows) = list.query_selector_all("[data-row-key]") {
+            for i in 0..rows.length() {
+                let row = match rows.item(i).and_then(|node| node.dyn_into::<web_sys::HtmlElement>().ok()) {
+                    Some(row) => row,
+                    None => continue,
+                };
+                if let Some(key) = row.get_attribute("data-row-key") {
+                    self.row_heights.insert(key, row.offset_height());
+                }
+            }
+        }
+        if resized && self.shown_rows(&current_username(ctx)) > VIRTUALIZE_AFTER {
+            ctx.link().send_message(Msg::PaneScrolled);
+        }
+    }
+
+    fn view_search(&self, ctx: &Context<Self>, current_username: &str) -> Html {
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            Msg::SearchChanged(input.value())
+        });
+        let onkeydown = ctx.link().batch_callback(|e: KeyboardEvent| (e.key() == "Escape").then(|| Msg::ToggleSearch));
+        let count = self.matching(current_username).count();
+        html! {
+            <>
+                <input ref={self.search_input.clone()} {oninput} {onkeydown} value={self.search.clone()} type="search"
+                    placeholder="Search messages"
+                    class="w-48 px-3 py-1 rounded-full bg-white text-sm outline-none focus:text-gray-700 dark:bg-gray-700 dark:text-gray-100" />
+                if !self.search.is_empty() {
+                    <span class="text-xs text-gray-500">
+                        {format!("{} {}", count, if count == 1 { "match" } else { "matches" })}
+                    </span>
+                }
+            </>
+        }
+    }
+
+    fn view_bubble(&self, ctx: &Context<Self>, m: &MessageData, current_username: &str) -> Html {
+        if m.announcement {
+            return html! {
+                <div role="status" class="my-3 mx-2 px-4 py-2 rounded-lg border border-amber-300 bg-amber-100 text-center text-sm text-amber-900 dark:border-amber-700 dark:bg-amber-900 dark:text-amber-100">
+                    <span class="mr-1">{"📢"}</span>
+                    {highlight(&m.message, &self.search)}
+                    if let Some(time) = m.time {
+                        <RelativeTime {time} class="ml-2 text-[10px] text-amber-700 dark:text-amber-300" />
+                    }
+                </div>
+            };
+        }
+        if m.system {
+            return html! {
+                <div class="my-2 text-center text-xs text-gray-500 italic dark:text-gray-400">
+                    {highlight(&m.message, &self.search)}
+                    if let Some(time) = m.time {
+                        <RelativeTime {time} class="ml-1 text-[10px] text-gray-400" />
+                    }
+                </div>
+            };
+        }
+        let user = self.users.iter().find(|u| u.name == m.from);
+        let avatar = user.map(|u| u.avatar.clone()).unwrap_or_else(|| "https://cdn-icons-png.flaticon.com/512/4712/4712035.png".to_string());
+        let is_me = m.from == current_username;
+        let bubble_class = if is_me {
+            "bg-blue-200 text-right ml-auto dark:bg-blue-900"
+        } else {
+            "bg-gray-100 dark:bg-gray-700"
+        };
+        let text_class = if is_me {
+            "text-blue-900 dark:text-blue-100"
+        } else {
+            "text-gray-700 dark:text-gray-200"
+        };
+        let mention_class = if !is_me && !m.deleted && mentions(&m.message, current_username) {
+            "border-l-4 border-yellow-400"
+        } else {
+            ""
+        };
+        let outgoing = self.outbox.showing(m.id.as_deref()).filter(|_| is_me);
+        // Until the server has the message, it has no id others know it by.
+        let confirmed = outgoing.is_none_or(|o| o.state == OutgoingState::Sent);
+        // Own messages with an id can be edited with a double click, or
+        // deleted from the trash button shown on hover. Others' get the
+        // quick reaction instead.
+        let editable_id = m.id.clone().filter(|_| is_me && !m.deleted && confirmed);
+        let ondblclick = match (&editable_id, &m.id) {
+            (Some(id), _) => {
+                let id = id.clone();
+                Some(ctx.link().callback(move |_| Msg::StartEdit(id.clone())))
+            }
+            (None, Some(id)) if !is_me && !m.deleted => {
+                let id = id.clone();
+                Some(ctx.link().callback(move |_| Msg::QuickReact(id.clone())))
+            }
+            _ => None,
+        };
+        let body = if editable_id.is_some() && editable_id == self.editing {
+            let onkeydown = ctx.link().batch_callback(|e: KeyboardEvent| {
+                if e.key() == "Enter" {
+                    e.prevent_default();
+                    Some(Msg::SubmitEdit)
+                } else {
+                    None
+                }
+            });
+            html! {
+                <input ref={self.edit_input.clone()} {onkeydown} type="text" value={m.message.clone()}
+                    title="Enter to save, Esc to cancel"
+                    class="w-full px-2 py-1 rounded bg-white text-gray-700 outline-none" />
+            }
+        } else if m.deleted {
+            html! { <span class="italic text-gray-400">{"This message was deleted"}</span> }
+        } else if let Some(file) = &m.file {
+            self.view_attachment(ctx, file)
+        } else {
+            let highlights = Highlights {
+                query: &self.search,
+                me: current_username,
+                mention_links: Some(&self.mention_links),
+            };
+            let text = self.preferences.filtered(&m.message);
+            self.view_message_body(ctx, m.key.clone(), &text, &highlights)
+        };
+        let delete = editable_id.clone().map(|id| {
+            let onclick = ctx.link().callback(move |_| Msg::DeleteMessage(id.clone()));
+            html! {
+                <button {onclick} title="Delete message" aria-label="Delete message" class="opacity-0 group-hover:opacity-100 focus:opacity-100 self-center mx-1 text-gray-400 hover:text-red-600">{"🗑️"}</button>
+            }
+        }).unwrap_or_default();
+        let reply = match &m.id {
+            Some(id) if !m.deleted && confirmed => {
+                let id = id.clone();
+                let onclick = ctx.link().callback(move |_| Msg::Reply(id.clone()));
+                // Keeps the click from clearing a selection to quote.
+                let onmousedown = Callback::from(|e: MouseEvent| e.prevent_default());
+                html! {
+                    <button {onclick} {onmousedown} title="Reply" aria-label="Reply" class="opacity-0 group-hover:opacity-100 focus:opacity-100 self-center mx-1 text-gray-400 hover:text-blue-600">{"↩️"}</button>
+                }
+            }
+            _ => html! {},
+        };
+        let reply_privately = match &m.id {
+            Some(id) if !m.deleted && m.to.is_none() && m.from != current_username => {
+                let id = id.clone();
+                let onclick = ctx.link().callback(move |_| Msg::ReplyPrivately(id.clone()));
+                let onmousedown = Callback::from(|e: MouseEvent| e.prevent_default());
+                html! {
+                    <button {onclick} {onmousedown} title="Reply privately" aria-label={format!("Reply privately to {}", m.from)} class="opacity-0 group-hover:opacity-100 focus:opacity-100 self-center mx-1 text-gray-400 hover:text-blue-600">{"✉️"}</button>
+                }
+            }
+            _ => html! {},
+        };
+        // Only server ids are kept; ours change once the server acks.
+        let bookmark = match &m.id {
+            Some(id) if confirmed && outgoing.is_none() => {
+                let bookmarked = self.bookmarks.contains(id);
+                let id = id.clone();
+                let onclick = ctx.link().callback(move |_| Msg::ToggleBookmark(id.clone()));
+                let label = if bookmarked { "Remove bookmark" } else { "Bookmark" };
+                let visibility = if bookmarked { "text-amber-500" } else { "opacity-0 group-hover:opacity-100 focus:opacity-100 text-gray-400" };
+                html! {
+                    <button {onclick} title={label} aria-label={label} aria-pressed={bookmarked.to_string()}
+                        class={classes!("self-center", "mx-1", "hover:text-amber-600", visibility)}>{"🔖"}</button>
+                }
+            }
+            _ => html! {},
+        };
+        let copy = if m.deleted || m.file.is_some() {
+            html! {}
+        } else {
+            match &self.copy_status {
+                Some((copied, true)) if *copied == m.key => html! {
+                    <span class="self-center mx-1 text-xs text-green-600">{"Copied!"}</span>
+                },
+                Some((copied, false)) if *copied == m.key => html! {
+                    <span class="self-center mx-1 text-xs text-red-600">{"Couldn't copy. Select the text and press Ctrl+C."}</span>
+                },
+                _ => {
+                    let key = m.key.clone();
+                    let onclick = ctx.link().callback(move |_| Msg::CopyMessage(key.clone()));
+                    html! {
+                        <button {onclick} title="Copy text" aria-label="Copy text" class="opacity-0 group-hover:opacity-100 focus:opacity-100 self-center mx-1 text-gray-400 hover:text-blue-600">{"📋"}</button>
+                    }
+                }
+            }
+        };
+        html!{
+            <div data-read-id={m.id.clone().filter(|_| !is_me)} class={format!("group flex items-end w-3/6 m-4 rounded-tl-lg rounded-tr-lg rounded-br-lg shadow-sm {}", if is_me { "flex-row-reverse" } else { "" })}>
+                <img class="w-8 h-8 rounded-full m-3 border border-blue-100" src={avatar} alt={format!("{}'s avatar", m.from)}/>
+                <div {ondblclick} class={classes!("p-3", "rounded-lg", bubble_class, mention_class, outgoing.map(|_| "opacity-70"))}>
+                    <div class={format!("text-sm font-bold {}", text_class)}>
+                        {if is_me { html! { "You" } } else { highlight(&m.from, &self.search) }}
+                    </div>
+                    {m.reply_to.as_deref().map(|id| self.view_quote(ctx, &m.key, id, m.quote.as_deref())).unwrap_or_default()}
+                    <div class={format!("text-xs mt-1 {}", text_class)}>
+                        {body}
+                    </div>
+                    {self.render_meta(ctx, m, is_me, outgoing)}
+                    if confirmed {
+                        {self.view_reactions(ctx, m, current_username)}
+                    }
+                </div>
+                {reply}
+                {reply_privately}
+                {bookmark}
+                {copy}
+                {delete}
+            </div>
+        }
+    }
+
+    /// The line under a bubble: the parts from `meta_parts`, in their
+    /// order and separated by dots, e.g. "3 min ago · edited · Seen".
+    fn render_meta(&self, ctx: &Context<Self>, m: &MessageData, is_me: bool, outgoing: Option<&Outgoing>) -> Html {
+        let seen_at = m
+            .to
+            .as_ref()
+            .and_then(|peer| self.dm_seen.get(peer))
+            .filter(|(key, _)| *key == m.key)
+            .map(|&(_, time)| time);
+        let parts = meta_parts(m, is_me, outgoing, self.room_capabilities().retention_secs, seen_at);
+        html! {
+            <div class="text-[10px] text-gray-400 mt-1">
+                {
+                    for parts.into_iter().enumerate().map(|(i, part)| html! {
+                        <>
+                            if i > 0 && !matches!(part, Meta::Expires { .. }) {
+                                {" · "}
+                            }
+                            {
+                                match part {
+                                    Meta::Time(time) => html! { <RelativeTime {time} /> },
+                                    Meta::Edited => html! { <span class="italic">{"edited"}</span> },
+                                    Meta::Seen { direct: true, by, at: Some(at) } => html! {
+                                        <span title={seen_by_names(by)}>{format!("Seen {}", format_time(at))}</span>
+                                    },
+                                    Meta::Seen { direct: true, by, at: None } => html! {
+                                        <span title={seen_by_names(by)}>{"Seen"}</span>
+                                    },
+                                    Meta::Seen { direct: false, by, .. } => html! {
+                                        <span title={seen_by_names(by)}>{format!("Seen by {}", by.len())}</span>
+                                    },
+                                    Meta::Outgoing(outgoing) => self.view_outgoing_state(ctx, outgoing),
+                                    Meta::Expires { at, warn_ms } => html! {
+                                        <ExpiresIn time={at} {warn_ms} separated={i > 0} />
+                                    },
+                                }
+                            }
+                        </>
+                    })
+                }
+            </div>
+        }
+    }
+
+    /// Where our message is on its way to the server, with Retry and
+    /// Discard once it failed.
+    fn view_outgoing_state(&self, ctx: &Context<Self>, outgoing: &Outgoing) -> Html {
+        match outgoing.state {
+            OutgoingState::Failed => {
+                let id = outgoing.id.clone();
+                let retry = ctx.link().callback(move |_| Msg::RetryOutgoing(id.clone()));
+                let id = outgoing.id.clone();
+                let discard = ctx.link().callback(move |_| Msg::DiscardOutgoing(id.clone()));
+                html! {
+                    <span role="alert" class="text-red-600">
+                        <span class="inline-block w-4 h-4 mr-1 rounded-full bg-red-600 text-white text-center font-bold leading-4">{"!"}</span>
+                        {"Not sent · "}
+                        <button onclick={retry} class="underline hover:text-red-800">{"Retry"}</button>
+                        {" · "}
+                        <button onclick={discard} class="underline hover:text-red-800">{"Discard"}</button>
+                    </span>
+                }
+            }
+            OutgoingState::Sent => html! { <span>{"✓ Sent"}</span> },
+            OutgoingState::Queued | OutgoingState::Sending => html! { <span>{"🕓 Sending…"}</span> },
+        }
+    }
+
+    /// The text selected in the bubble of the message `id`, to quote just
+    /// that in a reply; see `selection_quote`. `None` quotes all of it.
+    fn selected_quote(&self, id: &str) -> Option<String> {
+        let m = self.find_message(id)?;
+        let selection = gloo::utils::window().get_selection().ok()??;
+        let list = self.message_list.cast::<web_sys::Element>()?;
+        let row = list.query_selector(&format!("[data-row-key=\"{}\"]", m.key)).ok()??;
+        let within = |node: Option<web_sys::Node>| node.is_some_and(|node| row.contains(Some(&node)));
+        if !within(selection.anchor_node()) || !within(selection.focus_node()) {
+            return None;
+        }
+        selection_quote(&String::from(selection.to_string()), &m.message)
+    }
+
+    fn find_message(&self, id: &str) -> Option<&MessageData> {
+        self.messages.iter().find(|m| m.id.as_deref() == Some(id))
+    }
+
+    /// What quoting `m`, or the `quote` of it a reply picked, shows,
+    /// masked like its bubble.
+    fn quoted_text(&self, m: &MessageData, quote: Option<&str>) -> String {
+        if m.deleted {
+            "This message was deleted".to_string()
+        } else if let Some(file) = &m.file {
+            format!("📎 {}", file.name)
+        } else if let Some(quote) = quote {
+            format!("“{}”", self.preferences.filtered(quote))
+        } else {
+            self.preferences.filtered(&m.message).into_owned()
+        }
+    }
+
+    /// A one-line preview of `quoted_text`.
+    fn quote_text(&self, m: &MessageData, quote: Option<&str>) -> String {
+        quote_preview(&self.quoted_text(m, quote))
+    }
+
+    /// The quote of the message `parent_id`, or of the part `quote` of it,
+    /// shown in the reply with the key `reply_key`. It is one line until
+    /// clicked, then shown in full; the ↗ next to it scrolls to the
+    /// original, if we have it.
+    fn view_quote(&self, ctx: &Context<Self>, reply_key: &str, parent_id: &str, quote: Option<&str>) -> Html {
+        // A private reply to a room message we don't have still shows the
+        // text it carried.
+        let parent = self.find_message(parent_id);
+        let (from, text) = match (parent, quote) {
+            (Some(parent), _) => (Some(parent.from.clone()), self.quoted_text(parent, quote)),
+            (None, Some(quote)) => (None, format!("“{}”", self.preferences.filtered(quote))),
+            (None, None) => {
+                return html! {
+                    <div class="mt-1 px-2 py-1 border-l-4 border-gray-300 rounded text-left text-xs text-gray-400 italic">
+                        {"Original message unavailable"}
+                    </div>
+                };
+            }
+        };
+        let expanded = self.expanded_quotes.contains(reply_key);
+        let (text, text_class) = if expanded {
+            (text, "whitespace-pre-wrap break-words")
+        } else {
+            (quote_preview(&text), "truncate")
+        };
+        let toggle = {
+            let key = reply_key.to_string();
+            ctx.link().callback(move |_| Msg::ToggleQuote(key.clone()))
+        };
+        let jump = parent.map(|_| {
+            let id = parent_id.to_string();
+            let onclick = ctx.link().callback(move |_| Msg::ScrollToMessage(id.clone()));
+            html! {
+                <button {onclick} title="Show original message" aria-label="Show original message"
+                    class="flex-none px-1 rounded text-blue-600 hover:bg-blue-100 dark:text-blue-300 dark:hover:bg-gray-700">{"↗"}</button>
+            }
+        });
+        let border = if parent.is_some() { "border-blue-400" } else { "border-gray-300" };
+        // Double-clicking the bubble edits or reacts; here it just expands
+        // and collapses the quote.
+        let ondblclick = Callback::from(|e: MouseEvent| e.stop_propagation());
+        html! {
+            <div {ondblclick} class={classes!("flex", "items-start", "gap-1", "mt-1", "px-2", "py-1", "border-l-4", border, "rounded", "bg-white", "bg-opacity-60", "text-xs", "text-gray-600", "dark:bg-gray-800", "dark:bg-opacity-60", "dark:text-gray-300")}>
+                <button onclick={toggle} aria-expanded={expanded.to_string()} title={if expanded { "Show less" } else { "Show the whole quote" }}
+                    class={classes!("grow", "min-w-0", "text-left", text_class)}>
+                    if let Some(from) = from {
+                        <span class="font-semibold">{from}{": "}</span>
+                    }
+                    {text}
+                </button>
+                {jump.unwrap_or_default()}
+            </div>
+        }
+    }
+
+    /// The reaction tallies under a bubble, and the "+" that opens the
+    /// picker on hover. Clicking a tally toggles our own reaction.
+    fn view_reactions(&self, ctx: &Context<Self>, m: &MessageData, current_username: &str) -> Html {
+        let Some(id) = m.id.clone().filter(|_| !m.deleted) else {
+            return html! {};
+        };
+        let tallies = REACTIONS.iter().filter_map(|&emoji| {
+            let names = m.reactions.get(emoji).filter(|names| !names.is_empty())?;
+            let mine = names.iter().any(|name| name == current_username);
+            let id = id.clone();
+            let onclick = ctx.link().callback(move |_| Msg::React(id.clone(), emoji));
+            let class = if mine {
+                "border-blue-400 bg-blue-100 dark:bg-blue-800"
+            } else {
+                "border-gray-300 bg-white dark:border-gray-600 dark:bg-gray-800"
+            };
+            Some(html! {
+                <button {onclick} title={names.join(", ")} aria-pressed={mine.to_string()}
+                    aria-label={format!("{} reaction from {}", emoji, names.join(", "))} class={format!("px-1.5 rounded-full border text-xs {}", class)}>
+                    {format!("{} {}", emoji, names.len())}
+                </button>
+            })
+        });
+        let picker_open = self.reacting_to.as_ref() == Some(&id);
+        let toggle_picker = {
+            let id = id.clone();
+            ctx.link().callback(move |_| Msg::ToggleReactionPicker(Some(id.clone())))
+        };
+        html! {
+            <div class="relative flex flex-wrap gap-1 mt-1">
+                {for tallies}
+                <button onclick={toggle_picker} title="Add reaction" aria-label="Add reaction" aria-expanded={picker_open.to_string()}
+                    class={format!("px-1.5 rounded-full text-xs text-gray-400 hover:text-gray-600 {}", if picker_open { "" } else { "opacity-0 group-hover:opacity-100 focus:opacity-100" })}>
+                    {"+"}
+                </button>
+                if picker_open {
+                    <div class="absolute bottom-6 z-40 flex gap-1 p-1 rounded-lg shadow-lg bg-white dark:bg-gray-800">
+                        { for REACTIONS.iter().map(|&emoji| {
+                            let id = id.clone();
+                            let onclick = ctx.link().callback(move |_| Msg::React(id.clone(), emoji));
+                            html! { <button {onclick} aria-label={format!("React with {}", emoji)} class="w-7 h-7 rounded text-base hover:bg-gray-100 dark:hover:bg-gray-700">{emoji}</button> }
+                        }) }
+                    </div>
+                }
+            </div>
+        }
+    }
+
+    /// The "Bookmarks" button in the sidebar and, while open, the list
+    /// under it. Bookmarks of messages still loaded jump to them; the
+    /// others only show their snapshot.
+    fn view_bookmarks(&self, ctx: &Context<Self>) -> Html {
+        let toggle = ctx.link().callback(|_| Msg::ToggleBookmarks);
+        let entries = self.bookmarks.iter().map(|b| {
+            let place = match (&b.peer, &b.room) {
+                (Some(peer), _) => format!("with {}", peer),
+                (None, Some(room)) => format!("#{}", room),
+                (None, None) => String::new(),
+            };
+            let jump = self.find_message(&b.id).map(|_| {
+                let id = b.id.clone();
+                let onclick = ctx.link().callback(move |_| Msg::OpenBookmark(id.clone()));
+                html! {
+                    <button {onclick} title="Show message" aria-label="Show message" class="px-1 rounded text-blue-600 hover:bg-blue-100 dark:text-blue-300 dark:hover:bg-gray-600">{"↗"}</button>
+                }
+            });
+            let remove = {
+                let id = b.id.clone();
+                ctx.link().callback(move |_| Msg::RemoveBookmark(id.clone()))
+            };
+            html! {
+                <li class="px-2 py-1 border-b last:border-b-0 border-gray-100 dark:border-gray-600">
+                    <div class="flex items-center gap-1 text-[10px] text-gray-500 dark:text-gray-400">
+                        <span class="font-semibold text-gray-700 dark:text-gray-200">{b.from.clone()}</span>
+                        <span class="grow truncate">{place}</span>
+                        if let Some(time) = b.time {
+                            <span>{format_time(time)}</span>
+                        }
+                        {jump.unwrap_or_default()}
+                        <button onclick={remove} title="Remove bookmark" aria-label="Remove bookmark" class="px-1 rounded hover:text-red-600">{"✕"}</button>
+                    </div>
+                    <div class="text-xs truncate">{quote_preview(&self.preferences.filtered(&b.text))}</div>
+                </li>
+            }
+        });
+        html! {
+            <div class="mx-3 my-2">
+                <button onclick={toggle} aria-expanded={self.show_bookmarks.to_string()}
+                    class="flex w-full rounded-lg px-3 py-2 shadow-sm items-center gap-2 text-sm font-semibold bg-white hover:bg-blue-50 dark:bg-gray-700 dark:hover:bg-gray-600">
+                    {"🔖 Bookmarks"}
+                    if !self.bookmarks.is_empty() {
+                        <span class="ml-auto text-xs text-gray-500 dark:text-gray-400">{self.bookmarks.len()}</span>
+                    }
+                </button>
+                if self.show_bookmarks {
+                    if self.bookmarks.is_empty() {
+                        <div class="mt-1 px-2 text-xs text-gray-400 italic">{"Bookmark a message with 🔖 to find it here."}</div>
+                    } else {
+                        <ul aria-label="Bookmarks" class="mt-1 max-h-64 overflow-y-auto rounded-lg bg-white text-sm shadow-sm dark:bg-gray-700">
+                            { for entries }
+                        </ul>
+                    }
+                }
+            </div>
+        }
+    }
+
+    fn view_notes(&self, ctx: &Context<Self>) -> Html {
+        if self.notes.is_empty() {
+            return html! {
+                <div class="text-center text-sm text-gray-400 italic mt-8">
+                    {"Jot down links and reminders here. Nobody else can see them."}
+                </div>
+            };
+        }
+
+        self.notes
+            .iter()
+            .enumerate()
+            .map(|(index, note)| {
+                html! {
+                    <div class="flex w-3/6 m-4 ml-auto">
+                        <div class="p-3 rounded-lg shadow-sm bg-amber-100 text-amber-900 text-right ml-auto">
+                            <div class="text-xs mt-1">
+                                {self.view_message_body(ctx, format!("note-{}", index), &note.text, &Highlights::default())}
+                            </div>
+                            <div class="text-[10px] text-amber-700 mt-1"><RelativeTime time={note.time} /></div>
+                        </div>
+                    </div>
+                }
+            })
+            .collect()
+    }
+
+    /// Renders message text. `gif_key` identifies the message for
+    /// click-to-play GIFs.
+    fn view_message_body(&self, ctx: &Context<Self>, gif_key: String, text: &str, highlights: &Highlights<'_>) -> Html {
+        if let Some(code) = snippet_body(text) {
+            return view_snippet(code);
+        }
+        match media::media_kind(text) {
+            Some(Media::Gif) => self.view_gif(ctx, gif_key, text),
+            Some(Media::Video) => self.view_video(text.trim()),
+            Some(Media::YouTube(id)) => html! {
+                <iframe class="mt-3 w-full aspect-video rounded" src={media::youtube_embed_url(&id)}
+                    title="YouTube video" allow="encrypted-media; picture-in-picture; fullscreen" allowfullscreen=true loading="lazy"></iframe>
+            },
+            None => render_message_body(text, highlights),
+        }
+    }
+
+    /// Sends `text` to the open conversation: the room, subject to slow
+    /// mode, a direct message, or the local notes. Messages to others are
+    /// also held back while offline or sent too quickly in a row. Returns
+    /// whether it was accepted.
+    fn submit_text(&mut self, ctx: &Context<Self>, text: String) -> bool {
+        if self.is_offline() || (self.conversation == Conversation::Room && self.cooldown_remaining() > 0) {
+            return false;
+        }
+        if self.conversation != Conversation::Notes && !self.rate_limiter.try_acquire(js_sys::Date::now()) {
+            let link = ctx.link().clone();
+            self.rate_limit_warning = Some(Timeout::new(RATE_LIMIT_WARNING_MS, move || {
+                link.send_message(Msg::RateLimitWarningDone)
+            }));
+            return false;
+        }
+        match &self.conversation {
+            Conversation::Room => {
+                self.send_message(ctx, text);
+                self.start_cooldown(ctx);
+            }
+            Conversation::Notes => {
+                self.notes.push(Note {
+                    text,
+                    time: js_sys::Date::now(),
+                });
+                notes::save(&self.notes);
+                self.scroll_to_bottom = true;
+            }
+            Conversation::Direct(peer) => {
+                let peer = peer.clone();
+                self.send_text(ctx, Some(peer), text);
+            }
+        }
+        true
+    }
+
+    /// Sends an uploaded file to the open conversation, under the same slow
+    /// mode as text.
+    fn send_attachment(&mut self, ctx: &Context<Self>, file: Attachment) {
+        if self.connection == ConnectionState::Offline {
+            self.upload_error = Some("You're offline, so the file wasn't sent.".to_string());
+            return;
+        }
+        let to = match &self.conversation {
+            Conversation::Room if self.cooldown_remaining() > 0 => {
+                self.upload_error = Some("Slow mode is on. Try again in a moment.".to_string());
+                return;
+            }
+            Conversation::Room => None,
+            Conversation::Direct(peer) => Some(peer.clone()),
+            Conversation::Notes => return,
+        };
+        let sent = self.send(WebSocketMessage {
+            to: to.clone(),
+            id: Some(new_message_id()),
+            room: to.is_none().then(|| self.room.clone()),
+            ..WebSocketMessage::new(MsgTypes::File, serde_json::to_string(&file).ok())
+        });
+        if !sent {
+            self.upload_error = Some("The file couldn't be sent. Try again in a moment.".to_string());
+        } else if to.is_none() {
+            self.start_cooldown(ctx);
+        }
+    }
+
+    /// Adds a notice to the default room, e.g. "Bob joined", since everyone
+    /// is in it. Announcements from the server are shown as a banner in
+    /// every room instead.
+    fn push_system_message(&mut self, message: String, announcement: bool) {
+        if !self.scrolled_up {
+            self.scroll_to_bottom = true;
+        }
+        self.messages.push(MessageData {
+            message,
+            time: Some(js_sys::Date::now()),
+            room: (!announcement).then(|| DEFAULT_ROOM.to_string()),
+            system: true,
+            announcement,
+            key: new_message_id(),
+            ..MessageData::default()
+        });
+    }
+
+    /// Saves the newest messages for the next page load. Uploaded files are
+    /// left out, since a few of them would fill `localStorage`.
+    fn save_history(&self) {
+        let messages: Vec<&MessageData> = self
+            .messages
+            .iter()
+            .filter(|m| m.file.is_none() && !m.system && self.outbox.showing(m.id.as_deref()).is_none())
+            .collect();
+        history::save(&messages, self.preferences.history_limit as usize);
+    }
+
+    fn send_message(&mut self, ctx: &Context<Self>, text: String) {
+        self.send_text(ctx, None, text);
+    }
+
+    /// Sends `text` to the room, or to the peer `to`, through the outbox.
+    /// It shows in the conversation right away, marked pending until the
+    /// server confirms it, and waits in the outbox while the connection is
+    /// down.
+    fn send_text(&mut self, ctx: &Context<Self>, to: Option<String>, text: String) {
+        let id = new_message_id();
+        let room = to.is_none().then(|| self.room.clone());
+        let (reply_to, quote) = self.replying_to.take().map(|r| (r.id, r.quote)).unzip();
+        let quote = quote.flatten();
+        self.messages.push(MessageData {
+            from: current_username(ctx),
+            id: Some(id.clone()),
+            to: to.clone(),
+            room: room.clone(),
+            message: text.clone(),
+            time: Some(js_sys::Date::now()),
+            reply_to: reply_to.clone(),
+            quote: quote.clone(),
+            key: id.clone(),
+            ..Default::default()
+        });
+        let mut outgoing = Outgoing::new(id, to, room, text, reply_to, js_sys::Date::now());
+        outgoing.quote = quote;
+        self.outbox.push(outgoing);
+        self.outbox.save(&current_username(ctx));
+        self.scroll_to_bottom = true;
+        if self.connection == ConnectionState::Connected {
+            self.flush_outbox(ctx);
+        }
+    }
+
+    /// Sends the queued outbox messages, in the order they were written.
+    /// Those the socket refuses are marked failed, to be retried, and the
+    /// others fail if the server doesn't acknowledge them in time.
+    fn flush_outbox(&mut self, ctx: &Context<Self>) {
+        let wss = self.wss.as_ref();
+        let sent = self.outbox.flush(|outgoing| {
+            let message_type = if outgoing.to.is_some() { MsgTypes::Direct } else { MsgTypes::Message };
+            send_to_socket(wss, WebSocketMessage {
+                to: outgoing.to.clone(),
+                id: Some(outgoing.id.clone()),
+                room: outgoing.room.clone(),
+                client_msg_id: Some(outgoing.id.clone()),
+                reply_to: outgoing.reply_to.clone(),
+                quote: outgoing.quote.clone(),
+                ..WebSocketMessage::new(message_type, Some(outgoing.text.clone()))
+            })
+        });
+        for id in sent {
+            let link = ctx.link().clone();
+            let timed_out = id.clone();
+            if let Some(outgoing) = self.outbox.get_mut(&id) {
+                outgoing.timeout = Some(Timeout::new(SEND_TIMEOUT_MS, move || {
+                    link.send_message(Msg::OutgoingTimedOut(timed_out))
+                }));
+            }
+        }
+    }
+
+    /// Takes `messages[index]` out, keeping what points at later messages
+    /// on them.
+    fn remove_message(&mut self, index: usize) {
+        self.messages.remove(index);
+        if let Some(divider) = &mut self.unread_divider {
+            if *divider > index {
+                *divider -= 1;
+            }
+        }
+    }
+
+    /// Shows `room`, adding it to the room list if it is new.
+    fn switch_room(&mut self, ctx: &Context<Self>, room: String) {
+        self.join_room(&room);
+        self.room_unread.remove(&room);
+        self.room = room;
+        self.conversation = Conversation::Room;
+        self.replying_to = None;
+        self.room_shown = HISTORY_PAGE_SIZE;
+        self.history_more = true;
+        self.loading_history = false;
+        self.typing.clear();
+        self.scroll_to_bottom = true;
+        self.show_read_boundary(&current_username(ctx));
+        if let Some(id) = self.jump_after_switch.take() {
+            ctx.link().send_message(Msg::ScrollToMessage(id));
+        }
+        self.update_cooldown_ticker(ctx);
+    }
+
+    fn join_room(&mut self, room: &str) {
+        if self.rooms.iter().any(|r| r == room) {
+            return;
+        }
+        self.rooms.push(room.to_string());
+        save_rooms(&self.rooms);
+        self.send(WebSocketMessage {
+            room: Some(room.to_string()),
+            ..WebSocketMessage::new(MsgTypes::Join, None)
+        });
+    }
+
+    /// Opens a new connection after reconnecting gave up.
+    fn reconnect(&mut self, ctx: &Context<Self>) {
+        let (user, _) = ctx
+            .link()
+            .context::<User>(Callback::noop())
+            .expect("context to be set");
+        self.wss = Some(WebsocketService::connect_with_retry(
+            websocket::server_url(),
+            register_frame(&user.username.borrow(), &user.avatar.borrow()),
+            Some(Heartbeat::default()),
+        ));
+    }
+
+    fn send_frame(&self, message_type: MsgTypes, data: Option<String>) {
+        self.send(WebSocketMessage::new(message_type, data));
+    }
+
+    /// Hands `message` to the socket, returning whether it took it.
+    fn send(&self, message: WebSocketMessage) -> bool {
+        send_to_socket(self.wss.as_ref(), message)
+    }
+
+    /// The policies of the open room.
+    fn room_capabilities(&self) -> &RoomCapabilities {
+        self.capabilities.get(&self.room).unwrap_or(&NO_CAPABILITIES)
+    }
+
+    /// Whole seconds left before slow mode allows another message in the
+    /// open room, rounded up so the countdown never shows "0s".
+    fn cooldown_remaining(&self) -> u64 {
+        let (Some(secs), Some(last_sent_at)) = (self.room_capabilities().slow_mode_secs, self.last_sent_at.get(&self.room))
+        else {
+            return 0;
+        };
+        let elapsed = js_sys::Date::now() - last_sent_at;
+        ((secs as f64 * 1000.0 - elapsed) / 1000.0).ceil().max(0.0) as u64
+    }
+
+    fn start_cooldown(&mut self, ctx: &Context<Self>) {
+        self.last_sent_at.insert(self.room.clone(), js_sys::Date::now());
+        self.update_cooldown_ticker(ctx);
+    }
+
+    /// Runs the once-a-second ticker only while a cooldown is pending, so it
+    /// stops by itself when the wait is over or slow mode is switched off.
+    fn update_cooldown_ticker(&mut self, ctx: &Context<Self>) {
+        if self.cooldown_remaining() == 0 {
+            self.cooldown_ticker = None;
+        } else if self.cooldown_ticker.is_none() {
+            let link = ctx.link().clone();
+            self.cooldown_ticker = Some(Interval::new(1000, move || {
+                link.send_message(Msg::CooldownTick)
+            }));
+        }
+    }
+
+    /// Adds a message to the next notification, which goes out
+    /// `NOTIFY_COALESCE_MS` after the first message waiting for it.
+    fn queue_notification(&mut self, ctx: &Context<Self>, message: &MessageData) {
+        let text = if message.file.is_some() {
+            "Sent a file".to_string()
+        } else {
+            message.message.clone()
+        };
+        self.pending_notifications.push((message.from.clone(), text));
+        if self.notify_timer.is_none() {
+            let link = ctx.link().clone();
+            self.notify_timer = Some(Timeout::new(NOTIFY_COALESCE_MS, move || {
+                link.send_message(Msg::FlushNotifications)
+            }));
+        }
+    }
+
+    /// Sends the configured join greeting to the open room, unless it is
+    /// one of `no_greeting_rooms`. Called once the first roster confirms
+    /// the registration went through.
+    fn send_greeting(&mut self, ctx: &Context<Self>) {
+        let greeting = self.preferences.greeting.trim().to_string();
+        if greeting.is_empty() || self.conversation != Conversation::Room {
+            return;
+        }
+        let skipped = self
+            .preferences
+            .no_greeting_rooms
+            .iter()
+            .any(|room| room_name(room).as_deref() == Some(self.room.as_str()));
+        if skipped {
+            return;
+        }
+        if greeting.chars().count() > self.max_length {
+            log::debug!("greeting is longer than the {} characters the server takes", self.max_length);
+            return;
+        }
+        let now = js_sys::Date::now();
+        let last_sent: f64 = LocalStorage::get(GREETING_STORAGE_KEY).unwrap_or(0.0);
+        // Goes through the same slow mode and rate limit as typed messages.
+        if now - last_sent >= GREETING_COOLDOWN_MS && self.submit_text(ctx, greeting) {
+            let _ = LocalStorage::set(GREETING_STORAGE_KEY, now);
+        }
+    }
+
+    fn send_status(&self, status: Presence) {
+        let status = serde_json::to_value(status)
+            .ok()
+            .and_then(|value| value.as_str().map(str::to_string));
+        self.send_frame(MsgTypes::Status, status);
+    }
+
+    /// Asks the server to resend the `Users` list.
+    fn request_roster(&self) {
+        self.send_frame(MsgTypes::Users, None);
+    }
+
+    /// Forgets typists we haven't heard from in a while, and keeps a
+    /// ticker running to do so only while someone is typing.
+    fn update_typing_ticker(&mut self, ctx: &Context<Self>) {
+        let now = js_sys::Date::now();
+        self.typing.retain(|(_, at)| now - at < TYPING_TIMEOUT_MS);
+        if self.typing.is_empty() {
+            self.typing_ticker = None;
+        } else if self.typing_ticker.is_none() {
+            let link = ctx.link().clone();
+            self.typing_ticker = Some(Interval::new(1000, move || {
+                link.send_message(Msg::TypingTick)
+            }));
+        }
+    }
+
+    /// Whether the open conversation needs the server and it can't be
+    /// reached. Notes work regardless.
+    fn is_offline(&self) -> bool {
+        self.connection == ConnectionState::Offline && self.conversation != Conversation::Notes
+    }
+
+    /// Sends read receipts for conversations with newly seen messages,
+    /// unless the user turned them off or isn't looking. Read positions in
+    /// rooms are reported either way; only this user's devices get those.
+    fn report_read(&mut self) {
+        if self.blurred_at.is_some() {
+            return;
+        }
+        self.report_read_positions();
+        if !self.preferences.read_receipts {
+            return;
+        }
+        let unreported: Vec<(String, String)> = self
+            .seen
+            .iter()
+            .filter(|(key, id)| self.reported_read.get(*key) != Some(id))
+            .map(|(key, id)| (key.clone(), id.clone()))
+            .collect();
+        for (key, id) in unreported {
+            let room = key.strip_prefix('#').map(str::to_string);
+            self.send(WebSocketMessage {
+                id: Some(id.clone()),
+                to: room.is_none().then(|| key.clone()),
+                room,
+                ..WebSocketMessage::new(MsgTypes::Read, None)
+            });
+            self.reported_read.insert(key, id);
+        }
+    }
+
+    fn report_read_positions(&mut self) {
+        let seen: Vec<(String, ReadPosition)> = self
+            .seen
+            .iter()
+            .filter_map(|(key, id)| {
+                let room = key.strip_prefix('#')?;
+                let time = self.find_message(id)?.time?;
+                Some((room.to_string(), ReadPosition { id: id.clone(), time }))
+            })
+            .collect();
+        for (room, position) in seen {
+            if !reads_further(&self.read_positions, &room, position.time) {
+                continue;
+            }
+            let sent = self.send(WebSocketMessage {
+                id: Some(position.id.clone()),
+                room: Some(room.clone()),
+                ..WebSocketMessage::new(MsgTypes::ReadPosition, None)
+            });
+            if sent {
+                self.read_positions.insert(room, position);
+            }
+        }
+    }
+
+    /// Puts the "New messages" divider above the first message in the
+    /// open room that was unread on every device when we connected, and
+    /// scrolls to it, unless it already is above that. Only done once per
+    /// room. Returns whether the divider moved.
+    fn show_read_boundary(&mut self, current_username: &str) -> bool {
+        let Some(read_time) = self.read_boundaries.remove(&self.room) else {
+            return false;
+        };
+        let Some(index) = unread_boundary(&self.messages, &self.room, current_username, read_time) else {
+            return false;
+        };
+        if self.unread_divider.is_some_and(|divider| divider <= index) {
+            return false;
+        }
+        self.unread_divider = Some(index);
+        self.unread_divider_seen = false;
+        self.scroll_to = Some(self.messages[index].key.clone());
+        true
+    }
+
+    fn keep_scroll_position(&mut self) {
+        if let Some(pane) = self.message_pane.cast::<web_sys::Element>() {
+            self.keep_scroll = Some(pane.scroll_height() - pane.scroll_top());
+        }
+    }
+
+    fn is_near_bottom(&self) -> bool {
+        match self.message_pane.cast::<web_sys::Element>() {
+            Some(pane) => pane.scroll_height() - pane.scroll_top() - pane.client_height() < NEAR_BOTTOM_PX,
+            None => true,
+        }
+    }
+
+    /// Reads `draft_len` from the message input, e.g. after changing it
+    /// from code. The warning goes once the draft fits again.
+    fn sync_draft_len(&mut self) {
+        if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+            self.draft_len = input.value().chars().count();
+        }
+        if self.draft_len <= self.max_length {
+            self.too_long_warning = false;
+        }
+    }
+
+    fn length_counter_color(&self) -> &'static str {
+        if self.draft_len as f64 >= self.max_length as f64 * LENGTH_WARNING_RATIO {
+            "text-red-600"
+        } else {
+            "text-gray-400"
+        }
+    }
+
+    fn focus_input(&self) {
+        if let Some(input) = self.chat_input.cast::<HtmlInputElement>() {
+            let _ = input.focus();
+        }
+    }
+
+    fn view_attachment(&self, ctx: &Context<Self>, file: &Attachment) -> Html {
+        if file.is_image() {
+            return self.view_image(ctx, &file.url);
+        }
+        match file.href() {
+            Some(href) => html! {
+                <a href={href.to_string()} download={file.name.clone()} class="mt-1 inline-flex items-center gap-1 underline text-blue-600 hover:text-blue-800">
+                    {format!("📄 {}", file.name)}
+                </a>
+            },
+            None => html! {
+                <span class="mt-1 inline-flex items-center gap-1 text-gray-400 italic">{format!("📄 {} (can't be opened)", file.name)}</span>
+            },
+        }
+    }
+
+    fn view_gif(&self, ctx: &Context<Self>, key: String, src: &str) -> Html {
+        let image_style = format!(
+            "max-width: {}px; max-height: {}px;",
+            self.preferences.max_image_width, self.preferences.max_image_height
+        );
+
+        if !self.preferences.autoplay_gifs && !self.played_gifs.contains(&key) {
+            // Nothing is fetched until the user asks for it, so the
+            // placeholder has a fixed size rather than the GIF's own.
+            let play = ctx.link().callback(move |_| Msg::PlayGif(key.clone()));
+            return html! {
+                <button onclick={play} style={image_style} class="mt-3 w-40 h-28 rounded bg-gray-300 flex items-center justify-center hover:bg-gray-400 transition" title="Play GIF">
+                    <span class="px-3 py-1 rounded-full bg-black bg-opacity-60 text-white text-sm font-bold">{"▶ GIF"}</span>
+                </button>
+            };
+        }
+
+        self.view_image(ctx, src)
+    }
+
+    /// A video player, sized like images. Only its first frame loads until
+    /// it is played.
+    fn view_video(&self, src: &str) -> Html {
+        let style = format!(
+            "max-width: min(100%, {}px); max-height: {}px;",
+            self.preferences.max_image_width, self.preferences.max_image_height
+        );
+        html! {
+            <video class="mt-3 rounded" {style} src={src.to_string()} controls=true preload="metadata"></video>
+        }
+    }
+
+    /// An inline image, sized by the preferences, that opens in the
+    /// lightbox when clicked.
+    fn view_image(&self, ctx: &Context<Self>, src: &str) -> Html {
+        let image_style = format!(
+            "max-width: {}px; max-height: {}px;",
+            self.preferences.max_image_width, self.preferences.max_image_height
+        );
+        let full = src.to_string();
+        let open = ctx.link().callback(move |_| Msg::OpenLightbox(full.clone()));
+        html! {
+            <img class="mt-3 rounded cursor-zoom-in" style={image_style} src={src.to_string()} onclick={open}/>
+        }
+    }
+}
+
+/// The `Register` frame announcing us, sent on every (re)connect.
+fn register_frame(username: &str, avatar: &str) -> String {
+    let register = WebSocketMessage {
+        avatar: Some(avatar.to_string()).filter(|avatar| !avatar.is_empty()),
+        ..WebSocketMessage::new(MsgTypes::Register, Some(username.to_string()))
+    };
+    serde_json::to_string(&register).unwrap()
+}
+
+/// Whether `url` may be used as someone's avatar. Only web images are
+/// allowed, so a roster entry can't point `<img>` at anything else.
+fn is_avatar_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
+
+/// The length limit set at build time, or 500 if it is unset or
+/// not a positive number.
+fn max_message_length() -> usize {
+    CONFIGURED_MAX_MESSAGE_LENGTH
+        .and_then(|length| length.trim().parse().ok())
+        .filter(|&length| length > 0)
+        .unwrap_or(DEFAULT_MAX_MESSAGE_LENGTH)
+}
+
+/// Returns to the login page, which shows `error`.
+fn back_to_login(ctx: &Context<Chat>, error: String) {
+    if let Some((user, _)) = ctx.link().context::<User>(Callback::noop()) {
+        *user.login_error.borrow_mut() = Some(error);
+    }
+    if let Some(history) = ctx.link().history() {
+        history.push(Route::Login);
+    }
+}
+
+/// Reads a frame from the server, or logs why it can't be read: it isn't
+/// JSON, lacks a field, or has a `messageType` this client doesn't know.
+fn parse_frame(frame: &str) -> Option<WebSocketMessage> {
+    match serde_json::from_str(frame) {
+        Ok(msg) => Some(msg),
+        Err(e) => {
+            log::error!("invalid frame {:?}: {:?}", frame, e);
+            None
+        }
+    }
+}
+
+/// The message in a `Message`, `Direct` or `File` frame. `None`, after
+/// logging why, if it has none or one that can't be shown.
+fn read_message(msg: WebSocketMessage) -> Option<MessageData> {
+    let Some(data) = msg.data else {
+        log::error!("message frame without data");
+        return None;
+    };
+    let message_data: MessageData = match serde_json::from_str(&data) {
+        Ok(message_data) => message_data,
+        Err(e) => {
+            log::error!("invalid message frame: {:?}", e);
+            return None;
+        }
+    };
+    if has_unsafe_attachment(&message_data) {
+        log::error!("dropped a file from {} that isn't a data: URL", message_data.from);
+        return None;
+    }
+    Some(message_data)
+}
+
+/// Hands `message` to the socket of `wss`, and returns whether it took
+/// it.
+fn send_to_socket(wss: Option<&WebsocketService>, message: WebSocketMessage) -> bool {
+    let Some(wss) = wss else {
+        return false;
+    };
+    match wss.tx.clone().try_send(serde_json::to_string(&message).unwrap()) {
+        Ok(()) => true,
+        Err(e) => {
+            log::debug!("error sending to channel: {:?}", e);
+            false
+        }
+    }
+}
+
+fn current_username(ctx: &Context<Chat>) -> String {
+    ctx.link()
+        .context::<User>(Callback::noop())
+        .map(|(u, _)| u.username.borrow().clone())
+        .unwrap_or_default()
+}
+
+/// Identifies the conversation of a message received from someone else:
+/// the room name after a `#`, or the sender for a direct message.
+fn thread_key(m: &MessageData) -> String {
+    if m.to.is_some() {
+        m.from.clone()
+    } else {
+        format!("#{}", room_of(m))
+    }
+}
+
+/// Whether reading up to a message sent at `time` in `room` gets further
+/// than the known position.
+fn reads_further(positions: &HashMap<String, ReadPosition>, room: &str, time: f64) -> bool {
+    positions.get(room).is_none_or(|known| time > known.time)
+}
+
+/// The index of the first message from someone else in `room` sent after
+/// `read_time`, where unread messages begin.
+fn unread_boundary(messages: &[MessageData], room: &str, current_username: &str, read_time: f64) -> Option<usize> {
+    messages.iter().position(|m| {
+        m.to.is_none()
+            && !m.system
+            && room_of(m) == room
+            && m.from != current_username
+            && m.time.is_some_and(|time| time > read_time)
+    })
+}
+
+/// The room of a room message; those from servers without rooms are in
+/// the default one.
+fn room_of(m: &MessageData) -> &str {
+    m.room.as_deref().unwrap_or(DEFAULT_ROOM)
+}
+
+/// Turns what the user typed into a room name: lowercase letters, digits,
+/// `-` and `_`, without the leading `#`. Returns `None` if nothing is left.
+fn room_name(input: &str) -> Option<String> {
+    let name: String = input
+        .trim()
+        .trim_start_matches('#')
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_whitespace() { '-' } else { c })
+        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+        .take(MAX_ROOM_NAME_CHARS)
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+/// The rooms listed in the sidebar last time, always including the
+/// default room.
+fn load_rooms() -> Vec<String> {
+    let mut rooms: Vec<String> = LocalStorage::get(ROOMS_STORAGE_KEY)
+        .unwrap_or_else(|_| DEFAULT_ROOMS.iter().map(|room| room.to_string()).collect());
+    if !rooms.iter().any(|room| room == DEFAULT_ROOM) {
+        rooms.insert(0, DEFAULT_ROOM.to_string());
+    }
+    rooms
+}
+
+fn save_rooms(rooms: &[String]) {
+    let _ = LocalStorage::set(ROOMS_STORAGE_KEY, rooms);
+}
+
+/// A part of the line under a bubble; see `meta_parts`.
+enum Meta<'a> {
+    Time(f64),
+    Edited,
+    /// Who has seen our message, for direct messages or the room. `at` is
+    /// when the peer did, for the latest direct message they have seen.
+    Seen {
+        direct: bool,
+        by: &'a HashSet<String>,
+        at: Option<f64>,
+    },
+    Outgoing(&'a Outgoing),
+    /// When the server deletes the message, counted down from `warn_ms`
+    /// before. `ExpiresIn` puts its own separator, as it shows nothing
+    /// until then.
+    Expires { at: f64, warn_ms: f64 },
+}
+
+/// What goes under a bubble, in this order: when it was sent, whether it
+/// was edited, who has seen it, where it is on its way to the server, and
+/// when the room's `retention_secs` runs out for it. Parts that don't
+/// apply are left out. Only our own messages, `is_me`, are marked seen,
+/// with `seen_at` as when if it is given.
+fn meta_parts<'a>(
+    m: &'a MessageData,
+    is_me: bool,
+    outgoing: Option<&'a Outgoing>,
+    retention_secs: Option<u64>,
+    seen_at: Option<f64>,
+) -> Vec<Meta<'a>> {
+    let mut parts = Vec::new();
+    if let Some(time) = m.time {
+        parts.push(Meta::Time(time));
+    }
+    if m.edited && !m.deleted {
+        parts.push(Meta::Edited);
+    }
+    if is_me && !m.read_by.is_empty() {
+        parts.push(Meta::Seen {
+            direct: m.to.is_some(),
+            by: &m.read_by,
+            at: seen_at,
+        });
+    }
+    parts.extend(outgoing.map(Meta::Outgoing));
+    if let (Some(secs), Some(time)) = (retention_secs, m.time.filter(|_| !m.deleted)) {
+        let retention_ms = secs as f64 * 1000.0;
+        parts.push(Meta::Expires {
+            at: time + retention_ms,
+            warn_ms: (retention_ms / 4.0).min(EXPIRY_WARNING_MS),
+        });
+    }
+    parts
+}
+
+/// What of `selected`, the text selected in the bubble of `message`, a
+/// reply quotes: `None` to quote the whole message when nothing, all of
+/// it, or text that isn't in the message as written, like formatted
+/// parts, is selected.
+fn selection_quote(selected: &str, message: &str) -> Option<String> {
+    let selected = selected.split_whitespace().collect::<Vec<_>>().join(" ");
+    let message = message.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!selected.is_empty() && selected != message && message.contains(&selected)).then_some(selected)
+}
+
+/// `text` on one line, cut off after `QUOTE_PREVIEW_CHARS` characters.
+fn quote_preview(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > QUOTE_PREVIEW_CHARS {
+        format!("{}…", text.chars().take(QUOTE_PREVIEW_CHARS).collect::<String>())
+    } else {
+        text
+    }
+}
+
+/// Who to message, and with what reply, to answer the room message `m`
+/// privately. The reply quotes the `selected` part of it, or all of its
+/// text, since the peer may not have the room message to show.
+fn private_reply(m: &MessageData, selected: Option<String>, me: &str) -> Option<(String, ReplyTarget)> {
+    if m.system || m.deleted || m.to.is_some() || m.from == me {
+        return None;
+    }
+    let quote = selected.or_else(|| (m.file.is_none()).then(|| m.message.clone()));
+    Some((m.from.clone(), ReplyTarget { id: m.id.clone()?, quote }))
+}
+
+/// Adds `name` to the people reacting to `m` with `emoji`, or takes them
+/// out.
+fn set_reaction(m: &mut MessageData, emoji: &str, name: String, add: bool) {
+    let names = m.reactions.entry(emoji.to_string()).or_default();
+    names.retain(|n| *n != name);
+    if add {
+        names.push(name);
+    } else if names.is_empty() {
+        m.reactions.remove(emoji);
+    }
+}
+
+/// The reaction a `quick_reaction` preference stands for, if it is one
+/// of `REACTIONS`.
+fn quick_reaction(preference: &str) -> Option<&'static str> {
+    REACTIONS.iter().copied().find(|&emoji| emoji == preference)
+}
+
+fn seen_by_names(read_by: &HashSet<String>) -> String {
+    let mut names: Vec<&str> = read_by.iter().map(String::as_str).collect();
+    names.sort_unstable();
+    format!("Seen by {}", names.join(", "))
+}
+
+/// What the connection notice says, in full and in short, and how it is
+/// styled.
+struct Notice {
+    role: &'static str,
+    class: &'static str,
+    text: String,
+    short: String,
+}
+
+/// The notice for the connection `state`, if there is anything to say:
+/// while reconnecting, once reconnecting gave up, and while `refill`, the
+/// number of people back of those there before, is short.
+fn connection_notice(state: ConnectionState, refill: Option<(usize, usize)>) -> Option<Notice> {
+    match (state, refill) {
+        (ConnectionState::Connected, Some((back, expected))) => Some(Notice {
+            role: "status",
+            class: "bg-blue-50 text-blue-900 dark:bg-gray-700 dark:text-gray-100",
+            text: format!("Reconnected. Waiting for the others… ({} of {} users back)", back, expected),
+            short: format!("{}/{} back", back, expected),
+        }),
+        (ConnectionState::Connecting | ConnectionState::Connected, _) => None,
+        (ConnectionState::Reconnecting { attempt }, _) => Some(Notice {
+            role: "status",
+            class: "bg-amber-100 text-amber-900",
+            text: format!("Connection lost. Reconnecting… (attempt {})", attempt),
+            short: "Reconnecting…".to_string(),
+        }),
+        (ConnectionState::Offline, _) => Some(Notice {
+            role: "alert",
+            class: "bg-red-100 text-red-900",
+            text: "Can't reach the chat server. Reload the page to try again.".to_string(),
+            short: "Offline".to_string(),
+        }),
+    }
+}
+
+/// The header pill showing whether the chat server is reachable.
+fn connection_pill(state: ConnectionState) -> Html {
+    let (label, class) = match state {
+        ConnectionState::Connected => ("Connected", "bg-green-100 text-green-800"),
+        ConnectionState::Connecting => ("Connecting…", "bg-amber-100 text-amber-800"),
+        ConnectionState::Reconnecting { .. } => ("Reconnecting…", "bg-amber-100 text-amber-800"),
+        ConnectionState::Offline => ("Offline", "bg-red-100 text-red-800"),
+    };
+    let dot = match state {
+        ConnectionState::Connected => "bg-green-500",
+        ConnectionState::Connecting | ConnectionState::Reconnecting { .. } => "bg-amber-500 animate-pulse",
+        ConnectionState::Offline => "bg-red-500",
+    };
+    html! {
+        <span class={format!("flex items-center gap-1 px-2 py-0.5 rounded-full text-xs font-semibold {}", class)} title="Connection to the chat server">
+            <span class={format!("w-2 h-2 rounded-full {}", dot)}></span>
+            {label}
+        </span>
+    }
+}
+
+/// Stands in for the roster until the first `Users` frame arrives.
+fn view_roster_skeleton() -> Html {
+    html! {
+        <>
+            <div class="px-3 text-xs text-gray-400 italic">{"Connecting…"}</div>
+            { for (0..3).map(|_| html! {
+                <div class="flex m-3 rounded-lg p-2 shadow-sm items-center bg-white animate-pulse dark:bg-gray-700">
+                    <div class="flex-none w-12 h-12 rounded-full bg-gray-200 dark:bg-gray-600"></div>
+                    <div class="flex-grow p-3 flex flex-col gap-2">
+                        <div class="w-20 h-2 rounded-full bg-gray-200 dark:bg-gray-600"></div>
+                        <div class="w-12 h-2 rounded-full bg-gray-200 dark:bg-gray-600"></div>
+                    </div>
+                </div>
+            }) }
+        </>
+    }
+}
+
+/// The status dot drawn over the corner of a roster avatar.
+fn presence_dot(status: Presence, size: &str) -> Html {
+    html! {
+        <span title={status.label()} role="img" aria-label={status.label()} class={format!("absolute bottom-0 right-0 rounded-full border-2 border-white {} {}", size, status.dot_class())}></span>
+    }
+}
+
+/// A random version 4 UUID identifying a message across clients.
+fn new_message_id() -> String {
+    let mut bytes = [0u8; 16];
+    for byte in bytes.iter_mut() {
+        *byte = (js_sys::Math::random() * 256.0) as u8;
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// Shows the unread count in the tab title, e.g. "(3) Yewchat!", or
+/// "(3) @ Yewchat!" if one of them mentions this user.
+fn update_document_title(unread: usize, mentioned: bool) {
+    let title = match (unread, mentioned) {
+        (0, _) => DOCUMENT_TITLE.to_string(),
+        (unread, false) => format!("({}) {}", unread, DOCUMENT_TITLE),
+        (unread, true) => format!("({}) @ {}", unread, DOCUMENT_TITLE),
+    };
+    gloo::utils::document().set_title(&title);
+}
+
+/// Describes who is typing, e.g. "Alice and Bob are typing…", or `None`
+/// if nobody is.
+fn typing_label(names: &[&str]) -> Option<String> {
+    match names {
+        [] => None,
+        [one] => Some(format!("{} is typing…", one)),
+        [first, second] => Some(format!("{} and {} are typing…", first, second)),
+        [first, second, third] => Some(format!("{}, {} and {} are typing…", first, second, third)),
+        _ => Some("Several people are typing…".to_string()),
+    }
+}
+
+/// Compares two rosters by name, returning who joined and who left. Users
+/// who left come with their index in `old`.
+fn roster_diff(old: &[UserProfile], new: &[UserProfile]) -> (Vec<String>, Vec<(usize, UserProfile)>) {
+    let joined = new
+        .iter()
+        .filter(|u| !old.iter().any(|o| o.name == u.name))
+        .map(|u| u.name.clone())
+        .collect();
+    let left = old
+        .iter()
+        .enumerate()
+        .filter(|(_, o)| !new.iter().any(|u| u.name == o.name))
+        .map(|(i, o)| (i, o.clone()))
+        .collect();
+    (joined, left)
+}
+
+/// Returns the code inside a message that is a single fenced block, as sent
+/// by "Send as snippet". An optional language tag on the opening fence is
+/// dropped.
+fn snippet_body(message: &str) -> Option<&str> {
+    let inner = message.strip_prefix("```")?.strip_suffix("```")?;
+    let (_lang, code) = inner.split_once('\n')?;
+    Some(code.strip_suffix('\n').unwrap_or(code))
+}
+
+fn view_snippet(code: &str) -> Html {
+    html! {
+        <details class="mt-1 text-left">
+            <summary class="cursor-pointer select-none">
+                {format!("📄 Snippet · {} lines", code.lines().count())}
+            </summary>
+            <pre class="mt-2 p-2 max-w-full overflow-x-auto rounded bg-gray-800 text-gray-100 font-mono whitespace-pre">{code}</pre>
+        </details>
+    }
+}
+
+/// Formats a retention period in its largest whole unit, e.g. "24h",
+/// "7 days" or "90 minutes".
+fn format_duration(secs: u64) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+
+    if secs >= 2 * DAY && secs.is_multiple_of(DAY) {
+        format!("{} days", secs / DAY)
+    } else if secs >= HOUR && secs.is_multiple_of(HOUR) {
+        format!("{}h", secs / HOUR)
+    } else if secs >= MINUTE {
+        format!("{} minutes", secs / MINUTE)
+    } else {
+        format!("{} seconds", secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_data_url_attachments_are_linked() {
+        let file = |url: &str| Attachment {
+            name: "notes.txt".to_string(),
+            url: url.to_string(),
+        };
+        assert_eq!(file("data:text/plain;base64,aGk=").href(), Some("data:text/plain;base64,aGk="));
+        assert_eq!(file("javascript:alert(1)").href(), None);
+        assert_eq!(file("JavaScript:alert(1)").href(), None);
+        assert_eq!(file(" data:text/plain,hi").href(), None);
+        assert_eq!(file("https://example.com/notes.txt").href(), None);
+
+        let message = MessageData {
+            file: Some(file("javascript:alert(1)")),
+            ..Default::default()
+        };
+        assert!(has_unsafe_attachment(&message));
+    }
+
+    #[test]
+    fn garbage_frames_are_skipped() {
+        assert!(parse_frame("").is_none());
+        assert!(parse_frame("not json").is_none());
+        assert!(parse_frame("{\"messageType\":").is_none());
+        assert!(parse_frame("[1, 2, 3]").is_none());
+        assert!(parse_frame("{\"data\":\"no type\"}").is_none());
+    }
+
+    #[test]
+    fn replies_quote_the_selected_part_or_everything() {
+        let message = "Lunch at noon?\nOr  later works too.";
+        assert_eq!(selection_quote("at noon", message).as_deref(), Some("at noon"));
+        assert_eq!(selection_quote(" noon?\nOr ", message).as_deref(), Some("noon? Or"));
+        assert_eq!(selection_quote("", message), None);
+        assert_eq!(selection_quote("  ", message), None);
+        assert_eq!(selection_quote("Lunch at noon? Or later works too.", message), None);
+        // Picked up from elsewhere on the bubble, like the sender's name.
+        assert_eq!(selection_quote("ann", message), None);
+    }
+
+    #[test]
+    fn private_replies_carry_the_room_message_text() {
+        let message = MessageData {
+            id: Some("m1".to_string()),
+            from: "ann".to_string(),
+            room: Some("general".to_string()),
+            message: "Lunch at noon?".to_string(),
+            ..Default::default()
+        };
+        let (peer, target) = private_reply(&message, None, "bob").unwrap();
+        assert_eq!(peer, "ann");
+        assert_eq!(target.id, "m1");
+        assert_eq!(target.quote.as_deref(), Some("Lunch at noon?"));
+        let (_, target) = private_reply(&message, Some("noon".to_string()), "bob").unwrap();
+        assert_eq!(target.quote.as_deref(), Some("noon"));
+
+        // Not to ourselves, and only from the room.
+        assert!(private_reply(&message, None, "ann").is_none());
+        let direct = MessageData {
+            to: Some("bob".to_string()),
+            ..message
+        };
+        assert!(private_reply(&direct, None, "bob").is_none());
+    }
+
+    #[test]
+    fn the_furthest_read_position_wins() {
+        let mut positions = HashMap::new();
+        assert!(reads_further(&positions, "general", 20.0));
+        positions.insert("general".to_string(), ReadPosition { id: "m2".to_string(), time: 20.0 });
+        // Read less far on another device.
+        assert!(!reads_further(&positions, "general", 10.0));
+        assert!(!reads_further(&positions, "general", 20.0));
+        assert!(reads_further(&positions, "general", 30.0));
+        assert!(reads_further(&positions, "random", 10.0));
+    }
+
+    #[test]
+    fn unread_messages_begin_after_the_read_position() {
+        let message = |from: &str, room: &str, time: f64| MessageData {
+            from: from.to_string(),
+            room: Some(room.to_string()),
+            time: Some(time),
+            ..Default::default()
+        };
+        let messages = [
+            message("ann", "general", 10.0),
+            message("ann", "general", 20.0),
+            // Our own and other rooms' messages don't start the unread ones.
+            message("bob", "general", 30.0),
+            message("ann", "random", 40.0),
+            message("ann", "general", 50.0),
+        ];
+        assert_eq!(unread_boundary(&messages, "general", "bob", 10.0), Some(1));
+        assert_eq!(unread_boundary(&messages, "general", "bob", 20.0), Some(4));
+        assert_eq!(unread_boundary(&messages, "random", "bob", 40.0), None);
+    }
+
+    #[test]
+    fn quote_previews_are_one_short_line() {
+        assert_eq!(quote_preview("Lunch\n  at noon?"), "Lunch at noon?");
+        let long = "word ".repeat(40);
+        let preview = quote_preview(&long);
+        assert_eq!(preview.chars().count(), QUOTE_PREVIEW_CHARS + 1);
+        assert!(preview.ends_with('…'));
+    }
+
+    #[test]
+    fn connection_notices_come_with_a_short_form() {
+        assert!(connection_notice(ConnectionState::Connected, None).is_none());
+        assert!(connection_notice(ConnectionState::Connecting, Some((1, 2))).is_none());
+        let refill = connection_notice(ConnectionState::Connected, Some((3, 10))).unwrap();
+        assert_eq!(refill.short, "3/10 back");
+        let reconnecting = connection_notice(ConnectionState::Reconnecting { attempt: 2 }, None).unwrap();
+        assert_eq!(reconnecting.text, "Connection lost. Reconnecting… (attempt 2)");
+        assert_eq!(connection_notice(ConnectionState::Offline, None).unwrap().role, "alert");
+    }
+
+    #[test]
+    fn quick_reactions_are_offered_reactions() {
+        assert_eq!(quick_reaction(&Preferences::default().quick_reaction), Some("👍"));
+        assert_eq!(quick_reaction("🎉"), Some("🎉"));
+        assert_eq!(quick_reaction(""), None);
+        assert_eq!(quick_reaction("🍕"), None);
+    }
+
+    #[test]
+    fn roster_refill_counts_people_back_until_all_are() {
+        let mut refill = RosterRefill::default();
+        refill.dropped(10);
+        // Dropping again while reconnecting keeps the first count.
+        refill.dropped(0);
+        assert_eq!(refill.shown(), None);
+        assert!(refill.roster(3));
+        assert_eq!(refill.shown(), Some((3, 10)));
+        assert!(!refill.roster(3));
+        assert!(refill.roster(7));
+        assert_eq!(refill.shown(), Some((7, 10)));
+        assert!(refill.roster(10));
+        assert_eq!(refill.shown(), None);
+        // Later roster changes are just people coming and going.
+        assert!(!refill.roster(2));
+
+        refill.dropped(5);
+        refill.roster(2);
+        assert!(refill.settle());
+        assert_eq!(refill.shown(), None);
+        // Nobody to wait for when we were alone.
+        refill.dropped(1);
+        assert!(!refill.roster(1));
+        assert_eq!(refill.shown(), None);
+    }
+
+    #[test]
+    fn garbage_message_frames_leave_the_list_alone() {
+        let mut messages = vec![MessageData {
+            from: "ann".to_string(),
+            message: "hi".to_string(),
+            ..Default::default()
+        }];
+        let frames = [
+            "not json",
+            r#"{"messageType":"message"}"#,
+            r#"{"messageType":"message","data":"not json"}"#,
+            r#"{"messageType":"message","data":"{\"message\":\"no sender\"}"}"#,
+            r#"{"messageType":"file","data":"{\"from\":\"bob\",\"message\":\"\",\"file\":{\"name\":\"x\",\"url\":\"javascript:alert(1)\"}}"}"#,
+        ];
+        for frame in frames {
+            messages.extend(parse_frame(frame).and_then(read_message));
+        }
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message, "hi");
+
+        let frame = r#"{"messageType":"message","data":"{\"from\":\"bob\",\"message\":\"yo\"}"}"#;
+        messages.extend(parse_frame(frame).and_then(read_message));
+        assert_eq!(messages.len(), 2);
+    }
+
+    #[test]
+    fn unknown_message_types_are_skipped() {
+        assert!(parse_frame(r#"{"messageType":"teleport","data":"x"}"#).is_none());
+    }
+
+    #[test]
+    fn valid_frames_are_read() {
+        let frame = r#"{"messageType":"message","data":"{\"from\":\"ann\",\"message\":\"hi\"}"}"#;
+        let msg = parse_frame(frame).expect("a valid frame");
+        assert!(matches!(msg.message_type, MsgTypes::Message));
+        let data: MessageData = serde_json::from_str(msg.data.as_deref().unwrap()).unwrap();
+        assert_eq!(data.from, "ann");
+        assert_eq!(data.message, "hi");
+    }
+
+    #[test]
+    fn meta_parts_come_in_order_and_absent_ones_are_left_out() {
+        let mut m = MessageData {
+            from: "ann".to_string(),
+            to: Some("bob".to_string()),
+            message: "hi".to_string(),
+            time: Some(1.0),
+            edited: true,
+            ..Default::default()
+        };
+        m.read_by.insert("bob".to_string());
+        let outgoing = Outgoing::new("a".to_string(), None, None, "hi".to_string(), None, 1.0);
+        assert!(matches!(
+            meta_parts(&m, true, Some(&outgoing), None, None)[..],
+            [Meta::Time(_), Meta::Edited, Meta::Seen { direct: true, .. }, Meta::Outgoing(_)]
+        ));
+        // Only the sender sees who read it.
+        assert!(matches!(meta_parts(&m, false, None, None, None)[..], [Meta::Time(_), Meta::Edited]));
+        // The latest direct message the peer saw says when.
+        assert!(matches!(
+            meta_parts(&m, true, None, None, Some(5.0))[..],
+            [_, _, Meta::Seen { at: Some(at), .. }] if at == 5.0
+        ));
+        // A day's retention counts down over the last hour.
+        assert!(matches!(
+            meta_parts(&m, false, None, Some(86_400), None)[..],
+            [Meta::Time(_), Meta::Edited, Meta::Expires { at, warn_ms }]
+                if at == 1.0 + 86_400_000.0 && warn_ms == EXPIRY_WARNING_MS
+        ));
+        assert!(matches!(
+            meta_parts(&m, false, None, Some(60), None)[..],
+            [.., Meta::Expires { warn_ms, .. }] if warn_ms == 15_000.0
+        ));
+
+        m.time = None;
+        m.deleted = true;
+        assert!(matches!(meta_parts(&m, true, None, Some(60), None)[..], [Meta::Seen { .. }]));
+        m.read_by.clear();
+        assert!(meta_parts(&m, true, None, None, None).is_empty());
+    }
 }
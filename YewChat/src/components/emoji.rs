@@ -0,0 +1,43 @@
+use yew::prelude::*;
+
+/// The emojis offered by the picker, in display order.
+const EMOJIS: &[&str] = &[
+    "😀", "😂", "🥹", "😊", "😍", "🤔", "😎", "😢",
+    "😡", "😴", "🥳", "🤯", "👍", "👎", "👏", "🙏",
+    "💪", "👋", "🎉", "🔥", "✨", "💯", "❤️", "💔",
+    "✅", "❌", "⚡", "🚀", "☕", "🍕", "🐛", "👀",
+];
+
+#[derive(Properties, PartialEq)]
+pub struct EmojiPickerProps {
+    pub on_pick: Callback<&'static str>,
+    pub on_close: Callback<()>,
+}
+
+/// A popover grid of emojis. Render it inside a `relative` wrapper next to
+/// the button that opens it; a click anywhere outside closes it.
+#[function_component(EmojiPicker)]
+pub fn emoji_picker(props: &EmojiPickerProps) -> Html {
+    let close = props.on_close.reform(|_| ());
+
+    html! {
+        <>
+            <div onclick={close} class="fixed inset-0 z-40"></div>
+            <div class="absolute bottom-12 right-0 z-50 p-2 bg-white rounded-lg shadow-lg dark:bg-gray-800 grid grid-cols-8 gap-1">
+                {
+                    EMOJIS.iter().map(|&emoji| {
+                        // Acting on mousedown, and cancelling it, keeps focus
+                        // in the message input.
+                        let onmousedown = props.on_pick.reform(move |e: MouseEvent| {
+                            e.prevent_default();
+                            emoji
+                        });
+                        html! {
+                            <button type="button" {onmousedown} class="w-8 h-8 rounded text-lg hover:bg-gray-100 dark:hover:bg-gray-700">{emoji}</button>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+        </>
+    }
+}
@@ -3,36 +3,60 @@ use yew::functional::*;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
+use crate::services::username;
 use crate::Route;
 use crate::User;
 
 #[function_component(Login)]
 pub fn login() -> Html {
-    let username = use_state(|| String::new());
+    let username = use_state(String::new);
     let user = use_context::<User>().expect("No context found.");
+    let history = use_history();
+    // Set when the chat turned the name away and sent us back here.
+    let error = {
+        let user = user.clone();
+        use_state(move || user.login_error.borrow_mut().take())
+    };
 
     let oninput = {
         let current_username = username.clone();
+        let error = error.clone();
 
         Callback::from(move |e: InputEvent| {
             let input: HtmlInputElement = e.target_unchecked_into();
             current_username.set(input.value());
+            error.set(None);
         })
     };
 
-    let onclick = {
+    let onsubmit = {
         let username = username.clone();
-        let user = user.clone();
-        Callback::from(move |_| *user.username.borrow_mut() = (*username).clone())
+        let error = error.clone();
+        Callback::from(move |e: FocusEvent| {
+            e.prevent_default();
+            match username::validate(&username) {
+                Ok(name) => {
+                    *user.username.borrow_mut() = name;
+                    if let Some(history) = &history {
+                        history.push(Route::Chat);
+                    }
+                }
+                Err(e) => error.set(Some(e.to_string())),
+            }
+        })
     };
 
     html! {
        <div class="bg-gray-800 flex w-screen">
             <div class="container mx-auto flex flex-col justify-center items-center">
-                <form class="m-4 flex">
-                    <input {oninput} class="rounded-l-lg p-4 border-t mr-0 border-b border-l text-gray-800 border-gray-200 bg-white" placeholder="Username" />
-                    <Link<Route> to={Route::Chat}> <button {onclick} disabled={username.len()<1} class="px-8 rounded-r-lg bg-violet-600	  text-white font-bold p-4 uppercase border-violet-600 border-t border-b border-r" >{"Go Chatting!"}</button></Link<Route>>
+                <form {onsubmit} class="m-4 flex">
+                    <input {oninput} class="rounded-l-lg p-4 border-t mr-0 border-b border-l text-gray-800 border-gray-200 bg-white" placeholder="Username"
+                        aria-label="Username" aria-invalid={error.is_some().to_string()} />
+                    <button type="submit" disabled={username.is_empty()} class="px-8 rounded-r-lg bg-violet-600	  text-white font-bold p-4 uppercase border-violet-600 border-t border-b border-r" >{"Go Chatting!"}</button>
                 </form>
+                if let Some(error) = &*error {
+                    <div role="alert" class="text-sm text-red-300">{error.clone()}</div>
+                }
             </div>
         </div>
     }
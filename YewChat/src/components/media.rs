@@ -0,0 +1,52 @@
+/// Media a message can be shown as instead of text, when the whole message
+/// is a link to it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Media {
+    Gif,
+    /// An `.mp4` or `.webm` file, played by the browser.
+    Video,
+    /// A YouTube video, with its id.
+    YouTube(String),
+}
+
+/// Decides how to show `text`, or returns `None` to show it as text.
+pub fn media_kind(text: &str) -> Option<Media> {
+    if text.ends_with(".gif") {
+        return Some(Media::Gif);
+    }
+    let url = text.trim();
+    if url.contains(char::is_whitespace) || !(url.starts_with("https://") || url.starts_with("http://")) {
+        return None;
+    }
+    if let Some(id) = youtube_id(url) {
+        return Some(Media::YouTube(id.to_string()));
+    }
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    if path.ends_with(".mp4") || path.ends_with(".webm") {
+        return Some(Media::Video);
+    }
+    None
+}
+
+/// The player URL for a YouTube video. The no-cookie domain keeps YouTube
+/// from tracking people who only scroll past it.
+pub fn youtube_embed_url(id: &str) -> String {
+    format!("https://www.youtube-nocookie.com/embed/{}", id)
+}
+
+/// The video id of a YouTube watch link (`youtube.com/watch?v=…`) or
+/// share link (`youtu.be/…`).
+fn youtube_id(url: &str) -> Option<&str> {
+    let rest = url.split_once("://")?.1;
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let id = match host.trim_start_matches("www.").trim_start_matches("m.") {
+        "youtu.be" => path.split(['?', '#']).next()?,
+        "youtube.com" => {
+            let query = path.strip_prefix("watch?")?;
+            query.split(['&', '#']).find_map(|pair| pair.strip_prefix("v="))?
+        }
+        _ => return None,
+    };
+    let valid = id.len() == 11 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then_some(id)
+}
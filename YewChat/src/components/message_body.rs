@@ -0,0 +1,610 @@
+use std::collections::HashMap;
+
+use yew::prelude::*;
+
+use crate::components::syntax::highlight_code;
+use crate::services::username::is_name_char;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Block<'a> {
+    /// Lines of ordinary text.
+    Text(Vec<&'a str>),
+    /// A fenced code block; its contents are never parsed further.
+    Code { lang: &'a str, code: Vec<&'a str> },
+    Table {
+        header: Vec<&'a str>,
+        align: Vec<Align>,
+        rows: Vec<Vec<&'a str>>,
+    },
+}
+
+/// Splits a message into blocks.
+///
+/// - A line starting with ` ``` ` opens a code block that runs to the next
+///   line consisting of just ` ``` `. Without a closing fence the lines stay
+///   literal text.
+/// - A table is a header row, a separator row and any number of data rows,
+///   each starting with `|`. The separator has one `---` cell per header
+///   cell, with optional `:` on either side to set the column alignment.
+///   Data rows are padded or truncated to the header's width. A header
+///   without a matching separator is literal text.
+/// - Anything else is text.
+pub fn parse_blocks(text: &str) -> Vec<Block<'_>> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut blocks = Vec::new();
+    let mut pending_text: Vec<&str> = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        if let Some(lang) = line.trim_start().strip_prefix("```") {
+            let close = lines[i + 1..]
+                .iter()
+                .position(|l| l.trim() == "```")
+                .map(|offset| i + 1 + offset);
+            if let Some(close) = close {
+                flush_text(&mut blocks, &mut pending_text);
+                blocks.push(Block::Code {
+                    lang: lang.trim(),
+                    code: lines[i + 1..close].to_vec(),
+                });
+                i = close + 1;
+                continue;
+            }
+        }
+
+        if let Some(header) = table_row(line) {
+            let align = lines
+                .get(i + 1)
+                .and_then(|l| table_separator(l))
+                .filter(|align| align.len() == header.len());
+            if let Some(align) = align {
+                flush_text(&mut blocks, &mut pending_text);
+                let mut rows = Vec::new();
+                i += 2;
+                while let Some(mut row) = lines.get(i).and_then(|l| table_row(l)) {
+                    row.resize(header.len(), "");
+                    rows.push(row);
+                    i += 1;
+                }
+                blocks.push(Block::Table { header, align, rows });
+                continue;
+            }
+        }
+
+        pending_text.push(line);
+        i += 1;
+    }
+
+    flush_text(&mut blocks, &mut pending_text);
+    blocks
+}
+
+fn flush_text<'a>(blocks: &mut Vec<Block<'a>>, pending: &mut Vec<&'a str>) {
+    if !pending.is_empty() {
+        blocks.push(Block::Text(std::mem::take(pending)));
+    }
+}
+
+/// The trimmed cells of a `| a | b |` row, or `None` if the line isn't one.
+fn table_row(line: &str) -> Option<Vec<&str>> {
+    let inner = line.trim().strip_prefix('|')?;
+    let inner = inner.strip_suffix('|').unwrap_or(inner);
+    Some(inner.split('|').map(str::trim).collect())
+}
+
+/// The column alignments of a `|---|:-:|` separator row.
+fn table_separator(line: &str) -> Option<Vec<Align>> {
+    table_row(line)?
+        .into_iter()
+        .map(|cell| {
+            let left = cell.starts_with(':');
+            let right = cell.ends_with(':');
+            let dashes = cell.trim_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (left, right) {
+                (true, true) => Align::Center,
+                (false, true) => Align::Right,
+                _ => Align::Left,
+            })
+        })
+        .collect()
+}
+
+/// Renders a message as Yew nodes. The Markdown-like subset is parsed by
+/// hand and all text is emitted as text nodes, never as raw HTML, so other
+/// users' messages can't inject markup.
+pub fn render_message_body(text: &str, highlights: &Highlights<'_>) -> Html {
+    parse_blocks(text)
+        .into_iter()
+        .map(|block| render_block(block, highlights))
+        .collect()
+}
+
+/// What to emphasize while rendering a message.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Highlights<'a> {
+    /// Matches of this search query are marked, see [`highlight`].
+    pub query: &'a str,
+    /// Mentions of this user stand out from other mentions.
+    pub me: &'a str,
+    /// Makes mentions of others clickable. Without it they are only
+    /// highlighted.
+    pub mention_links: Option<&'a MentionLinks>,
+}
+
+/// Who mentions can open a conversation with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MentionLinks {
+    /// The names of the people here, keyed by their lowercase form.
+    pub present: HashMap<String, String>,
+    /// Called with the name of the person whose mention was clicked.
+    pub open: Callback<String>,
+}
+
+impl MentionLinks {
+    /// The name, as its owner spells it, of the person mentioned as
+    /// `@mention`, if they are still here.
+    pub fn resolve(&self, mention: &str) -> Option<&str> {
+        self.present.get(&mention.to_lowercase()).map(String::as_str)
+    }
+}
+
+fn render_block(block: Block<'_>, highlights: &Highlights<'_>) -> Html {
+    match block {
+        Block::Text(lines) => lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                html! {
+                    <>
+                        if i > 0 { <br/> }
+                        {render_inline(line, highlights)}
+                    </>
+                }
+            })
+            .collect(),
+        Block::Code { lang, code } => html! {
+            <pre class="my-1 p-2 max-w-full overflow-x-auto rounded bg-gray-800 text-gray-100 font-mono text-left whitespace-pre">
+                <code>{highlight_code(&code.join("\n"), lang, highlights.query)}</code>
+            </pre>
+        },
+        Block::Table { header, align, rows } => {
+            let style = |column: usize| match align[column] {
+                Align::Left => "text-align: left;",
+                Align::Center => "text-align: center;",
+                Align::Right => "text-align: right;",
+            };
+            html! {
+                <div class="my-1 max-w-full overflow-x-auto">
+                    <table class="border-collapse text-xs">
+                        <thead>
+                            <tr>
+                                { for header.iter().enumerate().map(|(c, cell)| html! {
+                                    <th class="border border-gray-300 px-2 py-1 font-semibold bg-white bg-opacity-50" style={style(c)}>{render_inline(cell, highlights)}</th>
+                                }) }
+                            </tr>
+                        </thead>
+                        <tbody>
+                            { for rows.iter().map(|row| html! {
+                                <tr>
+                                    { for row.iter().enumerate().map(|(c, cell)| html! {
+                                        <td class="border border-gray-300 px-2 py-1" style={style(c)}>{render_inline(cell, highlights)}</td>
+                                    }) }
+                                </tr>
+                            }) }
+                        </tbody>
+                    </table>
+                </div>
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Inline<'a> {
+    Text(&'a str),
+    /// Text between backticks; its contents are never parsed further.
+    Code(&'a str),
+    Bold(Vec<Inline<'a>>),
+    Italic(Vec<Inline<'a>>),
+    /// `[text](url)`, only for `http`, `https` and `mailto` URLs.
+    Link { text: Vec<Inline<'a>>, url: &'a str },
+    /// Text between `||` markers, hidden until clicked.
+    Spoiler(&'a str),
+    /// `@name`, holding the name without the `@`.
+    Mention(&'a str),
+}
+
+/// Splits a line into inline spans:
+///
+/// - `` `code` ``, `**bold**`, `_italic_` or `*italic*`, `||spoiler||`,
+///   `[text](url)` and `@name`. Bold, italic and link text may nest other
+///   spans.
+/// - Bare `http` and `https` URLs become links. Trailing punctuation is
+///   left out, so "see https://example.com." links without the period.
+/// - Italic markers and `@` only count at word boundaries, so
+///   `snake_case_names` and `me@example.com` stay as they are. A mention
+///   runs over letters, digits, `_` and `-`.
+/// - A marker without a closing partner, a pair with nothing between, or a
+///   link to any other kind of URL stays literal.
+pub fn parse_inline(text: &str) -> Vec<Inline<'_>> {
+    let mut spans = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+
+    while let Some(c) = text[i..].chars().next() {
+        match parse_span(text, i) {
+            Some((span, len)) => {
+                if literal_start < i {
+                    spans.push(Inline::Text(&text[literal_start..i]));
+                }
+                spans.push(span);
+                i += len;
+                literal_start = i;
+            }
+            None => i += c.len_utf8(),
+        }
+    }
+    if literal_start < text.len() {
+        spans.push(Inline::Text(&text[literal_start..]));
+    }
+    spans
+}
+
+/// Parses the span starting at byte `at`, if there is one, returning it
+/// with its length in bytes.
+fn parse_span(text: &str, at: usize) -> Option<(Inline<'_>, usize)> {
+    let rest = &text[at..];
+
+    if let Some(len) = bare_url_len(text, at) {
+        let url = &rest[..len];
+        return Some((Inline::Link { text: vec![Inline::Text(url)], url }, len));
+    }
+    if let Some(inner) = rest.strip_prefix('`') {
+        let len = inner.find('`').filter(|&len| len > 0)?;
+        return Some((Inline::Code(&inner[..len]), len + 2));
+    }
+    if let Some(inner) = rest.strip_prefix("||") {
+        let len = inner.find("||").filter(|&len| len > 0)?;
+        return Some((Inline::Spoiler(&inner[..len]), len + 4));
+    }
+    if let Some(inner) = rest.strip_prefix("**") {
+        let len = inner.find("**").filter(|&len| len > 0)?;
+        return Some((Inline::Bold(parse_inline(&inner[..len])), len + 4));
+    }
+    if let Some(marker) = rest.chars().next().filter(|c| matches!(c, '_' | '*')) {
+        let before = text[..at].chars().next_back();
+        let inner = &rest[1..];
+        if before.map(char::is_alphanumeric).unwrap_or(false)
+            || inner.starts_with(char::is_whitespace)
+        {
+            return None;
+        }
+        let len = inner.match_indices(marker).map(|(i, _)| i).find(|&i| {
+            let closes_word = !inner[..i].ends_with(char::is_whitespace);
+            let after = inner[i + 1..].chars().next();
+            i > 0 && closes_word && !after.map(char::is_alphanumeric).unwrap_or(false)
+        })?;
+        return Some((Inline::Italic(parse_inline(&inner[..len])), len + 2));
+    }
+    if let Some(inner) = rest.strip_prefix('@') {
+        if text[..at].chars().next_back().map(char::is_alphanumeric).unwrap_or(false) {
+            return None;
+        }
+        let len = inner
+            .find(|c: char| !is_name_char(c))
+            .unwrap_or(inner.len());
+        if len == 0 {
+            return None;
+        }
+        return Some((Inline::Mention(&inner[..len]), len + 1));
+    }
+    if let Some(inner) = rest.strip_prefix('[') {
+        let label_len = inner.find("](").filter(|&len| len > 0)?;
+        let target = &inner[label_len + 2..];
+        let url = &target[..target.find(')')?];
+        if !is_safe_url(url) {
+            return None;
+        }
+        let span = Inline::Link {
+            text: parse_inline(&inner[..label_len]),
+            url,
+        };
+        return Some((span, 1 + label_len + 2 + url.len() + 1));
+    }
+    None
+}
+
+/// The length of the bare URL starting at byte `at`, if one does.
+fn bare_url_len(text: &str, at: usize) -> Option<usize> {
+    let rest = &text[at..];
+    let lower = rest.get(..8).unwrap_or(rest).to_ascii_lowercase();
+    if !(lower.starts_with("http://") || lower.starts_with("https://")) {
+        return None;
+    }
+    if text[..at].chars().next_back().map(char::is_alphanumeric).unwrap_or(false) {
+        return None;
+    }
+    let mut url = &rest[..rest.find(char::is_whitespace).unwrap_or(rest.len())];
+    // Keep a closing parenthesis only if the URL opened one, as in
+    // Wikipedia links.
+    while let Some(last) = url.chars().next_back() {
+        let dangling_paren = last == ')' && url.matches('(').count() < url.matches(')').count();
+        if matches!(last, '.' | ',' | ';' | ':' | '!' | '?' | '\'' | '"') || dangling_paren {
+            url = &url[..url.len() - 1];
+        } else {
+            break;
+        }
+    }
+    is_safe_url(url).then_some(url.len())
+}
+
+/// Whether `text` mentions `name` as `@name`, ignoring case. Mentions in
+/// code don't count, and neither do longer names starting with `name`.
+pub fn mentions(text: &str, name: &str) -> bool {
+    let name = name.to_lowercase();
+    let in_line = |line: &str| spans_mention(&parse_inline(line), &name);
+    parse_blocks(text).iter().any(|block| match block {
+        Block::Text(lines) => lines.iter().any(|line| in_line(line)),
+        Block::Code { .. } => false,
+        Block::Table { header, rows, .. } => header
+            .iter()
+            .chain(rows.iter().flatten())
+            .any(|cell| in_line(cell)),
+    })
+}
+
+/// `name` is already lowercase.
+fn spans_mention(spans: &[Inline<'_>], name: &str) -> bool {
+    spans.iter().any(|span| match span {
+        Inline::Mention(mention) => mention.to_lowercase() == name,
+        Inline::Bold(spans) | Inline::Italic(spans) | Inline::Link { text: spans, .. } => {
+            spans_mention(spans, name)
+        }
+        Inline::Text(_) | Inline::Code(_) | Inline::Spoiler(_) => false,
+    })
+}
+
+/// Whether a link target is safe to put in an `href`. This keeps out
+/// `javascript:` and other schemes that run code or read local data.
+fn is_safe_url(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    !url.contains(char::is_whitespace)
+        && ["http://", "https://", "mailto:"]
+            .iter()
+            .any(|scheme| lower.starts_with(scheme) && lower.len() > scheme.len())
+}
+
+/// Renders a single line of text.
+fn render_inline(text: &str, highlights: &Highlights<'_>) -> Html {
+    render_spans(parse_inline(text), highlights)
+}
+
+fn render_spans(spans: Vec<Inline<'_>>, highlights: &Highlights<'_>) -> Html {
+    let query = highlights.query;
+    spans
+        .into_iter()
+        .map(|span| match span {
+            Inline::Text(text) => html! { <span>{highlight(text, query)}</span> },
+            Inline::Code(code) => html! {
+                <code class="px-1 rounded bg-gray-800 bg-opacity-10 font-mono">{highlight(code, query)}</code>
+            },
+            Inline::Bold(spans) => html! { <strong>{render_spans(spans, highlights)}</strong> },
+            Inline::Italic(spans) => html! { <em>{render_spans(spans, highlights)}</em> },
+            Inline::Link { text, url } => html! {
+                <a href={url.to_string()} target="_blank" rel="noopener noreferrer" class="underline text-blue-600 hover:text-blue-800">
+                    {render_spans(text, highlights)}
+                </a>
+            },
+            Inline::Spoiler(text) => html! { <Spoiler text={text.to_string()} /> },
+            Inline::Mention(name) => {
+                let text = highlight(&format!("@{}", name), query);
+                if name.to_lowercase() == highlights.me.to_lowercase() {
+                    return html! { <span class="px-0.5 rounded bg-yellow-200 font-semibold text-yellow-900">{text}</span> };
+                }
+                // A chip for someone here opens a conversation with them;
+                // one for someone who left is disabled.
+                match highlights.mention_links.map(|links| (links, links.resolve(name))) {
+                    None => html! { <span class="font-semibold text-blue-700">{text}</span> },
+                    Some((links, Some(name))) => {
+                        let name = name.to_string();
+                        let title = format!("Message {}", name);
+                        let open = links.open.clone();
+                        let onclick = Callback::from(move |_: MouseEvent| open.emit(name.clone()));
+                        html! {
+                            <button type="button" {onclick} {title} class="font-semibold text-blue-700 hover:underline">{text}</button>
+                        }
+                    }
+                    Some((_, None)) => html! {
+                        <button type="button" disabled=true title={format!("{} is no longer here", name)}
+                            class="font-semibold text-gray-400 cursor-not-allowed">{text}</button>
+                    },
+                }
+            }
+        })
+        .collect()
+}
+
+/// Whether `text` contains `query`, ignoring case. Everything matches an
+/// empty query.
+pub fn contains_ignore_case(text: &str, query: &str) -> bool {
+    query.is_empty() || text.char_indices().any(|(i, _)| match_len(&text[i..], query).is_some())
+}
+
+/// The byte length of the match of `query` at the start of `text`, ignoring
+/// case.
+fn match_len(text: &str, query: &str) -> Option<usize> {
+    let mut chars = text.char_indices();
+    for q in query.chars() {
+        let (_, c) = chars.next()?;
+        if !c.to_lowercase().eq(q.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(chars.next().map_or(text.len(), |(i, _)| i))
+}
+
+/// Renders `text` with every case-insensitive match of `query` wrapped in
+/// a `<mark>`. Matches don't overlap; an empty query marks nothing.
+pub fn highlight(text: &str, query: &str) -> Html {
+    if query.is_empty() {
+        return html! { text.to_string() };
+    }
+    let mut nodes = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+
+    while let Some(c) = text[i..].chars().next() {
+        match match_len(&text[i..], query) {
+            Some(len) => {
+                if literal_start < i {
+                    nodes.push(html! { text[literal_start..i].to_string() });
+                }
+                nodes.push(html! { <mark class="rounded-sm bg-yellow-200">{text[i..i + len].to_string()}</mark> });
+                i += len;
+                literal_start = i;
+            }
+            None => i += c.len_utf8(),
+        }
+    }
+    if literal_start < text.len() {
+        nodes.push(html! { text[literal_start..].to_string() });
+    }
+    nodes.into_iter().collect()
+}
+
+#[derive(Properties, PartialEq)]
+struct SpoilerProps {
+    text: String,
+}
+
+/// Hidden text that stays revealed once clicked.
+#[function_component(Spoiler)]
+fn spoiler(props: &SpoilerProps) -> Html {
+    let revealed = use_state(|| false);
+    let onclick = {
+        let revealed = revealed.clone();
+        Callback::from(move |_| revealed.set(true))
+    };
+
+    if *revealed {
+        html! { <span class="px-0.5 rounded bg-gray-400 bg-opacity-30">{props.text.clone()}</span> }
+    } else {
+        html! {
+            <span {onclick} title="Spoiler — click to reveal" class="px-0.5 rounded bg-gray-700 text-transparent cursor-pointer select-none">
+                {props.text.clone()}
+            </span>
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::username;
+
+    #[test]
+    fn parses_tables_with_alignment() {
+        let blocks = parse_blocks("| a | b | c |\n|:--|:-:|--:|\n| 1 | 2 | 3 |\nafter");
+        assert_eq!(
+            blocks,
+            [
+                Block::Table {
+                    header: vec!["a", "b", "c"],
+                    align: vec![Align::Left, Align::Center, Align::Right],
+                    rows: vec![vec!["1", "2", "3"]],
+                },
+                Block::Text(vec!["after"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn fits_rows_to_the_header() {
+        let blocks = parse_blocks("| a | b |\n|---|---|\n| 1 |\n| 1 | 2 | 3 |");
+        assert_eq!(
+            blocks,
+            [Block::Table {
+                header: vec!["a", "b"],
+                align: vec![Align::Left, Align::Left],
+                rows: vec![vec!["1", ""], vec!["1", "2"]],
+            }]
+        );
+    }
+
+    #[test]
+    fn tables_without_a_matching_separator_stay_text() {
+        assert_eq!(parse_blocks("| a | b |\n| 1 | 2 |"), [Block::Text(vec!["| a | b |", "| 1 | 2 |"])]);
+        assert_eq!(parse_blocks("| a | b |\n|---|"), [Block::Text(vec!["| a | b |", "|---|"])]);
+        assert_eq!(parse_blocks("| a |\n|-x-|"), [Block::Text(vec!["| a |", "|-x-|"])]);
+    }
+
+    #[test]
+    fn tables_in_code_fences_stay_code() {
+        assert_eq!(
+            parse_blocks("```\n| a |\n|---|\n```"),
+            [Block::Code {
+                lang: "",
+                code: vec!["| a |", "|---|"],
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_spoilers() {
+        assert_eq!(parse_inline("||x||"), [Inline::Spoiler("x")]);
+        assert_eq!(
+            parse_inline("a ||b c|| d"),
+            [Inline::Text("a "), Inline::Spoiler("b c"), Inline::Text(" d")]
+        );
+    }
+
+    #[test]
+    fn malformed_spoilers_stay_literal() {
+        assert_eq!(parse_inline("||x"), [Inline::Text("||x")]);
+        assert_eq!(parse_inline("||||"), [Inline::Text("||||")]);
+    }
+
+    #[test]
+    fn spoilers_in_code_stay_code() {
+        assert_eq!(parse_inline("`||x||`"), [Inline::Code("||x||")]);
+        assert_eq!(
+            parse_blocks("```\n||x||\n```"),
+            [Block::Code {
+                lang: "",
+                code: vec!["||x||"],
+            }]
+        );
+    }
+
+    #[test]
+    fn mentions_resolve_to_the_people_here() {
+        let links = MentionLinks {
+            present: [("ann_lee".to_string(), "Ann_Lee".to_string())].into_iter().collect(),
+            open: Callback::noop(),
+        };
+        assert_eq!(links.resolve("ann_lee"), Some("Ann_Lee"));
+        assert_eq!(links.resolve("ANN_LEE"), Some("Ann_Lee"));
+        assert_eq!(links.resolve("bob"), None);
+    }
+
+    #[test]
+    fn legal_usernames_are_mentioned() {
+        let name = username::validate(" Ann_Lee-2 ").unwrap();
+        assert!(mentions(&format!("hi @{}, look", name), &name));
+        assert!(mentions("@ann_lee-2", &name));
+        assert!(!mentions("@Ann_Lee-23", &name));
+        assert!(!mentions("`@Ann_Lee-2`", &name));
+    }
+}
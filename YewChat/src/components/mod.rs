@@ -1,2 +1,12 @@
+pub mod avatar;
 pub mod chat;
+pub mod emoji;
 pub mod login;
+pub mod media;
+pub mod message_body;
+pub mod palette;
+pub mod relative_time;
+pub mod settings;
+pub mod shortcuts;
+pub mod syntax;
+pub mod toolbar;
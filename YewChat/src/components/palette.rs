@@ -0,0 +1,189 @@
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    ShowShortcuts,
+    ToggleSettings,
+    ToggleCompactRoster,
+    ToggleGifAutoplay,
+    FocusInput,
+    OpenNotes,
+    Mention(String),
+}
+
+/// An entry in the command palette.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaletteItem {
+    pub label: String,
+    /// Short category shown on the right, e.g. "Action" or "User".
+    pub hint: &'static str,
+    pub command: Command,
+}
+
+impl PaletteItem {
+    fn action(label: &str, command: Command) -> Self {
+        Self {
+            label: label.to_string(),
+            hint: "Action",
+            command,
+        }
+    }
+}
+
+/// The fixed actions the palette offers, in the order shown for an empty
+/// query. Items for the current roster are appended by the caller.
+pub fn actions() -> Vec<PaletteItem> {
+    vec![
+        PaletteItem::action("Focus message input", Command::FocusInput),
+        PaletteItem::action("Open Saved Messages", Command::OpenNotes),
+        PaletteItem::action("Open settings", Command::ToggleSettings),
+        PaletteItem::action("Toggle compact user list", Command::ToggleCompactRoster),
+        PaletteItem::action("Toggle GIF autoplay", Command::ToggleGifAutoplay),
+        PaletteItem::action("Show keyboard shortcuts", Command::ShowShortcuts),
+    ]
+}
+
+/// Builds the palette item that mentions `username` in the message input.
+pub fn mention(username: &str) -> PaletteItem {
+    PaletteItem {
+        label: format!("@{}", username),
+        hint: "User",
+        command: Command::Mention(username.to_string()),
+    }
+}
+
+/// Scores `candidate` against `query` as a case-insensitive subsequence
+/// match, or returns `None` if some query character can't be matched in
+/// order. Consecutive matches and matches at the start of a word score
+/// higher, so "cu" prefers "Compact user list" over "Focus message input".
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<usize> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut previous: Option<usize> = None;
+
+    for q in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = pos + candidate[pos..].iter().position(|&c| c == q)?;
+        score += 1;
+        if previous.map(|p| p + 1 == found).unwrap_or(false) {
+            score += 5;
+        }
+        if found == 0 || matches!(candidate[found - 1], ' ' | '@' | '#') {
+            score += 3;
+        }
+        previous = Some(found);
+        pos = found + 1;
+    }
+    Some(score)
+}
+
+/// Returns the items matching `query`, best match first. Ties keep their
+/// original order.
+fn filter<'a>(items: &'a [PaletteItem], query: &str) -> Vec<&'a PaletteItem> {
+    let mut scored: Vec<(usize, &PaletteItem)> = items
+        .iter()
+        .filter_map(|item| fuzzy_score(query, &item.label).map(|score| (score, item)))
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, item)| item).collect()
+}
+
+#[derive(Properties, PartialEq)]
+pub struct CommandPaletteProps {
+    pub items: Vec<PaletteItem>,
+    pub on_select: Callback<Command>,
+    pub on_close: Callback<()>,
+}
+
+#[function_component(CommandPalette)]
+pub fn command_palette(props: &CommandPaletteProps) -> Html {
+    let query = use_state(String::new);
+    let selected = use_state(|| 0usize);
+    let input_ref = use_node_ref();
+
+    {
+        let input_ref = input_ref.clone();
+        use_effect_with_deps(
+            move |_| {
+                if let Some(input) = input_ref.cast::<HtmlInputElement>() {
+                    let _ = input.focus();
+                }
+                || ()
+            },
+            (),
+        );
+    }
+
+    let matches = filter(&props.items, &query);
+    let selected_index = (*selected).min(matches.len().saturating_sub(1));
+
+    let oninput = {
+        let query = query.clone();
+        let selected = selected.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            query.set(input.value());
+            selected.set(0);
+        })
+    };
+
+    let onkeydown = {
+        let selected = selected.clone();
+        let commands: Vec<Command> = matches.iter().map(|item| item.command.clone()).collect();
+        let on_select = props.on_select.clone();
+        Callback::from(move |e: KeyboardEvent| match e.key().as_str() {
+            "ArrowDown" => {
+                e.prevent_default();
+                selected.set((selected_index + 1).min(commands.len().saturating_sub(1)));
+            }
+            "ArrowUp" => {
+                e.prevent_default();
+                selected.set(selected_index.saturating_sub(1));
+            }
+            "Enter" => {
+                e.prevent_default();
+                if let Some(command) = commands.get(selected_index) {
+                    on_select.emit(command.clone());
+                }
+            }
+            _ => {}
+        })
+    };
+
+    let close = props.on_close.reform(|_| ());
+    let stop = Callback::from(|e: MouseEvent| e.stop_propagation());
+
+    html! {
+        <div onclick={close} class="fixed inset-0 z-50 bg-black bg-opacity-50 flex justify-center items-start pt-24">
+            <div onclick={stop} class="bg-white rounded-lg shadow-lg w-96 overflow-hidden dark:bg-gray-800 dark:text-gray-100">
+                <input ref={input_ref} {oninput} {onkeydown} value={(*query).clone()} type="text"
+                    placeholder="Type a command or @user…"
+                    class="w-full px-4 py-3 border-b border-gray-200 outline-none dark:bg-gray-800 dark:border-gray-700" />
+                <div class="max-h-80 overflow-auto">
+                    {
+                        if matches.is_empty() {
+                            html! { <div class="px-4 py-3 text-sm text-gray-400 italic">{"No matches"}</div> }
+                        } else {
+                            matches.iter().enumerate().map(|(i, item)| {
+                                let command = item.command.clone();
+                                let onclick = props.on_select.reform(move |_| command.clone());
+                                let class = if i == selected_index {
+                                    "bg-blue-100"
+                                } else {
+                                    "hover:bg-gray-100"
+                                };
+                                html! {
+                                    <div {onclick} class={format!("flex justify-between px-4 py-2 text-sm cursor-pointer {}", class)}>
+                                        <span>{item.label.clone()}</span>
+                                        <span class="text-xs text-gray-400">{item.hint}</span>
+                                    </div>
+                                }
+                            }).collect::<Html>()
+                        }
+                    }
+                </div>
+            </div>
+        </div>
+    }
+}
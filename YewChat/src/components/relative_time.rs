@@ -0,0 +1,179 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use yew::prelude::*;
+
+/// How often `Clock::tick` should be called.
+pub const CLOCK_TICK_MS: u32 = 30_000;
+
+/// Times older than this are shown as the time of day.
+const RELATIVE_FOR_MS: f64 = 60.0 * 60.0 * 1000.0;
+
+/// Tells every `RelativeTime` label below it in a context to bring
+/// itself up to date. Only the labels re-render, not what contains them.
+#[derive(Clone, Default)]
+pub struct Clock {
+    subscribers: Rc<RefCell<HashMap<usize, Callback<()>>>>,
+    next_id: Rc<Cell<usize>>,
+}
+
+impl Clock {
+    pub fn tick(&self) {
+        // Copied first, as a label may unsubscribe while re-rendering.
+        let subscribers: Vec<Callback<()>> = self.subscribers.borrow().values().cloned().collect();
+        for subscriber in subscribers {
+            subscriber.emit(());
+        }
+    }
+
+    fn subscribe(&self, callback: Callback<()>) -> usize {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.subscribers.borrow_mut().insert(id, callback);
+        id
+    }
+
+    fn unsubscribe(&self, id: usize) {
+        self.subscribers.borrow_mut().remove(&id);
+    }
+}
+
+impl PartialEq for Clock {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.subscribers, &other.subscribers)
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct RelativeTimeProps {
+    /// Epoch millis.
+    pub time: f64,
+    #[prop_or_default]
+    pub class: Classes,
+}
+
+/// `time` as "just now" or "5m ago", turning into the time of day after
+/// an hour. Kept current by the `Clock` in context, if there is one.
+#[function_component(RelativeTime)]
+pub fn relative_time(props: &RelativeTimeProps) -> Html {
+    let time = props.time;
+    let label = use_clock_label(time, relative_label);
+
+    html! {
+        <time class={props.class.clone()} title={format_time(time)}>{label}</time>
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ExpiresInProps {
+    /// When the server deletes the message, in epoch millis.
+    pub time: f64,
+    /// The countdown shows once this little time is left.
+    pub warn_ms: f64,
+    /// Whether a " · " goes before the countdown, once it shows.
+    #[prop_or_default]
+    pub separated: bool,
+}
+
+/// A countdown to a message's deletion, e.g. "disappears in 5m". Shows
+/// nothing until `warn_ms` before it, and kept current like
+/// `RelativeTime`.
+#[function_component(ExpiresIn)]
+pub fn expires_in(props: &ExpiresInProps) -> Html {
+    let warn_ms = props.warn_ms;
+    let label = use_clock_label(props.time, move |time, now| expiry_label(time, now, warn_ms).unwrap_or_default());
+    if label.is_empty() {
+        return html! {};
+    }
+    html! {
+        <>
+            if props.separated {
+                {" · "}
+            }
+            <span class="text-orange-500" title={format!("Deleted by the server at {}", format_time(props.time))}>{label}</span>
+        </>
+    }
+}
+
+/// `label(time, now)`, worked out again on every tick of the `Clock` in
+/// context.
+fn use_clock_label(time: f64, label: impl Fn(f64, f64) -> String + 'static) -> String {
+    let clock = use_context::<Clock>();
+    let label = Rc::new(label);
+    let current = {
+        let label = label.clone();
+        use_state_eq(move || label(time, js_sys::Date::now()))
+    };
+    {
+        let current = current.clone();
+        use_effect_with_deps(
+            move |(clock, time)| {
+                let time = *time;
+                current.set(label(time, js_sys::Date::now()));
+                let subscription = clock.clone().map(|clock| {
+                    let id = clock.subscribe(Callback::from(move |_| current.set(label(time, js_sys::Date::now()))));
+                    (clock, id)
+                });
+                move || {
+                    if let Some((clock, id)) = subscription {
+                        clock.unsubscribe(id);
+                    }
+                }
+            },
+            (clock, time),
+        );
+    }
+    (*current).clone()
+}
+
+fn relative_label(time: f64, now: f64) -> String {
+    let elapsed = now - time;
+    if elapsed >= RELATIVE_FOR_MS {
+        format_time(time)
+    } else if elapsed < 60_000.0 {
+        "just now".to_string()
+    } else {
+        format!("{}m ago", (elapsed / 60_000.0) as u32)
+    }
+}
+
+/// How long until `time`, once no more than `warn_ms` is left, e.g.
+/// "disappears in 5m". Rounded up, so it counts down to "in 1m".
+fn expiry_label(time: f64, now: f64, warn_ms: f64) -> Option<String> {
+    let left = time - now;
+    if left > warn_ms {
+        return None;
+    }
+    Some(if left <= 0.0 {
+        "disappearing".to_string()
+    } else if left <= RELATIVE_FOR_MS {
+        format!("disappears in {}m", (left / 60_000.0).ceil() as u32)
+    } else {
+        format!("disappears in {}h", (left / RELATIVE_FOR_MS).ceil() as u32)
+    })
+}
+
+/// Formats epoch millis as local "HH:MM".
+pub fn format_time(millis: f64) -> String {
+    let date = js_sys::Date::new(&millis.into());
+    format!("{:02}:{:02}", date.get_hours(), date.get_minutes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: f64 = 60_000.0;
+
+    #[test]
+    fn expiry_counts_down_only_near_the_end() {
+        let warn_ms = 60.0 * MINUTE;
+        assert_eq!(expiry_label(100.0 * MINUTE, 0.0, warn_ms), None);
+        assert_eq!(expiry_label(60.0 * MINUTE, 0.0, warn_ms).as_deref(), Some("disappears in 60m"));
+        assert_eq!(expiry_label(4.5 * MINUTE, 0.0, warn_ms).as_deref(), Some("disappears in 5m"));
+        assert_eq!(expiry_label(10.0, 0.0, warn_ms).as_deref(), Some("disappears in 1m"));
+        assert_eq!(expiry_label(0.0, 10.0, warn_ms).as_deref(), Some("disappearing"));
+        assert_eq!(expiry_label(150.0 * MINUTE, 0.0, 240.0 * MINUTE).as_deref(), Some("disappears in 3h"));
+    }
+}
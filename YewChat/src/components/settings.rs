@@ -0,0 +1,293 @@
+use gloo::file::callbacks::FileReader;
+use web_sys::{HtmlInputElement, HtmlSelectElement, HtmlTextAreaElement};
+use yew::prelude::*;
+
+use crate::components::chat::REACTIONS;
+use crate::services::download::download;
+use crate::services::preferences::{ConnectionNotice, FontSize, Preferences, MAX_GREETING_LEN};
+
+#[derive(Properties, PartialEq)]
+pub struct SettingsProps {
+    pub preferences: Preferences,
+    pub on_change: Callback<Preferences>,
+    pub on_close: Callback<()>,
+}
+
+#[function_component(Settings)]
+pub fn settings(props: &SettingsProps) -> Html {
+    // Builds a checkbox or radio callback that emits a copy of the
+    // preferences with `edit` applied.
+    let set = |edit: fn(&mut Preferences)| {
+        let preferences = props.preferences.clone();
+        let on_change = props.on_change.clone();
+        Callback::from(move |_: Event| {
+            let mut preferences = preferences.clone();
+            edit(&mut preferences);
+            on_change.emit(preferences)
+        })
+    };
+    // Builds a number-input callback that stores the parsed value via `edit`,
+    // ignoring anything below `min` or not a number.
+    let number = |min: u32, edit: fn(&mut Preferences, u32)| {
+        let preferences = props.preferences.clone();
+        let on_change = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            if let Ok(value) = input.value().parse::<u32>() {
+                if value >= min {
+                    let mut preferences = preferences.clone();
+                    edit(&mut preferences, value);
+                    on_change.emit(preferences)
+                }
+            }
+        })
+    };
+    let set_greeting = {
+        let preferences = props.preferences.clone();
+        let on_change = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut preferences = preferences.clone();
+            preferences.greeting = input.value().trim().chars().take(MAX_GREETING_LEN).collect();
+            on_change.emit(preferences)
+        })
+    };
+    let set_no_greeting_rooms = {
+        let preferences = props.preferences.clone();
+        let on_change = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut preferences = preferences.clone();
+            preferences.no_greeting_rooms = input
+                .value()
+                .split(',')
+                .map(str::trim)
+                .filter(|room| !room.is_empty())
+                .map(str::to_string)
+                .collect();
+            on_change.emit(preferences)
+        })
+    };
+    let set_quick_reaction = {
+        let preferences = props.preferences.clone();
+        let on_change = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            let mut preferences = preferences.clone();
+            preferences.quick_reaction = select.value();
+            on_change.emit(preferences)
+        })
+    };
+    let set_blocked_words = {
+        let preferences = props.preferences.clone();
+        let on_change = props.on_change.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut preferences = preferences.clone();
+            preferences.blocked_words = input
+                .value()
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|word| !word.is_empty())
+                .map(str::to_string)
+                .collect();
+            on_change.emit(preferences)
+        })
+    };
+    let close = props.on_close.reform(|_| ());
+
+    let import_status = use_state(|| None::<String>);
+    let import_text = use_node_ref();
+    // Keeps an in-flight file read alive until its callback runs.
+    let file_reader = use_mut_ref(|| None::<FileReader>);
+
+    let export = {
+        let preferences = props.preferences.clone();
+        Callback::from(move |_| {
+            download("yewchat-preferences.json", "application/json", &preferences.export())
+        })
+    };
+
+    // Merges an imported file into the current preferences and reports
+    // what was taken.
+    let import = {
+        let preferences = props.preferences.clone();
+        let on_change = props.on_change.clone();
+        let import_status = import_status.clone();
+        Callback::from(move |json: String| match preferences.import(&json) {
+            Ok(result) => {
+                let mut status = format!("Imported {} setting(s).", result.applied);
+                if !result.rejected.is_empty() {
+                    status.push_str(&format!(" Ignored: {}.", result.rejected.join(", ")));
+                }
+                if result.newer_version {
+                    status.push_str(" The file is from a newer version.");
+                }
+                import_status.set(Some(status));
+                on_change.emit(result.preferences);
+            }
+            Err(e) => import_status.set(Some(format!("Import failed: {}", e))),
+        })
+    };
+
+    let import_pasted = {
+        let import = import.clone();
+        let import_text = import_text.clone();
+        Callback::from(move |_| {
+            if let Some(textarea) = import_text.cast::<HtmlTextAreaElement>() {
+                import.emit(textarea.value());
+                textarea.set_value("");
+            }
+        })
+    };
+
+    let import_file = {
+        let import = import.clone();
+        let file_reader = file_reader.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let file = input.files().and_then(|files| files.get(0));
+            if let Some(file) = file {
+                let import = import.clone();
+                let reader = gloo::file::callbacks::read_as_text(&file.into(), move |result| {
+                    match result {
+                        Ok(json) => import.emit(json),
+                        Err(e) => log::error!("failed to read preferences file: {:?}", e),
+                    }
+                });
+                *file_reader.borrow_mut() = Some(reader);
+            }
+            input.set_value("");
+        })
+    };
+
+    html! {
+        <div class="m-3 p-3 bg-white rounded-lg shadow-sm text-sm dark:bg-gray-800">
+            <div class="flex justify-between items-center mb-2">
+                <span class="font-semibold">{"Settings"}</span>
+                <button onclick={close} class="text-gray-400 hover:text-gray-600">{"✕"}</button>
+            </div>
+            <label class="flex items-center gap-2 cursor-pointer">
+                <input type="checkbox" checked={props.preferences.compact_roster} onchange={set(|p| p.compact_roster = !p.compact_roster)} />
+                {"Compact user list"}
+            </label>
+            <label class="flex items-center gap-2 cursor-pointer">
+                <input type="checkbox" checked={props.preferences.autoplay_gifs} onchange={set(|p| p.autoplay_gifs = !p.autoplay_gifs)} />
+                {"Autoplay GIFs"}
+            </label>
+            <label class="flex items-center gap-2 cursor-pointer">
+                <input type="checkbox" checked={props.preferences.lazy_avatars} onchange={set(|p| p.lazy_avatars = !p.lazy_avatars)} />
+                {"Lazy-load avatars in large rooms"}
+            </label>
+            <label class="flex items-center gap-2 cursor-pointer">
+                <input type="checkbox" checked={props.preferences.notify} onchange={set(|p| p.notify = !p.notify)} />
+                {"Notify me about new messages"}
+            </label>
+            <label class="flex items-center gap-2 cursor-pointer">
+                <input type="checkbox" checked={props.preferences.read_receipts} onchange={set(|p| p.read_receipts = !p.read_receipts)} />
+                {"Send read receipts"}
+            </label>
+            <label class="flex items-center gap-2 cursor-pointer">
+                <input type="checkbox" checked={props.preferences.filter_profanity} onchange={set(|p| p.filter_profanity = !p.filter_profanity)} />
+                {"Hide profanity"}
+            </label>
+            if props.preferences.filter_profanity {
+                <input type="text" aria-label="Hidden words" placeholder="Words to hide, separated by commas"
+                    class="w-full mt-1 px-1 border rounded"
+                    value={props.preferences.blocked_words.join(", ")} onchange={set_blocked_words} />
+            }
+            <div class="mt-2">
+                <div>{"Text size"}</div>
+                <div class="flex gap-3 mt-1">
+                    <label class="flex items-center gap-1 cursor-pointer">
+                        <input type="radio" name="font-size" checked={props.preferences.font_size == FontSize::Small} onchange={set(|p| p.font_size = FontSize::Small)} />
+                        {"Small"}
+                    </label>
+                    <label class="flex items-center gap-1 cursor-pointer">
+                        <input type="radio" name="font-size" checked={props.preferences.font_size == FontSize::Medium} onchange={set(|p| p.font_size = FontSize::Medium)} />
+                        {"Medium"}
+                    </label>
+                    <label class="flex items-center gap-1 cursor-pointer">
+                        <input type="radio" name="font-size" checked={props.preferences.font_size == FontSize::Large} onchange={set(|p| p.font_size = FontSize::Large)} />
+                        {"Large"}
+                    </label>
+                </div>
+            </div>
+            <div class="mt-2">
+                <div>{"When the connection drops, show"}</div>
+                <div class="flex gap-3 mt-1">
+                    <label class="flex items-center gap-1 cursor-pointer">
+                        <input type="radio" name="connection-notice" checked={props.preferences.connection_notice == ConnectionNotice::Banner} onchange={set(|p| p.connection_notice = ConnectionNotice::Banner)} />
+                        {"A banner"}
+                    </label>
+                    <label class="flex items-center gap-1 cursor-pointer">
+                        <input type="radio" name="connection-notice" checked={props.preferences.connection_notice == ConnectionNotice::Corner} onchange={set(|p| p.connection_notice = ConnectionNotice::Corner)} />
+                        {"A corner badge"}
+                    </label>
+                    <label class="flex items-center gap-1 cursor-pointer">
+                        <input type="radio" name="connection-notice" checked={props.preferences.connection_notice == ConnectionNotice::Silent} onchange={set(|p| p.connection_notice = ConnectionNotice::Silent)} />
+                        {"Only the status"}
+                    </label>
+                </div>
+            </div>
+            <label class="flex items-center gap-2 mt-2">
+                {"Double-click a message to react with"}
+                <select class="px-1 border rounded" onchange={set_quick_reaction}>
+                    <option value="" selected={props.preferences.quick_reaction.is_empty()}>{"Nothing"}</option>
+                    {
+                        for REACTIONS.iter().map(|&emoji| html! {
+                            <option value={emoji} selected={props.preferences.quick_reaction == emoji}>{emoji}</option>
+                        })
+                    }
+                </select>
+            </label>
+            <div class="mt-2">
+                <div>{"Greeting on join"}</div>
+                <input type="text" maxlength={MAX_GREETING_LEN.to_string()} placeholder="e.g. Hey everyone 👋"
+                    class="w-full mt-1 px-1 border rounded"
+                    value={props.preferences.greeting.clone()} onchange={set_greeting} />
+                if !props.preferences.greeting.is_empty() {
+                    <input type="text" aria-label="Rooms without the greeting" placeholder="Not in these rooms, separated by commas"
+                        class="w-full mt-1 px-1 border rounded"
+                        value={props.preferences.no_greeting_rooms.join(", ")} onchange={set_no_greeting_rooms} />
+                }
+            </div>
+            <div class="mt-2">
+                <div>{"Max image size (px)"}</div>
+                <div class="flex items-center gap-1 mt-1">
+                    <input type="number" min="64" class="w-16 px-1 border rounded"
+                        value={props.preferences.max_image_width.to_string()}
+                        onchange={number(64, |p, v| p.max_image_width = v)} />
+                    {"×"}
+                    <input type="number" min="64" class="w-16 px-1 border rounded"
+                        value={props.preferences.max_image_height.to_string()}
+                        onchange={number(64, |p, v| p.max_image_height = v)} />
+                </div>
+            </div>
+            <div class="mt-2">
+                <div>{"Messages kept after reload"}</div>
+                <input type="number" min="0" class="w-16 mt-1 px-1 border rounded"
+                    value={props.preferences.history_limit.to_string()}
+                    onchange={number(0, |p, v| p.history_limit = v)} />
+            </div>
+            <div class="mt-3 pt-2 border-t border-gray-200">
+                <div class="flex items-center gap-2">
+                    <button onclick={export} class="px-2 py-1 rounded bg-gray-100 hover:bg-gray-200">{"Export"}</button>
+                    <label class="px-2 py-1 rounded bg-gray-100 hover:bg-gray-200 cursor-pointer">
+                        {"Import file"}
+                        <input type="file" accept=".json,application/json" class="hidden" onchange={import_file} />
+                    </label>
+                </div>
+                <textarea ref={import_text} rows="2" placeholder="…or paste exported JSON"
+                    class="w-full mt-2 px-1 border rounded text-xs font-mono" />
+                <button onclick={import_pasted} class="px-2 py-1 rounded bg-gray-100 hover:bg-gray-200">{"Import pasted"}</button>
+                {
+                    if let Some(status) = &*import_status {
+                        html! { <div class="mt-1 text-xs text-gray-500">{status.clone()}</div> }
+                    } else {
+                        html! {}
+                    }
+                }
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,107 @@
+use wasm_bindgen::JsCast;
+use web_sys::{Element, KeyboardEvent};
+use yew::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    ShowHelp,
+    CloseOverlay,
+    FocusInput,
+    OpenPalette,
+}
+
+/// A keyboard shortcut handled by the chat.
+pub struct Shortcut {
+    pub action: Action,
+    /// The `KeyboardEvent::key` value that triggers the shortcut.
+    pub key: &'static str,
+    /// Whether Ctrl (or Cmd on macOS) must be held.
+    pub ctrl: bool,
+    /// Whether the shortcut also fires while typing in a text field.
+    pub in_text_fields: bool,
+    /// How the keys are shown in the help overlay.
+    pub label: &'static str,
+    pub description: &'static str,
+}
+
+/// Every shortcut the chat responds to. Both key handling and the help
+/// overlay read from this list, so a shortcut added here is documented
+/// automatically.
+pub const SHORTCUTS: &[Shortcut] = &[
+    Shortcut {
+        action: Action::ShowHelp,
+        key: "?",
+        ctrl: false,
+        in_text_fields: false,
+        label: "?",
+        description: "Show keyboard shortcuts",
+    },
+    Shortcut {
+        action: Action::CloseOverlay,
+        key: "Escape",
+        ctrl: false,
+        in_text_fields: true,
+        label: "Esc",
+        description: "Close the open overlay",
+    },
+    Shortcut {
+        action: Action::FocusInput,
+        key: "/",
+        ctrl: false,
+        in_text_fields: false,
+        label: "/",
+        description: "Focus the message input",
+    },
+    Shortcut {
+        action: Action::OpenPalette,
+        key: "k",
+        ctrl: true,
+        in_text_fields: true,
+        label: "Ctrl+K",
+        description: "Open the command palette",
+    },
+];
+
+/// Looks up the shortcut matching a `keydown` event, if any.
+pub fn find(e: &KeyboardEvent) -> Option<&'static Shortcut> {
+    let ctrl = e.ctrl_key() || e.meta_key();
+    let in_text_field = e
+        .target()
+        .and_then(|t| t.dyn_into::<Element>().ok())
+        .map(|el| matches!(el.tag_name().as_str(), "INPUT" | "TEXTAREA"))
+        .unwrap_or(false);
+
+    SHORTCUTS.iter().find(|s| {
+        s.key == e.key() && s.ctrl == ctrl && (s.in_text_fields || !in_text_field)
+    })
+}
+
+#[derive(Properties, PartialEq)]
+pub struct ShortcutsHelpProps {
+    pub on_close: Callback<()>,
+}
+
+#[function_component(ShortcutsHelp)]
+pub fn shortcuts_help(props: &ShortcutsHelpProps) -> Html {
+    let close = props.on_close.reform(|_| ());
+    // Keep clicks inside the panel from reaching the backdrop.
+    let stop = Callback::from(|e: MouseEvent| e.stop_propagation());
+
+    html! {
+        <div onclick={close} class="fixed inset-0 z-50 bg-black bg-opacity-50 flex items-center justify-center">
+            <div onclick={stop} class="bg-white rounded-lg shadow-lg p-4 w-80 dark:bg-gray-800 dark:text-gray-100">
+                <div class="text-lg font-semibold mb-3">{"Keyboard shortcuts"}</div>
+                {
+                    SHORTCUTS.iter().map(|s| {
+                        html! {
+                            <div class="flex justify-between items-center text-sm py-1">
+                                <span class="text-gray-700">{s.description}</span>
+                                <kbd class="px-2 py-0.5 rounded border border-gray-300 bg-gray-100 font-mono text-xs">{s.label}</kbd>
+                            </div>
+                        }
+                    }).collect::<Html>()
+                }
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,192 @@
+use yew::prelude::*;
+
+use crate::components::message_body::highlight;
+
+/// What the highlighter needs to know about a language.
+struct Language {
+    keywords: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self",
+        "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    // `'` also starts lifetimes, so char literals are left plain.
+    quotes: &['"'],
+};
+
+const JAVASCRIPT: Language = Language {
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "delete", "do", "else",
+        "export", "extends", "false", "finally", "for", "from", "function", "if", "import", "in", "instanceof",
+        "interface", "let", "new", "null", "of", "return", "static", "super", "switch", "this", "throw", "true", "try",
+        "type", "typeof", "undefined", "var", "void", "while", "yield",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+};
+
+const PYTHON: Language = Language {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except",
+        "False", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "None", "nonlocal", "not",
+        "or", "pass", "raise", "return", "True", "try", "while", "with", "yield",
+    ],
+    line_comment: Some("#"),
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const C_LIKE: Language = Language {
+    keywords: &[
+        "auto", "bool", "break", "case", "char", "class", "const", "continue", "default", "defer", "do", "double",
+        "else", "enum", "extends", "false", "final", "float", "for", "func", "go", "if", "implements", "import", "int",
+        "interface", "long", "namespace", "new", "nil", "null", "package", "private", "protected", "public", "return",
+        "short", "static", "struct", "switch", "template", "this", "throw", "true", "try", "typedef", "unsigned",
+        "void", "while",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+};
+
+const SHELL: Language = Language {
+    keywords: &[
+        "case", "do", "done", "echo", "elif", "else", "esac", "exit", "export", "fi", "for", "function", "if", "in",
+        "local", "return", "then", "while",
+    ],
+    line_comment: Some("#"),
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const JSON: Language = Language {
+    keywords: &["false", "null", "true"],
+    line_comment: None,
+    block_comment: None,
+    quotes: &['"'],
+};
+
+/// The language named by a code fence's hint, e.g. "rust" or "js".
+fn language(hint: &str) -> Option<&'static Language> {
+    match hint.to_lowercase().as_str() {
+        "rust" | "rs" => Some(&RUST),
+        "javascript" | "js" | "jsx" | "typescript" | "ts" | "tsx" => Some(&JAVASCRIPT),
+        "python" | "py" => Some(&PYTHON),
+        "c" | "h" | "cpp" | "c++" | "java" | "go" | "csharp" | "cs" | "kotlin" => Some(&C_LIKE),
+        "bash" | "sh" | "shell" | "zsh" => Some(&SHELL),
+        "json" => Some(&JSON),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    Plain,
+    Keyword,
+    Str,
+    Comment,
+    Number,
+}
+
+impl Token {
+    fn class(self) -> &'static str {
+        match self {
+            Token::Plain => "",
+            Token::Keyword => "text-purple-300",
+            Token::Str => "text-green-300",
+            Token::Comment => "text-gray-400 italic",
+            Token::Number => "text-orange-300",
+        }
+    }
+}
+
+/// Splits `code` into tokens. Strings end at their closing quote or the end
+/// of the line; comments without an end run to the end of the code.
+fn tokenize<'a>(code: &'a str, lang: &Language) -> Vec<(Token, &'a str)> {
+    let mut tokens = Vec::new();
+    let mut plain_start = 0;
+    let mut i = 0;
+
+    while i < code.len() {
+        let rest = &code[i..];
+        let c = rest.chars().next().unwrap_or_default();
+        let token = if lang.line_comment.is_some_and(|start| rest.starts_with(start)) {
+            Some((Token::Comment, rest.find('\n').unwrap_or(rest.len())))
+        } else if let Some((start, end)) = lang.block_comment.filter(|(start, _)| rest.starts_with(*start)) {
+            let len = rest[start.len()..].find(end).map_or(rest.len(), |at| start.len() + at + end.len());
+            Some((Token::Comment, len))
+        } else if lang.quotes.contains(&c) {
+            Some((Token::Str, string_len(rest, c)))
+        } else if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.')).unwrap_or(rest.len());
+            Some((Token::Number, len))
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            if !lang.keywords.contains(&&rest[..len]) {
+                // Other words stay part of the plain text around them.
+                i += len;
+                continue;
+            }
+            Some((Token::Keyword, len))
+        } else {
+            None
+        };
+
+        match token {
+            Some((kind, len)) => {
+                if plain_start < i {
+                    tokens.push((Token::Plain, &code[plain_start..i]));
+                }
+                tokens.push((kind, &rest[..len]));
+                i += len;
+                plain_start = i;
+            }
+            None => i += c.len_utf8(),
+        }
+    }
+    if plain_start < code.len() {
+        tokens.push((Token::Plain, &code[plain_start..]));
+    }
+    tokens
+}
+
+/// The length in bytes of the string literal at the start of `text`,
+/// including its quotes.
+fn string_len(text: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (at, c) in text.char_indices().skip(1) {
+        match c {
+            '\n' => return at,
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if c == quote => return at + c.len_utf8(),
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// Renders the contents of a fenced code block, colored for the language
+/// in `hint`. Unknown languages are left plain. Matches of the search
+/// `query` are marked within each token.
+pub fn highlight_code(code: &str, hint: &str, query: &str) -> Html {
+    let Some(lang) = language(hint) else {
+        return highlight(code, query);
+    };
+    tokenize(code, lang)
+        .into_iter()
+        .map(|(kind, text)| match kind {
+            Token::Plain => highlight(text, query),
+            _ => html! { <span class={kind.class()}>{highlight(text, query)}</span> },
+        })
+        .collect()
+}
@@ -0,0 +1,114 @@
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+/// Markdown markers the toolbar can insert.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Bold,
+    Italic,
+    Code,
+    Link,
+}
+
+impl Format {
+    /// The text inserted before and after the selection.
+    fn markers(self) -> (&'static str, &'static str) {
+        match self {
+            Format::Bold => ("**", "**"),
+            Format::Italic => ("_", "_"),
+            Format::Code => ("`", "`"),
+            Format::Link => ("[", "](url)"),
+        }
+    }
+}
+
+/// Wraps `value[start..end]` in the markers for `format`. Offsets are in
+/// UTF-16 code units, as reported by the DOM selection API.
+///
+/// Returns the new value and the selection to restore: the original
+/// selection for most formats, or the `url` placeholder for links so it
+/// can be typed over. With an empty selection the markers are inserted
+/// with the caret between them.
+pub fn apply(value: &str, start: u32, end: u32, format: Format) -> (String, u32, u32) {
+    let (before, after) = format.markers();
+    let units: Vec<u16> = value.encode_utf16().collect();
+    let end = (end as usize).min(units.len());
+    let start = (start as usize).min(end);
+
+    let mut wrapped = units[..start].to_vec();
+    wrapped.extend(before.encode_utf16());
+    wrapped.extend_from_slice(&units[start..end]);
+    wrapped.extend(after.encode_utf16());
+    wrapped.extend_from_slice(&units[end..]);
+
+    let inner_start = (start + before.encode_utf16().count()) as u32;
+    let inner_end = inner_start + (end - start) as u32;
+    let (sel_start, sel_end) = if format == Format::Link && end > start {
+        // Skip past "](" to select the placeholder URL.
+        (inner_end + 2, inner_end + 5)
+    } else {
+        (inner_start, inner_end)
+    };
+
+    (String::from_utf16_lossy(&wrapped), sel_start, sel_end)
+}
+
+/// Replaces `value[start..end]` with `text`, returning the new value and
+/// the caret position just after the insertion. Offsets are in UTF-16 code
+/// units, as in `apply`.
+pub fn insert(value: &str, start: u32, end: u32, text: &str) -> (String, u32) {
+    let units: Vec<u16> = value.encode_utf16().collect();
+    let end = (end as usize).min(units.len());
+    let start = (start as usize).min(end);
+
+    let mut inserted = units[..start].to_vec();
+    inserted.extend(text.encode_utf16());
+    let caret = inserted.len() as u32;
+    inserted.extend_from_slice(&units[end..]);
+
+    (String::from_utf16_lossy(&inserted), caret)
+}
+
+#[derive(Properties, PartialEq)]
+pub struct FormattingToolbarProps {
+    /// The text input the toolbar edits.
+    pub input: NodeRef,
+}
+
+#[function_component(FormattingToolbar)]
+pub fn formatting_toolbar(props: &FormattingToolbarProps) -> Html {
+    let button = |format: Format, label: Html, title: &'static str| {
+        let input = props.input.clone();
+        // Acting on mousedown, and cancelling it, keeps focus and the
+        // selection in the input.
+        let onmousedown = Callback::from(move |e: MouseEvent| {
+            e.prevent_default();
+            if let Some(input) = input.cast::<HtmlInputElement>() {
+                let start = input.selection_start().ok().flatten().unwrap_or(0);
+                let end = input.selection_end().ok().flatten().unwrap_or(start);
+                let (value, sel_start, sel_end) = apply(&input.value(), start, end, format);
+                input.set_value(&value);
+                // Lets the input's owner see the change, as with typing.
+                if let Ok(event) = Event::new("input") {
+                    let _ = input.dispatch_event(&event);
+                }
+                let _ = input.focus();
+                let _ = input.set_selection_range(sel_start, sel_end);
+            }
+        });
+        html! {
+            <button type="button" {onmousedown} {title} class="w-7 h-7 rounded text-sm text-gray-600 hover:bg-gray-200">
+                {label}
+            </button>
+        }
+    };
+
+    html! {
+        <div class="w-full flex items-center gap-1 px-3 pb-1">
+            {button(Format::Bold, html! { <b>{"B"}</b> }, "Bold")}
+            {button(Format::Italic, html! { <i>{"I"}</i> }, "Italic")}
+            {button(Format::Code, html! { <code>{"</>"}</code> }, "Code")}
+            {button(Format::Link, html! { {"🔗"} }, "Link")}
+        </div>
+    }
+}
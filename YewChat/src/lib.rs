@@ -1,4 +1,6 @@
 #![recursion_limit = "512"]
+// The yew 0.19 `html!` expansion trips these lints on component tags.
+#![allow(clippy::unnecessary_operation, clippy::let_unit_value)]
 
 mod components;
 mod services;
@@ -6,6 +8,7 @@ mod services;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use gloo::storage::{LocalStorage, Storage};
 use wasm_bindgen::prelude::*;
 use yew::functional::*;
 use yew::prelude::*;
@@ -13,6 +16,8 @@ use yew_router::prelude::*;
 
 use components::chat::Chat;
 use components::login::Login;
+use services::preferences::Preferences;
+use services::theme::Theme;
 
 // When the `wee_alloc` feature is enabled, this uses `wee_alloc` as the global
 // allocator.
@@ -22,12 +27,14 @@ use components::login::Login;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
-#[derive(Debug, Clone, Copy, PartialEq, Routable)]
+#[derive(Debug, Clone, PartialEq, Routable)]
 pub enum Route {
     #[at("/")]
     Login,
     #[at("/chat")]
     Chat,
+    #[at("/chat/:room")]
+    Room { room: String },
     #[not_found]
     #[at("/404")]
     NotFound,
@@ -38,13 +45,22 @@ pub type User = Rc<UserInner>;
 #[derive(Debug, PartialEq)]
 pub struct UserInner {
     pub username: RefCell<String>,
+    /// A custom avatar URL, or empty for the generated default.
+    pub avatar: RefCell<String>,
+    /// Why the chat sent the user back to the login page, shown there.
+    pub login_error: RefCell<Option<String>>,
 }
 
+/// Where the custom avatar URL is remembered between visits.
+pub const AVATAR_STORAGE_KEY: &str = "yewchat.avatar.v1";
+
 #[function_component(Main)]
 fn main() -> Html {
     let ctx = use_state(|| {
         Rc::new(UserInner {
             username: RefCell::new("initial".into()),
+            avatar: RefCell::new(LocalStorage::get(AVATAR_STORAGE_KEY).unwrap_or_default()),
+            login_error: RefCell::new(None),
         })
     });
 
@@ -63,6 +79,9 @@ fn switch(selected_route: &Route) -> Html {
     match selected_route {
         Route::Login => html! {<Login />},
         Route::Chat => html! {<Chat/>},
+        // The same `Chat` instance stays mounted when switching rooms, so
+        // the connection is kept.
+        Route::Room { room } => html! {<Chat room={Some(room.clone())}/>},
         Route::NotFound => html! {<h1>{"404 baby"}</h1>},
     }
 }
@@ -70,6 +89,8 @@ fn switch(selected_route: &Route) -> Html {
 #[wasm_bindgen]
 pub fn run_app() -> Result<(), JsValue> {
     wasm_logger::init(wasm_logger::Config::default());
+    Preferences::load().apply_to_document();
+    Theme::load().apply_to_document();
     yew::start_app::<Main>();
     Ok(())
 }
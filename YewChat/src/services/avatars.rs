@@ -0,0 +1,73 @@
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// Where users who haven't set a custom avatar get theirs from.
+pub trait AvatarProvider {
+    fn avatar_url(&self, username: &str) -> String;
+}
+
+/// Generated cartoon avatars from DiceBear, the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DiceBearProvider;
+
+impl AvatarProvider for DiceBearProvider {
+    fn avatar_url(&self, username: &str) -> String {
+        format!("https://avatars.dicebear.com/api/adventurer-neutral/{}.svg", username)
+    }
+}
+
+/// Avatars from any service taking the username in its URL, e.g.
+/// `https://avatars.example.com/{name}.png`; `{name}` is replaced with
+/// the username.
+#[derive(Clone, Debug)]
+pub struct TemplateProvider {
+    pub template: String,
+}
+
+impl AvatarProvider for TemplateProvider {
+    fn avatar_url(&self, username: &str) -> String {
+        let name = js_sys::encode_uri_component(username);
+        self.template.replace("{name}", &String::from(name))
+    }
+}
+
+/// An avatar URL template set at build time through `YEWCHAT_AVATAR_URL`,
+/// in the form `TemplateProvider` takes.
+const CONFIGURED_AVATAR_URL: Option<&str> = option_env!("YEWCHAT_AVATAR_URL");
+
+/// A shared `AvatarProvider`, as passed to components. Two are equal when
+/// they are the same provider.
+#[derive(Clone)]
+pub struct Avatars(Rc<dyn AvatarProvider>);
+
+impl Avatars {
+    pub fn new(provider: impl AvatarProvider + 'static) -> Self {
+        Self(Rc::new(provider))
+    }
+}
+
+/// The provider configured at build time, or DiceBear.
+impl Default for Avatars {
+    fn default() -> Self {
+        match CONFIGURED_AVATAR_URL.map(str::trim).filter(|template| !template.is_empty()) {
+            Some(template) => Self::new(TemplateProvider {
+                template: template.to_string(),
+            }),
+            None => Self::new(DiceBearProvider),
+        }
+    }
+}
+
+impl Deref for Avatars {
+    type Target = dyn AvatarProvider;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl PartialEq for Avatars {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
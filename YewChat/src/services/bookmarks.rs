@@ -0,0 +1,119 @@
+use gloo::storage::{LocalStorage, Storage};
+use serde::{Deserialize, Serialize};
+
+const STORAGE_KEY_PREFIX: &str = "yewchat.bookmarks.v1.";
+
+/// A message the user bookmarked, with a snapshot of it taken at the time,
+/// so the bookmark still reads the same once the message is gone from the
+/// timeline. Bookmarks never leave the browser.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    /// The server's id of the message.
+    pub id: String,
+    pub from: String,
+    /// The message's text, or a description of its attachment.
+    pub text: String,
+    /// When the message was sent, in epoch millis.
+    pub time: Option<f64>,
+    /// The room, for room messages.
+    pub room: Option<String>,
+    /// The peer, for direct messages.
+    pub peer: Option<String>,
+}
+
+/// The user's bookmarks, newest first, kept in `localStorage` as a JSON
+/// array of `Bookmark`s.
+#[derive(Default)]
+pub struct Bookmarks {
+    bookmarks: Vec<Bookmark>,
+}
+
+impl Bookmarks {
+    /// What `username` bookmarked in this browser.
+    pub fn load(username: &str) -> Self {
+        Self {
+            bookmarks: LocalStorage::get(storage_key(username)).unwrap_or_default(),
+        }
+    }
+
+    pub fn save(&self, username: &str) {
+        if let Err(e) = LocalStorage::set(storage_key(username), &self.bookmarks) {
+            log::error!("failed to save bookmarks: {:?}", e);
+        }
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.bookmarks.iter().any(|b| b.id == id)
+    }
+
+    /// Adds `bookmark`, or removes the one for the same message. Returns
+    /// whether it was added.
+    pub fn toggle(&mut self, bookmark: Bookmark) -> bool {
+        if self.remove(&bookmark.id) {
+            return false;
+        }
+        self.bookmarks.insert(0, bookmark);
+        true
+    }
+
+    /// Returns whether there was a bookmark for `id`.
+    pub fn remove(&mut self, id: &str) -> bool {
+        let before = self.bookmarks.len();
+        self.bookmarks.retain(|b| b.id != id);
+        self.bookmarks.len() != before
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Bookmark> {
+        self.bookmarks.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bookmarks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bookmarks.is_empty()
+    }
+}
+
+/// Each user has their own bookmarks, like their outbox.
+fn storage_key(username: &str) -> String {
+    format!("{}{}", STORAGE_KEY_PREFIX, username)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmark(id: &str) -> Bookmark {
+        Bookmark {
+            id: id.to_string(),
+            from: "ann".to_string(),
+            text: format!("text {}", id),
+            time: Some(1000.0),
+            room: Some("general".to_string()),
+            peer: None,
+        }
+    }
+
+    #[test]
+    fn toggling_adds_newest_first_and_removes() {
+        let mut bookmarks = Bookmarks::default();
+        assert!(bookmarks.toggle(bookmark("a")));
+        assert!(bookmarks.toggle(bookmark("b")));
+        assert_eq!(bookmarks.iter().map(|b| b.id.as_str()).collect::<Vec<_>>(), ["b", "a"]);
+        assert!(bookmarks.contains("a"));
+
+        assert!(!bookmarks.toggle(bookmark("a")));
+        assert!(!bookmarks.contains("a"));
+        assert!(!bookmarks.remove("a"));
+        assert_eq!(bookmarks.len(), 1);
+    }
+
+    #[test]
+    fn snapshots_survive_a_reload() {
+        let saved = serde_json::to_string(&[bookmark("a")]).unwrap();
+        let restored: Vec<Bookmark> = serde_json::from_str(&saved).unwrap();
+        assert_eq!(restored, [bookmark("a")]);
+    }
+}
@@ -0,0 +1,13 @@
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+/// Copies `text` to the clipboard. Fails where the Clipboard API is
+/// missing or the page isn't allowed to use it, e.g. over plain HTTP.
+pub async fn write_text(text: &str) -> Result<(), JsValue> {
+    // `Clipboard` is behind web-sys' unstable APIs, so call it
+    // reflectively.
+    let clipboard = js_sys::Reflect::get(&gloo::utils::window().navigator(), &"clipboard".into())?;
+    let write_text: js_sys::Function = js_sys::Reflect::get(&clipboard, &"writeText".into())?.dyn_into()?;
+    let promise: js_sys::Promise = write_text.call1(&clipboard, &text.into())?.dyn_into()?;
+    JsFuture::from(promise).await.map(|_| ())
+}
@@ -0,0 +1,26 @@
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlElement, Url};
+
+/// Saves `contents` as a file through a temporary object URL.
+pub fn download(filename: &str, mime_type: &str, contents: &str) {
+    let blob = gloo::file::Blob::new_with_options(contents, Some(mime_type));
+    let url = match Url::create_object_url_with_blob(&blob.into()) {
+        Ok(url) => url,
+        Err(e) => {
+            log::error!("failed to create download url: {:?}", e);
+            return;
+        }
+    };
+
+    let document = gloo::utils::document();
+    let anchor = document
+        .create_element("a")
+        .ok()
+        .and_then(|a| a.dyn_into::<HtmlElement>().ok());
+    if let Some(anchor) = anchor {
+        let _ = anchor.set_attribute("href", &url);
+        let _ = anchor.set_attribute("download", filename);
+        anchor.click();
+    }
+    let _ = Url::revoke_object_url(&url);
+}
@@ -2,9 +2,12 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use yew_agent::{Agent, AgentLink, Context, HandlerId};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::services::websocket::ConnectionState;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
     EventBusMsg(String),
+    ConnectionChanged(ConnectionState),
 }
 
 pub struct EventBus {
@@ -16,7 +19,7 @@ impl Agent for EventBus {
     type Reach = Context<Self>;
     type Message = ();
     type Input = Request;
-    type Output = String;
+    type Output = Request;
 
     fn create(link: AgentLink<Self>) -> Self {
         Self {
@@ -28,12 +31,8 @@ impl Agent for EventBus {
     fn update(&mut self, _msg: Self::Message) {}
 
     fn handle_input(&mut self, msg: Self::Input, _id: HandlerId) {
-        match msg {
-            Request::EventBusMsg(s) => {
-                for sub in self.subscribers.iter() {
-                    self.link.respond(*sub, s.clone())
-                }
-            }
+        for sub in self.subscribers.iter() {
+            self.link.respond(*sub, msg.clone())
         }
     }
 
@@ -0,0 +1,30 @@
+use gloo::storage::{LocalStorage, Storage};
+use serde::{de::DeserializeOwned, Serialize};
+
+const STORAGE_KEY: &str = "yewchat.history.v1";
+
+/// Loads the room messages saved by `save`. A blob that no longer matches
+/// the message format is dropped rather than kept around.
+pub fn load<T: DeserializeOwned>() -> Vec<T> {
+    match LocalStorage::get(STORAGE_KEY) {
+        Ok(messages) => messages,
+        Err(gloo::storage::errors::StorageError::KeyNotFound(_)) => Vec::new(),
+        Err(e) => {
+            log::error!("discarding unreadable chat history: {:?}", e);
+            LocalStorage::delete(STORAGE_KEY);
+            Vec::new()
+        }
+    }
+}
+
+/// Saves the newest `limit` messages. A limit of 0 clears the history.
+pub fn save<T: Serialize>(messages: &[T], limit: usize) {
+    if limit == 0 {
+        LocalStorage::delete(STORAGE_KEY);
+        return;
+    }
+    let recent = &messages[messages.len().saturating_sub(limit)..];
+    if let Err(e) = LocalStorage::set(STORAGE_KEY, recent) {
+        log::error!("failed to save chat history: {:?}", e);
+    }
+}
@@ -1,2 +1,18 @@
 pub mod websocket;
 pub mod event_bus;
+pub mod preferences;
+pub mod notes;
+pub mod download;
+pub mod history;
+pub mod notifications;
+pub mod theme;
+pub mod rate_limit;
+pub mod visibility;
+pub mod clipboard;
+pub mod username;
+pub mod profanity;
+pub mod avatars;
+pub mod transcript;
+pub mod outbox;
+pub mod bookmarks;
+pub mod reaction_batch;
@@ -0,0 +1,23 @@
+use gloo::storage::{LocalStorage, Storage};
+use serde::{Deserialize, Serialize};
+
+const STORAGE_KEY: &str = "yewchat.notes.v1";
+
+/// An entry in "Saved Messages". Notes never leave the browser: they are
+/// kept in `localStorage` and are not sent to the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Note {
+    pub text: String,
+    /// Creation time in epoch millis.
+    pub time: f64,
+}
+
+pub fn load() -> Vec<Note> {
+    LocalStorage::get(STORAGE_KEY).unwrap_or_default()
+}
+
+pub fn save(notes: &[Note]) {
+    if let Err(e) = LocalStorage::set(STORAGE_KEY, notes) {
+        log::error!("failed to save notes: {:?}", e);
+    }
+}
@@ -0,0 +1,64 @@
+use wasm_bindgen::JsValue;
+use web_sys::{AudioContext, Notification, NotificationOptions, NotificationPermission, OscillatorType};
+
+/// Asks for permission to show desktop notifications, unless the user has
+/// already answered. Browsers only show the prompt in response to a click
+/// or key press.
+pub fn request_permission() {
+    if Notification::permission() == NotificationPermission::Default {
+        if let Err(e) = Notification::request_permission() {
+            log::error!("failed to request notification permission: {:?}", e);
+        }
+    }
+}
+
+/// Plays the chime and shows desktop notifications for new messages.
+#[derive(Default)]
+pub struct Notifier {
+    /// Created on the first chime. Browsers keep it silent until the user
+    /// has interacted with the page.
+    audio: Option<AudioContext>,
+}
+
+impl Notifier {
+    /// Shows a desktop notification if permission was granted. Each one
+    /// replaces the previous, so a background tab leaves a single entry.
+    pub fn show(&self, title: &str, body: &str) {
+        if Notification::permission() != NotificationPermission::Granted {
+            return;
+        }
+        let mut options = NotificationOptions::new();
+        options.body(body).tag("yewchat-message");
+        if let Err(e) = Notification::new_with_options(title, &options) {
+            log::error!("failed to show notification: {:?}", e);
+        }
+    }
+
+    /// Plays a short two-note chime.
+    pub fn chime(&mut self) {
+        if let Err(e) = self.try_chime() {
+            log::error!("failed to play chime: {:?}", e);
+        }
+    }
+
+    fn try_chime(&mut self) -> Result<(), JsValue> {
+        let audio = match &self.audio {
+            Some(audio) => audio,
+            None => self.audio.insert(AudioContext::new()?),
+        };
+        let now = audio.current_time();
+        for (offset, frequency) in [(0.0, 880.0), (0.12, 1320.0)] {
+            let oscillator = audio.create_oscillator()?;
+            let gain = audio.create_gain()?;
+            oscillator.set_type(OscillatorType::Sine);
+            oscillator.frequency().set_value(frequency);
+            gain.gain().set_value_at_time(0.15, now + offset)?;
+            gain.gain().exponential_ramp_to_value_at_time(0.001, now + offset + 0.3)?;
+            oscillator.connect_with_audio_node(&gain)?;
+            gain.connect_with_audio_node(&audio.destination())?;
+            oscillator.start_with_when(now + offset)?;
+            oscillator.stop_with_when(now + offset + 0.3)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+use gloo::storage::{LocalStorage, Storage};
+use gloo::timers::callback::Timeout;
+use serde::{Deserialize, Serialize};
+
+const STORAGE_KEY_PREFIX: &str = "yewchat.outbox.v1.";
+
+/// A text message of ours the server hasn't echoed back yet. It is shown
+/// in `Chat::messages` from the start, marked with its state. Only what is
+/// needed to send it again is saved.
+#[derive(Deserialize, Serialize)]
+pub struct Outgoing {
+    /// The id chosen by this client, sent as `client_msg_id`.
+    pub id: String,
+    /// The id the server assigned, from its `Ack`. The echo carries it, or
+    /// `id` for servers that don't ack.
+    #[serde(skip)]
+    pub server_id: Option<String>,
+    /// The peer for direct messages; `None` for the room.
+    pub to: Option<String>,
+    /// The room, for room messages.
+    pub room: Option<String>,
+    pub text: String,
+    /// The message this one replies to.
+    pub reply_to: Option<String>,
+    /// The part of `reply_to` quoted, if not all of it.
+    #[serde(default)]
+    pub quote: Option<String>,
+    /// When it was written, in epoch millis.
+    pub time: f64,
+    #[serde(skip)]
+    pub state: OutgoingState,
+    /// Marks the message failed if the server doesn't acknowledge it in
+    /// time. Set by whoever sent it.
+    #[serde(skip)]
+    pub timeout: Option<Timeout>,
+}
+
+impl Outgoing {
+    /// A message waiting for the connection.
+    pub fn new(id: String, to: Option<String>, room: Option<String>, text: String, reply_to: Option<String>, time: f64) -> Self {
+        Self {
+            id,
+            server_id: None,
+            to,
+            room,
+            text,
+            reply_to,
+            quote: None,
+            time,
+            state: OutgoingState::Queued,
+            timeout: None,
+        }
+    }
+
+    /// Whether a message with `id` is this one as shown, by our id or,
+    /// once acknowledged, the server's.
+    pub fn shows(&self, id: Option<&str>) -> bool {
+        id.is_some_and(|id| id == self.id || self.server_id.as_deref() == Some(id))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutgoingState {
+    /// Waiting for the connection to come back.
+    #[default]
+    Queued,
+    /// Handed to the socket; waiting for the server.
+    Sending,
+    /// Acknowledged by the server; waiting for the echo.
+    Sent,
+    /// The socket refused it, the server didn't acknowledge it in time, or
+    /// reconnecting gave up before it got through.
+    Failed,
+}
+
+/// Our messages on their way to the server, in the order they were
+/// written.
+#[derive(Default)]
+pub struct Outbox {
+    messages: Vec<Outgoing>,
+}
+
+impl Outbox {
+    /// What `username` had in the outbox when the page was last closed,
+    /// queued to be sent again. That includes acknowledged messages whose
+    /// echo never came; the server recognises their `client_msg_id` and
+    /// only acknowledges them again.
+    pub fn load(username: &str) -> Self {
+        Self {
+            messages: LocalStorage::get(storage_key(username)).unwrap_or_default(),
+        }
+    }
+
+    /// Saves the messages for `load`. Call it whenever one comes or goes;
+    /// their states aren't saved.
+    pub fn save(&self, username: &str) {
+        let key = storage_key(username);
+        if self.messages.is_empty() {
+            LocalStorage::delete(key);
+        } else if let Err(e) = LocalStorage::set(key, &self.messages) {
+            log::error!("failed to save the outbox: {:?}", e);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Outgoing> {
+        self.messages.iter()
+    }
+
+    pub fn push(&mut self, outgoing: Outgoing) {
+        self.messages.push(outgoing);
+    }
+
+    /// The message shown with `id`; see `Outgoing::shows`.
+    pub fn showing(&self, id: Option<&str>) -> Option<&Outgoing> {
+        self.messages.iter().find(|o| o.shows(id))
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Outgoing> {
+        self.messages.iter_mut().find(|o| o.id == id)
+    }
+
+    /// Hands the queued messages to `send`, oldest first, and returns the
+    /// ids of those it took. Those it refuses are marked failed, to be
+    /// retried.
+    pub fn flush(&mut self, mut send: impl FnMut(&Outgoing) -> bool) -> Vec<String> {
+        let mut sent = Vec::new();
+        for outgoing in self.messages.iter_mut().filter(|o| o.state == OutgoingState::Queued) {
+            if send(outgoing) {
+                outgoing.state = OutgoingState::Sending;
+                sent.push(outgoing.id.clone());
+            } else {
+                outgoing.state = OutgoingState::Failed;
+            }
+        }
+        sent
+    }
+
+    /// Records the server's `Ack` of the message we sent as `client_id`,
+    /// and returns the id it was shown with until now. `None` for acks of
+    /// unknown messages and repeated acks, which can arrive after a resend
+    /// or after the echo already settled the message.
+    pub fn acknowledge(&mut self, client_id: &str, server_id: String) -> Option<String> {
+        let outgoing = self.get_mut(client_id)?;
+        if outgoing.state == OutgoingState::Sent && outgoing.server_id.as_ref() == Some(&server_id) {
+            return None;
+        }
+        outgoing.state = OutgoingState::Sent;
+        outgoing.timeout = None;
+        Some(outgoing.server_id.replace(server_id).unwrap_or_else(|| client_id.to_string()))
+    }
+
+    /// Takes out the message the server echoed back as `id`.
+    pub fn confirm(&mut self, id: &str) -> Option<Outgoing> {
+        let position = self.messages.iter().position(|o| o.shows(Some(id)))?;
+        Some(self.messages.remove(position))
+    }
+
+    /// Queues a message again, e.g. a failed one. Returns whether it was
+    /// known.
+    pub fn retry(&mut self, id: &str) -> bool {
+        let Some(outgoing) = self.get_mut(id) else {
+            return false;
+        };
+        outgoing.state = OutgoingState::Queued;
+        true
+    }
+
+    pub fn discard(&mut self, id: &str) -> Option<Outgoing> {
+        let position = self.messages.iter().position(|o| o.id == id)?;
+        Some(self.messages.remove(position))
+    }
+
+    /// Fails a message the server didn't acknowledge in time. Returns
+    /// whether it was still waiting.
+    pub fn time_out(&mut self, id: &str) -> bool {
+        let Some(outgoing) = self.get_mut(id).filter(|o| o.state == OutgoingState::Sending) else {
+            return false;
+        };
+        outgoing.state = OutgoingState::Failed;
+        outgoing.timeout = None;
+        true
+    }
+
+    /// Fails what hadn't got through when reconnecting gave up.
+    /// Acknowledged messages did; only their echo is missing.
+    pub fn give_up(&mut self) {
+        for outgoing in self.messages.iter_mut().filter(|o| o.state != OutgoingState::Sent) {
+            outgoing.state = OutgoingState::Failed;
+            outgoing.timeout = None;
+        }
+    }
+}
+
+/// Each user has their own outbox, so nobody sends what someone else
+/// wrote in the same browser.
+fn storage_key(username: &str) -> String {
+    format!("{}{}", STORAGE_KEY_PREFIX, username)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(outbox: &mut Outbox, id: &str) {
+        outbox.push(Outgoing::new(id.to_string(), None, Some("general".to_string()), format!("text {}", id), None, 0.0));
+    }
+
+    fn state(outbox: &Outbox, id: &str) -> OutgoingState {
+        outbox.messages.iter().find(|o| o.id == id).unwrap().state
+    }
+
+    #[test]
+    fn flushes_in_order_after_reconnecting() {
+        let mut outbox = Outbox::default();
+        queue(&mut outbox, "a");
+        queue(&mut outbox, "b");
+        queue(&mut outbox, "c");
+
+        // Written while offline, so nothing has gone out yet.
+        assert!(outbox.messages.iter().all(|o| o.state == OutgoingState::Queued));
+
+        let mut written = Vec::new();
+        let sent = outbox.flush(|o| {
+            written.push(o.text.clone());
+            true
+        });
+        assert_eq!(written, ["text a", "text b", "text c"]);
+        assert_eq!(sent, ["a", "b", "c"]);
+        assert!(outbox.messages.iter().all(|o| o.state == OutgoingState::Sending));
+
+        // Nothing is sent twice.
+        assert!(outbox.flush(|_| panic!("already sent")).is_empty());
+    }
+
+    #[test]
+    fn fails_when_reconnecting_gives_up() {
+        let mut outbox = Outbox::default();
+        queue(&mut outbox, "acked");
+        queue(&mut outbox, "sending");
+        outbox.flush(|_| true);
+        assert_eq!(outbox.acknowledge("acked", "server-1".to_string()).as_deref(), Some("acked"));
+        queue(&mut outbox, "refused");
+        outbox.flush(|_| false);
+        queue(&mut outbox, "queued");
+
+        outbox.give_up();
+        assert_eq!(state(&outbox, "acked"), OutgoingState::Sent);
+        assert_eq!(state(&outbox, "sending"), OutgoingState::Failed);
+        assert_eq!(state(&outbox, "queued"), OutgoingState::Failed);
+        assert_eq!(state(&outbox, "refused"), OutgoingState::Failed);
+
+        assert!(outbox.retry("queued"));
+        assert_eq!(outbox.flush(|_| true), ["queued"]);
+    }
+
+    #[test]
+    fn acks_and_echoes_settle_messages() {
+        let mut outbox = Outbox::default();
+        queue(&mut outbox, "a");
+        outbox.flush(|_| true);
+        assert_eq!(outbox.acknowledge("a", "server-a".to_string()).as_deref(), Some("a"));
+        assert_eq!(outbox.acknowledge("a", "server-a".to_string()), None);
+        assert_eq!(outbox.acknowledge("unknown", "server-b".to_string()), None);
+        assert!(outbox.showing(Some("server-a")).is_some());
+        assert!(!outbox.time_out("a"));
+
+        assert_eq!(outbox.confirm("server-a").map(|o| o.id).as_deref(), Some("a"));
+        assert!(outbox.messages.is_empty());
+    }
+
+    #[test]
+    fn restored_messages_are_queued_again() {
+        let mut outbox = Outbox::default();
+        queue(&mut outbox, "a");
+        outbox.flush(|_| true);
+        outbox.acknowledge("a", "server-a".to_string());
+
+        let saved = serde_json::to_string(&outbox.messages).unwrap();
+        let restored: Vec<Outgoing> = serde_json::from_str(&saved).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].id, "a");
+        assert_eq!(restored[0].text, "text a");
+        assert_eq!(restored[0].state, OutgoingState::Queued);
+        assert_eq!(restored[0].server_id, None);
+    }
+}
@@ -0,0 +1,255 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use gloo::storage::{LocalStorage, Storage};
+use serde::{Deserialize, Serialize};
+
+use crate::services::profanity::{self, DEFAULT_BLOCKED_WORDS};
+
+const STORAGE_KEY: &str = "yewchat.preferences.v1";
+
+/// Version written into exported preference files.
+const EXPORT_VERSION: u64 = 1;
+
+/// User-facing settings, persisted to `localStorage` as a single JSON blob.
+///
+/// Every field has a default so that blobs written by older versions (or
+/// missing entirely) still load.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Render the user list as single-line entries with a small avatar.
+    pub compact_roster: bool,
+    /// Upper bound, in pixels, for inline images in message bubbles. The
+    /// lightbox always shows the image at full size.
+    pub max_image_width: u32,
+    pub max_image_height: u32,
+    /// Play GIFs as soon as they render. When off, a placeholder is shown and
+    /// the GIF is only fetched once clicked.
+    pub autoplay_gifs: bool,
+    /// Lazy-load sidebar avatars, with initials as placeholders, once the
+    /// roster grows past `LAZY_AVATAR_THRESHOLD` users.
+    pub lazy_avatars: bool,
+    pub font_size: FontSize,
+    /// How a lost connection is shown, besides the header's connection
+    /// pill.
+    pub connection_notice: ConnectionNotice,
+    /// Sent automatically after joining the room. Empty disables it.
+    pub greeting: String,
+    /// Rooms the greeting isn't sent in, as typed in the settings.
+    pub no_greeting_rooms: Vec<String>,
+    /// How many room messages are kept across reloads. 0 keeps none.
+    pub history_limit: u32,
+    /// Chime and show a desktop notification for messages arriving while
+    /// the tab is in the background.
+    pub notify: bool,
+    /// People whose messages never chime or notify, though they still
+    /// show. Kept while `notify` is off, for when it is turned back on.
+    pub notif_muted: HashSet<String>,
+    /// Tell others which of their messages this user has seen.
+    pub read_receipts: bool,
+    /// The reaction toggled by double-clicking someone else's message. One
+    /// of the reactions offered under messages; empty turns it off.
+    pub quick_reaction: String,
+    /// Show the words in `blocked_words` as asterisks in messages. Only
+    /// the display changes; stored and exported messages keep them.
+    pub filter_profanity: bool,
+    pub blocked_words: Vec<String>,
+}
+
+/// Longest greeting the settings panel accepts, in characters.
+pub const MAX_GREETING_LEN: usize = 200;
+
+pub struct ImportResult {
+    pub preferences: Preferences,
+    /// How many fields were taken from the file.
+    pub applied: usize,
+    /// Fields that were unknown or invalid and were ignored.
+    pub rejected: Vec<String>,
+    /// The file was written by a newer version of the app.
+    pub newer_version: bool,
+}
+
+/// Base text size. Applied to the root element, so every rem-based size in
+/// the UI scales with it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FontSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl FontSize {
+    /// Root font size relative to the browser default.
+    fn css(self) -> &'static str {
+        match self {
+            FontSize::Small => "87.5%",
+            FontSize::Medium => "100%",
+            FontSize::Large => "112.5%",
+        }
+    }
+}
+
+/// How prominent the notice of a lost, or just restored, connection is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionNotice {
+    /// A full-width banner under the header, saying what is going on.
+    #[default]
+    Banner,
+    /// A small badge in the corner, with the details in its tooltip.
+    Corner,
+    /// Nothing beyond the connection pill.
+    Silent,
+}
+
+/// Roster size above which `Preferences::lazy_avatars` takes effect.
+pub const LAZY_AVATAR_THRESHOLD: usize = 50;
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            compact_roster: false,
+            max_image_width: 320,
+            max_image_height: 240,
+            autoplay_gifs: true,
+            lazy_avatars: true,
+            font_size: FontSize::Medium,
+            connection_notice: ConnectionNotice::default(),
+            greeting: String::new(),
+            no_greeting_rooms: Vec::new(),
+            history_limit: 100,
+            notify: false,
+            notif_muted: HashSet::new(),
+            read_receipts: true,
+            quick_reaction: "👍".to_string(),
+            filter_profanity: false,
+            blocked_words: DEFAULT_BLOCKED_WORDS.iter().map(|w| w.to_string()).collect(),
+        }
+    }
+}
+
+impl Preferences {
+    /// Message text as it is shown: masked if `filter_profanity` is on.
+    pub fn filtered<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.filter_profanity {
+            profanity::mask(text, &self.blocked_words)
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Whether a message from `from` arriving in the background notifies.
+    pub fn notifies_about(&self, from: &str) -> bool {
+        self.notify && !self.notif_muted.contains(from)
+    }
+
+    pub fn load() -> Self {
+        LocalStorage::get(STORAGE_KEY).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = LocalStorage::set(STORAGE_KEY, self) {
+            log::error!("failed to save preferences: {:?}", e);
+        }
+    }
+
+    /// Serializes the preferences for download as
+    /// `{"version": 1, "preferences": {...}}`.
+    pub fn export(&self) -> String {
+        let export = serde_json::json!({
+            "version": EXPORT_VERSION,
+            "preferences": self,
+        });
+        serde_json::to_string_pretty(&export).unwrap_or_default()
+    }
+
+    /// Merges an exported preferences file into a copy of `self`.
+    ///
+    /// Both the wrapped export format and a bare preferences object are
+    /// accepted. Each field is checked on its own: fields that parse and pass
+    /// validation are applied, unknown or invalid ones are listed in the
+    /// result and left unchanged. A file from a newer version is merged the
+    /// same way, so whatever this version understands still carries over.
+    pub fn import(&self, json: &str) -> Result<ImportResult, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| format!("not valid JSON: {}", e))?;
+        let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(EXPORT_VERSION);
+        let fields = value
+            .get("preferences")
+            .unwrap_or(&value)
+            .as_object()
+            .ok_or("expected a JSON object")?;
+
+        let mut merged = match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(map)) => map,
+            _ => return Err("could not read current preferences".into()),
+        };
+        let mut applied = 0;
+        let mut rejected = Vec::new();
+
+        for (key, field) in fields {
+            if !merged.contains_key(key) {
+                rejected.push(key.clone());
+                continue;
+            }
+            let mut candidate = merged.clone();
+            candidate.insert(key.clone(), field.clone());
+            match serde_json::from_value::<Preferences>(candidate.clone().into()) {
+                Ok(preferences) if preferences.is_valid() => {
+                    merged = candidate;
+                    applied += 1;
+                }
+                _ => rejected.push(key.clone()),
+            }
+        }
+
+        let preferences = serde_json::from_value(merged.into()).map_err(|e| e.to_string())?;
+        Ok(ImportResult {
+            preferences,
+            applied,
+            rejected,
+            newer_version: version > EXPORT_VERSION,
+        })
+    }
+
+    /// Checks the constraints the settings panel enforces on input.
+    fn is_valid(&self) -> bool {
+        self.max_image_width >= 64
+            && self.max_image_height >= 64
+            && self.greeting.chars().count() <= MAX_GREETING_LEN
+    }
+
+    /// Applies the settings that live outside the component tree.
+    pub fn apply_to_document(&self) {
+        if let Some(root) = gloo::utils::document().document_element() {
+            let style = format!("font-size: {};", self.font_size.css());
+            if let Err(e) = root.set_attribute("style", &style) {
+                log::error!("failed to apply font size: {:?}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn muted_users_never_notify() {
+        let mut preferences = Preferences {
+            notify: true,
+            ..Preferences::default()
+        };
+        preferences.notif_muted.insert("bob".to_string());
+        assert!(preferences.notifies_about("ann"));
+        assert!(!preferences.notifies_about("bob"));
+
+        preferences.notify = false;
+        assert!(!preferences.notifies_about("ann"));
+        // The mute outlives notifications being off.
+        preferences.notify = true;
+        assert!(!preferences.notifies_about("bob"));
+    }
+}
@@ -0,0 +1,73 @@
+use std::borrow::Cow;
+
+/// The words `Preferences::blocked_words` starts out with.
+pub const DEFAULT_BLOCKED_WORDS: &[&str] = &["arse", "asshole", "bastard", "bitch", "crap", "damn", "fuck", "shit"];
+
+/// `text` with each whole word found in `blocked` replaced by asterisks,
+/// ignoring case. Only whole words count, so "Scunthorpe" or "classic"
+/// are left alone by a list containing their parts. The text is only
+/// copied if something was masked.
+pub fn mask<'a>(text: &'a str, blocked: &[String]) -> Cow<'a, str> {
+    let is_blocked = |word: &str| blocked.iter().any(|b| b.to_lowercase() == word.to_lowercase());
+    if blocked.is_empty() || !words(text).any(|(_, word)| is_blocked(word)) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut masked = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (start, word) in words(text).filter(|&(_, word)| is_blocked(word)) {
+        masked.push_str(&text[copied..start]);
+        masked.extend(word.chars().map(|_| '*'));
+        copied = start + word.len();
+    }
+    masked.push_str(&text[copied..]);
+    Cow::Owned(masked)
+}
+
+/// The runs of letters and digits in `text`, with their byte offsets.
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut at = 0;
+    std::iter::from_fn(move || {
+        let start = at + text[at..].find(char::is_alphanumeric)?;
+        let len = text[start..].find(|c: char| !c.is_alphanumeric()).unwrap_or(text.len() - start);
+        at = start + len;
+        Some((start, &text[start..at]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::preferences::Preferences;
+
+    fn blocked(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn masks_whole_words_only() {
+        let blocked = blocked(&["cunt", "ass"]);
+        assert_eq!(mask("Scunthorpe is a classic town", &blocked), "Scunthorpe is a classic town");
+        assert!(matches!(mask("Scunthorpe", &blocked), Cow::Borrowed(_)));
+        assert_eq!(mask("what an ass", &blocked), "what an ***");
+    }
+
+    #[test]
+    fn ignores_case_and_punctuation() {
+        let blocked = blocked(&["darn"]);
+        assert_eq!(mask("DARN it", &blocked), "**** it");
+        assert_eq!(mask("oh, Darn! (darn.) darn-it", &blocked), "oh, ****! (****.) ****-it");
+        assert_eq!(mask("darned", &blocked), "darned");
+    }
+
+    #[test]
+    fn shows_the_original_when_off() {
+        let mut preferences = Preferences {
+            blocked_words: blocked(&["darn"]),
+            ..Preferences::default()
+        };
+        assert_eq!(preferences.filtered("darn it"), "darn it");
+        preferences.filter_profanity = true;
+        assert_eq!(preferences.filtered("darn it"), "**** it");
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::VecDeque;
+
+/// Allows at most `max` events in any `window_ms` long stretch of time.
+pub struct RateLimiter {
+    max: usize,
+    window_ms: f64,
+    /// Times of the events inside the current window, oldest first.
+    recent: VecDeque<f64>,
+}
+
+impl RateLimiter {
+    pub fn new(max: usize, window_ms: f64) -> Self {
+        Self {
+            max,
+            window_ms,
+            recent: VecDeque::with_capacity(max),
+        }
+    }
+
+    /// Records an event at `now` (epoch millis) if the limit allows it, and
+    /// returns whether it did. Rejected events don't count towards the
+    /// limit.
+    pub fn try_acquire(&mut self, now: f64) -> bool {
+        while self.recent.front().map(|&t| now - t >= self.window_ms).unwrap_or(false) {
+            self.recent.pop_front();
+        }
+        if self.recent.len() >= self.max {
+            return false;
+        }
+        self.recent.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_the_sixth_send_in_the_window() {
+        let mut limiter = RateLimiter::new(5, 3000.0);
+        for i in 0..5 {
+            assert!(limiter.try_acquire(1000.0 + i as f64 * 100.0));
+        }
+        assert!(!limiter.try_acquire(1500.0));
+        assert!(!limiter.try_acquire(3999.0));
+    }
+
+    #[test]
+    fn allows_sends_again_once_the_window_passed() {
+        let mut limiter = RateLimiter::new(5, 3000.0);
+        for _ in 0..5 {
+            assert!(limiter.try_acquire(1000.0));
+        }
+        assert!(!limiter.try_acquire(2000.0));
+        // Rejected sends didn't extend the window.
+        assert!(limiter.try_acquire(4000.0));
+        for _ in 0..4 {
+            assert!(limiter.try_acquire(4000.0));
+        }
+        assert!(!limiter.try_acquire(4000.0));
+    }
+}
@@ -0,0 +1,74 @@
+/// How long reaction toggles are collected before the net change is sent,
+/// in milliseconds. Each toggle restarts the wait.
+pub const REACTION_BATCH_MS: u32 = 500;
+
+/// Our reaction toggles waiting to be sent. Toggling the same reaction
+/// several times in a row only sends where it ended up, and nothing if
+/// that is where it started.
+#[derive(Default)]
+pub struct ReactionBatch {
+    pending: Vec<PendingReaction>,
+}
+
+/// Our reaction to the message `id`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingReaction {
+    pub id: String,
+    pub emoji: &'static str,
+    /// The peer, for reactions to direct messages.
+    pub to: Option<String>,
+    /// Whether we reacted when the batch started.
+    was: bool,
+    /// Whether we react now.
+    pub add: bool,
+}
+
+impl ReactionBatch {
+    /// Records that our `emoji` reaction to `id` went from `was` to `!was`.
+    pub fn toggle(&mut self, id: &str, emoji: &'static str, to: Option<String>, was: bool) {
+        match self.pending.iter_mut().find(|p| p.id == id && p.emoji == emoji) {
+            Some(pending) => pending.add = !was,
+            None => self.pending.push(PendingReaction {
+                id: id.to_string(),
+                emoji,
+                to,
+                was,
+                add: !was,
+            }),
+        }
+    }
+
+    /// Empties the batch, returning the reactions that changed, in the
+    /// order they were first toggled.
+    pub fn take(&mut self) -> Vec<PendingReaction> {
+        self.pending.drain(..).filter(|p| p.add != p.was).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sends_only_the_net_change() {
+        let mut batch = ReactionBatch::default();
+        // Added, then taken back: nothing to send.
+        batch.toggle("m1", "👍", None, false);
+        batch.toggle("m1", "👍", None, true);
+        // Removed, added and removed again: one removal.
+        batch.toggle("m1", "🎉", None, true);
+        batch.toggle("m1", "🎉", None, false);
+        batch.toggle("m1", "🎉", None, true);
+        batch.toggle("m2", "👍", Some("ann".to_string()), false);
+
+        let sent: Vec<_> = batch.take().into_iter().map(|p| (p.id, p.emoji, p.to, p.add)).collect();
+        assert_eq!(
+            sent,
+            [
+                ("m1".to_string(), "🎉", None, false),
+                ("m2".to_string(), "👍", Some("ann".to_string()), true),
+            ]
+        );
+        assert!(batch.take().is_empty());
+    }
+}
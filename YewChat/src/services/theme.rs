@@ -0,0 +1,59 @@
+use gloo::storage::{LocalStorage, Storage};
+use serde::{Deserialize, Serialize};
+
+const STORAGE_KEY: &str = "yewchat.theme.v1";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// The stored theme, or the OS color scheme if none was chosen yet.
+    pub fn load() -> Self {
+        LocalStorage::get(STORAGE_KEY).unwrap_or_else(|_| Self::system())
+    }
+
+    pub fn save(self) {
+        if let Err(e) = LocalStorage::set(STORAGE_KEY, self) {
+            log::error!("failed to save theme: {:?}", e);
+        }
+    }
+
+    fn system() -> Self {
+        let dark = gloo::utils::window()
+            .match_media("(prefers-color-scheme: dark)")
+            .ok()
+            .flatten()
+            .map(|query| query.matches())
+            .unwrap_or(false);
+        if dark {
+            Theme::Dark
+        } else {
+            Theme::Light
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Theme::Light => Theme::Dark,
+            Theme::Dark => Theme::Light,
+        }
+    }
+
+    /// Sets the `dark` class on the root element, which turns on the
+    /// `dark:` Tailwind variants.
+    pub fn apply_to_document(self) {
+        if let Some(root) = gloo::utils::document().document_element() {
+            let result = match self {
+                Theme::Light => root.class_list().remove_1("dark"),
+                Theme::Dark => root.class_list().add_1("dark"),
+            };
+            if let Err(e) = result {
+                log::error!("failed to apply theme: {:?}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use serde::Serialize;
+
+/// The formats a conversation can be exported in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// One "[date time] name: text" line per message.
+    Text,
+    Json,
+}
+
+impl Format {
+    pub fn label(self) -> &'static str {
+        match self {
+            Format::Text => "Plain text",
+            Format::Json => "JSON",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Text => "txt",
+            Format::Json => "json",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Format::Text => "text/plain",
+            Format::Json => "application/json",
+        }
+    }
+}
+
+/// A message as it appears in an export.
+#[derive(Serialize)]
+pub struct Entry {
+    pub from: String,
+    pub message: String,
+    /// Epoch millis; messages restored from before timestamps have none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Transcript<'a> {
+    conversation: &'a str,
+    exported_at: f64,
+    messages: &'a [Entry],
+}
+
+/// The export of `entries`, the messages of `conversation` (e.g.
+/// "#general"), oldest first. An empty conversation still gives a valid
+/// file.
+pub fn render(conversation: &str, entries: &[Entry], format: Format) -> String {
+    let now = js_sys::Date::now();
+    match format {
+        Format::Text => {
+            let mut text = format!("Yewchat transcript of {}, exported {}\n\n", conversation, format_date_time(now));
+            if entries.is_empty() {
+                text.push_str("(no messages)\n");
+            }
+            for entry in entries {
+                let time = entry.time.map(format_date_time).unwrap_or_else(|| "unknown time".to_string());
+                text.push_str(&format!("[{}] {}: {}\n", time, entry.from, entry.message));
+            }
+            text
+        }
+        Format::Json => {
+            let transcript = Transcript {
+                conversation,
+                exported_at: now,
+                messages: entries,
+            };
+            serde_json::to_string_pretty(&transcript).unwrap_or_default()
+        }
+    }
+}
+
+/// The download name for an export of `conversation` made today, e.g.
+/// `yewchat-general-2026-10-14.txt`.
+pub fn filename(conversation: &str, format: Format) -> String {
+    let name: String = conversation
+        .chars()
+        .filter(|&c| c != '#')
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    format!("yewchat-{}-{}.{}", name, format_date(js_sys::Date::now()), format.extension())
+}
+
+/// Formats epoch millis as the local date, "YYYY-MM-DD".
+fn format_date(millis: f64) -> String {
+    let date = js_sys::Date::new(&millis.into());
+    format!("{:04}-{:02}-{:02}", date.get_full_year(), date.get_month() + 1, date.get_date())
+}
+
+/// Formats epoch millis as the local "YYYY-MM-DD HH:MM".
+fn format_date_time(millis: f64) -> String {
+    let date = js_sys::Date::new(&millis.into());
+    format!("{} {:02}:{:02}", format_date(millis), date.get_hours(), date.get_minutes())
+}
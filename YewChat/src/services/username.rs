@@ -0,0 +1,85 @@
+use std::fmt;
+
+pub const MIN_USERNAME_CHARS: usize = 2;
+pub const MAX_USERNAME_CHARS: usize = 24;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UsernameError {
+    Empty,
+    TooShort,
+    TooLong,
+    /// Usernames may only contain the characters of an `@mention`; see
+    /// `is_name_char`. This is the first other character.
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for UsernameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsernameError::Empty => write!(f, "Please enter a username."),
+            UsernameError::TooShort => write!(f, "Usernames need at least {} characters.", MIN_USERNAME_CHARS),
+            UsernameError::TooLong => write!(f, "Usernames can have at most {} characters.", MAX_USERNAME_CHARS),
+            UsernameError::InvalidCharacter(c) => write!(
+                f,
+                "Usernames can't contain \"{}\". Use letters, digits, _ and - only.",
+                c
+            ),
+        }
+    }
+}
+
+/// Whether `c` can be part of a username, and so of an `@mention` of one.
+pub fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Cleans up a typed username and checks it can be registered: the ends
+/// are trimmed, then the length and characters are checked. Returns the
+/// cleaned name.
+pub fn validate(name: &str) -> Result<String, UsernameError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(UsernameError::Empty);
+    }
+    if let Some(c) = name.chars().find(|&c| !is_name_char(c)) {
+        return Err(UsernameError::InvalidCharacter(c));
+    }
+    match name.chars().count() {
+        len if len < MIN_USERNAME_CHARS => Err(UsernameError::TooShort),
+        len if len > MAX_USERNAME_CHARS => Err(UsernameError::TooLong),
+        _ => Ok(name.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_empty_names() {
+        assert_eq!(validate(""), Err(UsernameError::Empty));
+        assert_eq!(validate("   \t "), Err(UsernameError::Empty));
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        assert_eq!(validate("a"), Err(UsernameError::TooShort));
+        let longest = "é".repeat(MAX_USERNAME_CHARS);
+        assert_eq!(validate(&longest).as_deref(), Ok(longest.as_str()));
+        assert_eq!(validate(&"é".repeat(MAX_USERNAME_CHARS + 1)), Err(UsernameError::TooLong));
+    }
+
+    #[test]
+    fn rejects_invalid_characters() {
+        assert_eq!(validate("ann<script>"), Err(UsernameError::InvalidCharacter('<')));
+        assert_eq!(validate("bob@home"), Err(UsernameError::InvalidCharacter('@')));
+        // Neither would end an `@mention` of the name early.
+        assert_eq!(validate("Ann Lee"), Err(UsernameError::InvalidCharacter(' ')));
+        assert_eq!(validate("ann.lee"), Err(UsernameError::InvalidCharacter('.')));
+    }
+
+    #[test]
+    fn trims_valid_names() {
+        assert_eq!(validate("  Ann_Lee-2 \t").as_deref(), Ok("Ann_Lee-2"));
+    }
+}
@@ -0,0 +1,64 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, IntersectionObserver, IntersectionObserverEntry, IntersectionObserverInit};
+use yew::Callback;
+
+/// Reports which marked elements inside a scrolling container come into
+/// view. Elements are marked with an attribute whose value is passed to
+/// the callback. Stops observing when dropped.
+pub struct VisibilityObserver {
+    observer: IntersectionObserver,
+    attribute: &'static str,
+    _callback: Closure<dyn FnMut(js_sys::Array)>,
+}
+
+impl VisibilityObserver {
+    /// Calls `on_visible` with the `attribute` values of the elements that
+    /// became at least half visible within `root`.
+    pub fn new(root: &Element, attribute: &'static str, on_visible: Callback<Vec<String>>) -> Option<Self> {
+        let callback = Closure::wrap(Box::new(move |entries: js_sys::Array| {
+            let visible: Vec<String> = entries
+                .iter()
+                .filter_map(|entry| entry.dyn_into::<IntersectionObserverEntry>().ok())
+                .filter(|entry| entry.is_intersecting())
+                .filter_map(|entry| entry.target().get_attribute(attribute))
+                .collect();
+            if !visible.is_empty() {
+                on_visible.emit(visible);
+            }
+        }) as Box<dyn FnMut(js_sys::Array)>);
+
+        let mut options = IntersectionObserverInit::new();
+        options.root(Some(root)).threshold(&JsValue::from(0.5));
+        match IntersectionObserver::new_with_options(callback.as_ref().unchecked_ref(), &options) {
+            Ok(observer) => Some(Self {
+                observer,
+                attribute,
+                _callback: callback,
+            }),
+            Err(e) => {
+                log::error!("failed to create intersection observer: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// Starts watching every marked element under `root`. Elements that
+    /// are already watched are unaffected.
+    pub fn observe_marked(&self, root: &Element) {
+        let Ok(elements) = root.query_selector_all(&format!("[{}]", self.attribute)) else {
+            return;
+        };
+        for i in 0..elements.length() {
+            if let Some(element) = elements.get(i).and_then(|node| node.dyn_into::<Element>().ok()) {
+                self.observer.observe(&element);
+            }
+        }
+    }
+}
+
+impl Drop for VisibilityObserver {
+    fn drop(&mut self) {
+        self.observer.disconnect();
+    }
+}
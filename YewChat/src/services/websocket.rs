@@ -1,53 +1,409 @@
-use futures::{channel::mpsc::Sender, SinkExt, StreamExt};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use futures::future::{self, Either, Fuse, Pending};
+use futures::{channel::mpsc::Sender, FutureExt, SinkExt, StreamExt};
+use gloo::timers::future::TimeoutFuture;
 use reqwasm::websocket::{futures::WebSocket, Message};
+use serde::{Deserialize, Serialize};
 
 use wasm_bindgen_futures::spawn_local;
-use yew_agent::Dispatched;
+use yew_agent::{Dispatched, Dispatcher};
 
 use crate::services::event_bus::{EventBus, Request};
 
+/// The chat server, set at build time through `YEWCHAT_SERVER_URL`, e.g.
+/// `wss://chat.example.com` or just `chat.example.com:8080`.
+const CONFIGURED_SERVER_URL: Option<&str> = option_env!("YEWCHAT_SERVER_URL");
+
+/// The port the server listens on by default.
+const DEFAULT_SERVER_PORT: u16 = 8080;
+
+/// The URL of the chat server: the one configured at build time, or port
+/// 8080 on the host that served the page. Pages served over HTTPS get
+/// `wss://`, as browsers block plain `ws://` from them.
+pub fn server_url() -> String {
+    let location = gloo::utils::window().location();
+    let secure = location.protocol().map(|p| p == "https:").unwrap_or(false);
+    let scheme = if secure { "wss" } else { "ws" };
+
+    match CONFIGURED_SERVER_URL.map(str::trim).filter(|url| !url.is_empty()) {
+        Some(url) => match url.split_once("://") {
+            Some(("ws", rest)) if secure => format!("wss://{}", rest),
+            Some(_) => url.to_string(),
+            None => format!("{}://{}", scheme, url),
+        },
+        None => {
+            let host = location
+                .hostname()
+                .ok()
+                .filter(|host| !host.is_empty())
+                .unwrap_or_else(|| "127.0.0.1".to_string());
+            format!("{}://{}:{}", scheme, host, DEFAULT_SERVER_PORT)
+        }
+    }
+}
+
+/// Reconnecting stops after this many failed attempts in a row.
+pub const MAX_RECONNECT_ATTEMPTS: u32 = 10;
+
+const INITIAL_BACKOFF_MS: u32 = 1000;
+const MAX_BACKOFF_MS: u32 = 30_000;
+
+const PING_FRAME: &str = r#"{"messageType":"ping"}"#;
+
+/// Keeps idle connections from being dropped by proxies, and notices
+/// connections that died without a close event.
+#[derive(Clone, Copy, Debug)]
+pub struct Heartbeat {
+    /// A ping is sent after this long without hearing from the server.
+    pub interval_ms: u32,
+    /// The connection counts as dead if nothing arrives this long after
+    /// a ping.
+    pub timeout_ms: u32,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            interval_ms: 25_000,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ConnectionState {
+    /// Opening the first connection.
+    Connecting,
+    Connected,
+    /// The connection dropped; `attempt` counts retries since then,
+    /// starting at 1.
+    Reconnecting { attempt: u32 },
+    /// Gave up after `MAX_RECONNECT_ATTEMPTS` failed retries.
+    Offline,
+}
+
+/// How long to wait before retry number `attempt` (counting from 0):
+/// 1s, 2s, 4s and so on, capped at 30s.
+pub fn backoff_delay(attempt: u32) -> u32 {
+    INITIAL_BACKOFF_MS
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF_MS)
+}
+
+/// What to do after a connection failed or closed.
+#[derive(Debug, PartialEq)]
+enum AfterClose {
+    /// Wait this many millis, then connect again.
+    Retry(u32),
+    /// `MAX_RECONNECT_ATTEMPTS` retries failed.
+    GiveUp,
+    /// The service was dropped.
+    Stop,
+}
+
+/// Counts the connection attempts since the last good connection, and
+/// decides when to stop.
+#[derive(Debug, Default)]
+struct Retries {
+    attempt: u32,
+    /// Set by the service's `StopOnDrop`.
+    stopped: Rc<Cell<bool>>,
+}
+
+impl Retries {
+    /// Whether the service was dropped, so nothing should reconnect.
+    fn stopped(&self) -> bool {
+        self.stopped.get()
+    }
+
+    /// A handle that stops the retrying once it is dropped.
+    fn stop_on_drop(&self) -> StopOnDrop {
+        StopOnDrop(self.stopped.clone())
+    }
+
+    /// What to announce while opening the next connection.
+    fn state(&self) -> ConnectionState {
+        if self.attempt == 0 {
+            ConnectionState::Connecting
+        } else {
+            ConnectionState::Reconnecting { attempt: self.attempt }
+        }
+    }
+
+    fn connected(&mut self) {
+        self.attempt = 0;
+    }
+
+    fn after_close(&mut self) -> AfterClose {
+        if self.stopped() {
+            return AfterClose::Stop;
+        }
+        match self.next_delay() {
+            Some(delay) => AfterClose::Retry(delay),
+            None => AfterClose::GiveUp,
+        }
+    }
+
+    /// After a connection failed or dropped: how long to wait before the
+    /// next attempt, or `None` once `MAX_RECONNECT_ATTEMPTS` retries
+    /// failed.
+    fn next_delay(&mut self) -> Option<u32> {
+        if self.attempt >= MAX_RECONNECT_ATTEMPTS {
+            return None;
+        }
+        let delay = backoff_delay(self.attempt);
+        self.attempt += 1;
+        Some(delay)
+    }
+}
+
+/// Stops the reconnecting of its `Retries` when dropped.
+#[derive(Debug)]
+struct StopOnDrop(Rc<Cell<bool>>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
 pub struct WebsocketService {
     pub tx: Sender<String>,
+    handshake: Rc<RefCell<String>>,
+    /// Dropped with the service, so it stops reconnecting.
+    _stop: StopOnDrop,
 }
 
 impl WebsocketService {
-    pub fn new() -> Self {
-        let ws = WebSocket::open("ws://127.0.0.1:8080").unwrap();
-
-        let (mut write, mut read) = ws.split();
-
+    /// Connects to the chat server at `url` and keeps reconnecting with
+    /// exponential backoff whenever the socket closes or fails. `handshake` is sent
+    /// first on every connection, so the server learns who we are again
+    /// after a reconnect.
+    ///
+    /// Messages sent on `tx` while disconnected wait in the channel and go
+    /// out once the connection is back. Connection changes are published
+    /// on the `EventBus` as `Request::ConnectionChanged`. Passing `None` for
+    /// `heartbeat` turns pings off, so no timers run.
+    ///
+    /// Dropping the service closes the connection once the messages already
+    /// sent on `tx` are out, and stops any reconnecting.
+    pub fn connect_with_retry(url: String, handshake: String, heartbeat: Option<Heartbeat>) -> Self {
         let (in_tx, mut in_rx) = futures::channel::mpsc::channel::<String>(1000);
         let mut event_bus = EventBus::dispatcher();
+        let handshake = Rc::new(RefCell::new(handshake));
+        let current_handshake = handshake.clone();
+        let mut retries = Retries::default();
+        let stop = retries.stop_on_drop();
 
         spawn_local(async move {
-            while let Some(s) = in_rx.next().await {
-                log::debug!("got event from channel! {}", s);
-                write.send(Message::Text(s)).await.unwrap();
-            }
-        });
+            // A message whose send failed when the connection dropped.
+            let mut unsent: Option<String> = None;
 
-        spawn_local(async move {
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(data)) => {
-                        log::debug!("from websocket: {}", data);
-                        event_bus.send(Request::EventBusMsg(data));
-                    }
-                    Ok(Message::Bytes(b)) => {
-                        let decoded = std::str::from_utf8(&b);
-                        if let Ok(val) = decoded {
-                            log::debug!("from websocket: {}", val);
-                            event_bus.send(Request::EventBusMsg(val.into()));
+            loop {
+                if retries.stopped() {
+                    return;
+                }
+                event_bus.send(Request::ConnectionChanged(retries.state()));
+
+                match WebSocket::open(&url) {
+                    Ok(ws) => {
+                        let (mut write, read) = ws.split();
+                        let mut read = read.fuse();
+
+                        // The handshake goes out as soon as the socket opens;
+                        // a failed connection shows up on the read side first.
+                        let handshake = current_handshake.borrow().clone();
+                        let opened = futures::select! {
+                            sent = write.send(Message::Text(handshake)).fuse() => sent.is_ok(),
+                            _ = read.next() => false,
+                        };
+
+                        if opened {
+                            retries.connected();
+                            event_bus.send(Request::ConnectionChanged(ConnectionState::Connected));
+
+                            let mut awaiting_pong = false;
+                            let mut heartbeat_timer = timer(heartbeat.map(|h| h.interval_ms));
+
+                            loop {
+                                let outgoing = match unsent.take() {
+                                    Some(s) => s,
+                                    None => futures::select! {
+                                        outgoing = in_rx.next() => match outgoing {
+                                            Some(s) => s,
+                                            // The service was dropped.
+                                            None => return,
+                                        },
+                                        incoming = read.next() => {
+                                            match incoming {
+                                                Some(Ok(msg)) => {
+                                                    awaiting_pong = false;
+                                                    heartbeat_timer = timer(heartbeat.map(|h| h.interval_ms));
+                                                    forward(&mut event_bus, msg);
+                                                }
+                                                Some(Err(e)) => log::error!("ws: {:?}", e),
+                                                None => break,
+                                            }
+                                            continue;
+                                        }
+                                        _ = heartbeat_timer => {
+                                            if awaiting_pong {
+                                                log::error!("ws: no reply to ping, reconnecting");
+                                                break;
+                                            }
+                                            awaiting_pong = true;
+                                            heartbeat_timer = timer(heartbeat.map(|h| h.timeout_ms));
+                                            if let Err(e) = write.send(Message::Text(PING_FRAME.into())).await {
+                                                log::error!("ws: {:?}", e);
+                                                break;
+                                            }
+                                            continue;
+                                        }
+                                    },
+                                };
+                                log::debug!("got event from channel! {}", outgoing);
+                                if let Err(e) = write.send(Message::Text(outgoing.clone())).await {
+                                    log::error!("ws: {:?}", e);
+                                    unsent = Some(outgoing);
+                                    break;
+                                }
+                            }
+                            log::debug!("WebSocket Closed");
                         }
                     }
-                    Err(e) => {
-                        log::error!("ws: {:?}", e)
+                    Err(e) => log::error!("ws: {:?}", e),
+                }
+
+                match retries.after_close() {
+                    AfterClose::Retry(delay) => TimeoutFuture::new(delay).await,
+                    AfterClose::GiveUp => {
+                        event_bus.send(Request::ConnectionChanged(ConnectionState::Offline));
+                        return;
                     }
+                    AfterClose::Stop => return,
                 }
             }
-            log::debug!("WebSocket Closed");
         });
 
-        Self { tx: in_tx }
+        Self {
+            tx: in_tx,
+            handshake,
+            _stop: stop,
+        }
+    }
+
+    /// Replaces the frame sent on the next reconnect, e.g. after the user
+    /// changed what it announces.
+    pub fn set_handshake(&self, handshake: String) {
+        *self.handshake.borrow_mut() = handshake;
+    }
+}
+
+type Timer = Fuse<Either<TimeoutFuture, Pending<()>>>;
+
+/// A timer firing after `ms`, or never for `None`.
+fn timer(ms: Option<u32>) -> Timer {
+    match ms {
+        Some(ms) => Either::Left(TimeoutFuture::new(ms)),
+        None => Either::Right(future::pending()),
+    }
+    .fuse()
+}
+
+/// Whether `data` is the server's answer to a heartbeat ping. Pongs are
+/// handled here and not passed on.
+fn is_pong(data: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(data)
+        .ok()
+        .and_then(|frame| frame.get("messageType")?.as_str().map(|t| t == "pong"))
+        .unwrap_or(false)
+}
+
+fn forward(event_bus: &mut Dispatcher<EventBus>, msg: Message) {
+    match msg {
+        Message::Text(data) if is_pong(&data) => {}
+        Message::Text(data) => {
+            log::debug!("from websocket: {}", data);
+            event_bus.send(Request::EventBusMsg(data));
+        }
+        Message::Bytes(b) => {
+            let decoded = std::str::from_utf8(&b);
+            if let Ok(val) = decoded {
+                log::debug!("from websocket: {}", val);
+                event_bus.send(Request::EventBusMsg(val.into()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff_delay(0), 1000);
+        assert_eq!(backoff_delay(1), 2000);
+        assert_eq!(backoff_delay(2), 4000);
+        assert_eq!(backoff_delay(3), 8000);
+        assert_eq!(backoff_delay(4), 16_000);
+        assert_eq!(backoff_delay(5), MAX_BACKOFF_MS);
+        assert_eq!(backoff_delay(31), MAX_BACKOFF_MS);
+        assert_eq!(backoff_delay(32), MAX_BACKOFF_MS);
+        assert_eq!(backoff_delay(u32::MAX), MAX_BACKOFF_MS);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mut retries = Retries::default();
+        assert_eq!(retries.state(), ConnectionState::Connecting);
+        for attempt in 1..=MAX_RECONNECT_ATTEMPTS {
+            assert_eq!(retries.next_delay(), Some(backoff_delay(attempt - 1)));
+            assert_eq!(retries.state(), ConnectionState::Reconnecting { attempt });
+        }
+        assert_eq!(retries.next_delay(), None);
+    }
+
+    #[test]
+    fn connecting_starts_over() {
+        let mut retries = Retries::default();
+        retries.next_delay();
+        retries.next_delay();
+        retries.connected();
+        assert_eq!(retries.state(), ConnectionState::Connecting);
+        assert_eq!(retries.next_delay(), Some(INITIAL_BACKOFF_MS));
+    }
+
+    #[test]
+    fn reconnects_after_a_close_until_connected() {
+        let mut retries = Retries::default();
+        retries.connected();
+        // The connection drops: wait, then reconnect.
+        assert_eq!(retries.after_close(), AfterClose::Retry(INITIAL_BACKOFF_MS));
+        assert_eq!(retries.state(), ConnectionState::Reconnecting { attempt: 1 });
+        // That attempt fails too.
+        assert_eq!(retries.after_close(), AfterClose::Retry(backoff_delay(1)));
+        assert_eq!(retries.state(), ConnectionState::Reconnecting { attempt: 2 });
+        // The next one gets through, so the count starts over.
+        retries.connected();
+        assert_eq!(retries.after_close(), AfterClose::Retry(INITIAL_BACKOFF_MS));
+        assert_eq!(retries.state(), ConnectionState::Reconnecting { attempt: 1 });
+    }
+
+    #[test]
+    fn dropping_the_service_stops_retrying() {
+        let mut retries = Retries::default();
+        let stop = retries.stop_on_drop();
+        assert!(!retries.stopped());
+        assert_eq!(retries.after_close(), AfterClose::Retry(INITIAL_BACKOFF_MS));
+
+        drop(stop);
+        assert!(retries.stopped());
+        assert_eq!(retries.after_close(), AfterClose::Stop);
+        // Not even a success starts it again.
+        retries.connected();
+        assert_eq!(retries.after_close(), AfterClose::Stop);
     }
 }